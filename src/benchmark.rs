@@ -0,0 +1,278 @@
+//! Startup Benchmark
+//!
+//! Launches an agent several times and measures how long it takes to spawn,
+//! print its first output, and start the injected lazarus-mcp MCP server.
+//! Useful for telling slow-starting agents apart from hung ones.
+
+use anyhow::{Context, Result};
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::privileges;
+use crate::wrapper;
+
+/// Give up on a run after this long
+const RUN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often the process tree is checked for the MCP server
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Timings of one benchmark run, measured from just before spawning
+#[derive(Debug, Clone, Default)]
+pub struct RunTimings {
+    /// Time until the agent process was spawned
+    pub spawn: Duration,
+    /// Time until the agent wrote its first byte of output
+    pub first_output: Option<Duration>,
+    /// Time until the agent started the lazarus-mcp MCP server
+    pub mcp_server: Option<Duration>,
+}
+
+/// PIDs of all descendants of a process
+fn descendants(root: u32) -> Vec<u32> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    if let Ok(entries) = fs::read_dir("/proc") {
+        for entry in entries.flatten() {
+            let Ok(pid) = entry.file_name().to_string_lossy().parse::<u32>() else {
+                continue;
+            };
+            let Ok(stat) = fs::read_to_string(format!("/proc/{}/stat", pid)) else {
+                continue;
+            };
+            // The parent PID is the second field after the parenthesized command name
+            let ppid = stat
+                .rsplit_once(')')
+                .and_then(|(_, rest)| rest.split_whitespace().nth(1))
+                .and_then(|p| p.parse::<u32>().ok());
+            if let Some(ppid) = ppid {
+                children.entry(ppid).or_default().push(pid);
+            }
+        }
+    }
+
+    let mut found = Vec::new();
+    let mut stack = vec![root];
+    while let Some(pid) = stack.pop() {
+        for &child in children.get(&pid).into_iter().flatten() {
+            found.push(child);
+            stack.push(child);
+        }
+    }
+    found
+}
+
+/// Whether any descendant of `root` runs with `--mcp-server`
+fn has_mcp_server_descendant(root: u32) -> bool {
+    descendants(root).into_iter().any(|pid| {
+        fs::read(format!("/proc/{}/cmdline", pid))
+            .map(|cmdline| cmdline.split(|&b| b == 0).any(|arg| arg == b"--mcp-server"))
+            .unwrap_or(false)
+    })
+}
+
+/// Launch the agent once and time its startup
+///
+/// The run ends when every awaited milestone was reached, the agent exits,
+/// or `RUN_TIMEOUT` elapses; the agent's process group is then terminated.
+fn measure_run(command: &Path, args: &[String], wait_for_mcp: bool) -> Result<RunTimings> {
+    let start = Instant::now();
+    let mut child = Command::new(command)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .with_context(|| format!("Failed to spawn {}", command.display()))?;
+    let mut timings = RunTimings {
+        spawn: start.elapsed(),
+        ..Default::default()
+    };
+
+    // Report the first byte of output, then keep draining so the agent never blocks
+    let mut stdout = child.stdout.take().context("Agent has no stdout")?;
+    let (output_tx, output_rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        let mut reported = false;
+        while let Ok(n) = stdout.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            if !reported {
+                let _ = output_tx.send(start.elapsed());
+                reported = true;
+            }
+        }
+    });
+
+    let pid = child.id();
+    loop {
+        if timings.first_output.is_none() {
+            timings.first_output = output_rx.try_recv().ok();
+        }
+        if wait_for_mcp && timings.mcp_server.is_none() && has_mcp_server_descendant(pid) {
+            timings.mcp_server = Some(start.elapsed());
+        }
+
+        let done = timings.first_output.is_some() && (!wait_for_mcp || timings.mcp_server.is_some());
+        if done || start.elapsed() >= RUN_TIMEOUT || child.try_wait()?.is_some() {
+            break;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+
+    // Stop the agent and anything it started
+    let group = Pid::from_raw(-(pid as i32));
+    let _ = signal::kill(group, Signal::SIGTERM);
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while child.try_wait()?.is_none() && Instant::now() < deadline {
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    let _ = signal::kill(group, Signal::SIGKILL);
+    let _ = child.wait();
+
+    Ok(timings)
+}
+
+fn format_duration(duration: Option<Duration>) -> String {
+    match duration {
+        Some(d) => format!("{:.0}ms", d.as_secs_f64() * 1000.0),
+        None => "n/a".to_string(),
+    }
+}
+
+/// Mean of the measured durations, if any were measured
+fn average(durations: impl Iterator<Item = Option<Duration>>) -> Option<Duration> {
+    let measured: Vec<Duration> = durations.flatten().collect();
+    let count = u32::try_from(measured.len()).ok().filter(|&n| n > 0)?;
+    Some(measured.iter().sum::<Duration>() / count)
+}
+
+/// Render the per-run timings and their averages
+pub fn render_report(command_name: &str, runs: &[RunTimings]) -> String {
+    let mut lines = vec![format!("Startup benchmark: {} ({} runs)", command_name, runs.len())];
+    lines.push(format!("{:<8}{:>12}{:>16}{:>14}", "Run", "Spawn", "First output", "MCP server"));
+    for (i, run) in runs.iter().enumerate() {
+        lines.push(format!(
+            "{:<8}{:>12}{:>16}{:>14}",
+            i + 1,
+            format_duration(Some(run.spawn)),
+            format_duration(run.first_output),
+            format_duration(run.mcp_server)
+        ));
+    }
+    lines.push(format!(
+        "{:<8}{:>12}{:>16}{:>14}",
+        "Average",
+        format_duration(average(runs.iter().map(|r| Some(r.spawn)))),
+        format_duration(average(runs.iter().map(|r| r.first_output))),
+        format_duration(average(runs.iter().map(|r| r.mcp_server)))
+    ));
+    lines.join("\n")
+}
+
+/// Benchmark an agent's startup over several runs and print a report
+///
+/// With `inject_mcp`, lazarus-mcp is injected into .mcp.json for the duration
+/// of the benchmark so the time until the agent starts it can be measured.
+pub fn run(command: &Path, args: &[String], runs: usize, inject_mcp: bool, run_as: Option<&str>) -> Result<()> {
+    privileges::drop_privileges_for(run_as)?;
+
+    let mcp_paths = if inject_mcp {
+        match wrapper::acquire_mcp_injection(None) {
+            Ok(paths) => paths,
+            Err(e) => {
+                warn!("Failed to inject MCP server: {}. MCP startup will not be measured.", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let wait_for_mcp = mcp_paths.is_some();
+
+    let command_name = command
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let mut timings = Vec::with_capacity(runs);
+    let mut result = Ok(());
+    for i in 0..runs {
+        eprintln!("Run {}/{}...", i + 1, runs);
+        match measure_run(command, args, wait_for_mcp) {
+            Ok(run) => timings.push(run),
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+    }
+
+    if let Some((backup_path, target_path)) = &mcp_paths {
+        wrapper::release_mcp_injection(backup_path, target_path);
+    }
+    result?;
+
+    println!("{}", render_report(&command_name, &timings));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Vec<String> {
+        vec!["-c".to_string(), script.to_string()]
+    }
+
+    #[test]
+    fn test_measures_first_output() {
+        let run = measure_run(Path::new("/bin/sh"), &sh("sleep 0.1; echo ready; exec sleep 30"), false).unwrap();
+        let first_output = run.first_output.unwrap();
+        assert!(first_output >= Duration::from_millis(100));
+        assert!(first_output < Duration::from_secs(10));
+        assert!(run.mcp_server.is_none());
+    }
+
+    #[test]
+    fn test_detects_mcp_server_child() {
+        let script = "sh -c 'sleep 30; :' lazarus-mcp --mcp-server & echo ready; wait";
+        let run = measure_run(Path::new("/bin/sh"), &sh(script), true).unwrap();
+        assert!(run.first_output.is_some());
+        assert!(run.mcp_server.is_some());
+    }
+
+    #[test]
+    fn test_render_report_averages_measured_runs() {
+        let runs = [
+            RunTimings {
+                spawn: Duration::from_millis(2),
+                first_output: Some(Duration::from_millis(100)),
+                mcp_server: None,
+            },
+            RunTimings {
+                spawn: Duration::from_millis(4),
+                first_output: Some(Duration::from_millis(300)),
+                mcp_server: None,
+            },
+        ];
+        let report = render_report("claude", &runs);
+        let average = report.lines().last().unwrap();
+        assert!(report.starts_with("Startup benchmark: claude (2 runs)"));
+        assert!(average.starts_with("Average"));
+        assert!(average.contains("3ms"));
+        assert!(average.contains("200ms"));
+        assert!(average.ends_with("n/a"));
+    }
+}
@@ -0,0 +1,685 @@
+//! Tool Handlers
+//!
+//! Front-end independent implementations of lazarus-mcp's tools. Each handler
+//! returns plain text on success or a [`ToolError`] on failure; the MCP server
+//! (or any other front-end) is responsible for wrapping the result in its own
+//! response format.
+
+use serde_json::{json, Value};
+use std::fmt;
+use std::path::Path;
+use tracing::info;
+
+use crate::health;
+use crate::notify;
+use crate::pool::{AgentPool, AgentStatus, LockType, ResourceLimits, SpawnOutcome, Task, TaskPriority};
+use crate::restart;
+use crate::wrapper;
+
+/// Error returned by a tool handler, carrying the user-facing message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolError(pub String);
+
+impl ToolError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
+    }
+}
+
+impl fmt::Display for ToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ToolError {}
+
+/// Result of a tool handler: the text to show the caller
+pub type ToolResult = std::result::Result<String, ToolError>;
+
+/// Get a required string argument
+fn required_str<'a>(arguments: Option<&'a Value>, name: &str) -> Result<&'a str, ToolError> {
+    arguments
+        .and_then(|a| a.get(name))
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| ToolError(format!("Missing required parameter: {}", name)))
+}
+
+/// Restart the agent running under the wrapper (also exposed as `restart_claude`)
+pub fn restart_agent(arguments: Option<&Value>) -> ToolResult {
+    let reason = arguments
+        .and_then(|a| a.get("reason"))
+        .and_then(|r| r.as_str())
+        .unwrap_or("MCP server restart requested")
+        .to_string();
+
+    let prompt = arguments
+        .and_then(|a| a.get("prompt"))
+        .and_then(|p| p.as_str());
+
+    // The agent calls this tool after changing an MCP server; other restarts
+    // (the dashboard, crashes) are recorded by the wrapper itself
+    let trigger = restart::RestartTrigger::McpCodeChange;
+
+    info!(reason = %reason, prompt = ?prompt, trigger = %trigger, "Triggering agent restart via signal file");
+
+    match restart::send_restart_signal(&reason, prompt, trigger) {
+        Ok(info) => {
+            let prompt_msg = if prompt.is_some() {
+                "\nA prompt will be auto-sent after restart."
+            } else {
+                ""
+            };
+            let details = serde_json::to_string_pretty(&info.explanation)
+                .unwrap_or_else(|_| format!("{:?}", info.explanation));
+            let agent = wrapper::SharedState::load(info.wrapper_pid)
+                .map(|state| state.agent_name)
+                .unwrap_or_else(|_| "The agent".to_string());
+            let resume = match crate::pool::continue_flag_for(&agent) {
+                Some(flag) => format!("resume with {}", flag),
+                None => "be relaunched".to_string(),
+            };
+            Ok(format!(
+                "Restart signal sent!\n\nWrapper PID: {}\nReason: {}\nTrigger: {}{}\n\n{} will restart momentarily and {}.\n\nRestart details:\n{}",
+                info.wrapper_pid,
+                reason,
+                trigger,
+                prompt_msg,
+                agent,
+                resume,
+                details
+            ))
+        }
+        Err(e) => Err(ToolError(format!(
+            "Failed to trigger restart: {}\n\nMake sure you started your agent via the lazarus-mcp wrapper:\n  lazarus-mcp <agent> [args...]\n\nExample: lazarus-mcp claude --continue",
+            e
+        ))),
+    }
+}
+
+pub fn server_status() -> ToolResult {
+    let status = restart::get_status();
+    Ok(serde_json::to_string_pretty(&status).unwrap_or_else(|_| format!("{:?}", status)))
+}
+
+/// Which optional features are active in this session
+///
+/// Everything is read from the environment the wrapper exported and the
+/// files it leaves behind, so subsystems that are off simply show as such.
+pub fn server_capabilities() -> ToolResult {
+    let status = restart::get_status();
+    let mcp_injection = status
+        .working_directory
+        .as_ref()
+        .map(|dir| Path::new(dir).join(wrapper::MCP_BACKUP_FILE).exists())
+        .unwrap_or(false);
+    let health_history = std::env::var(health::HEALTH_HISTORY_ENV).ok();
+    let notify_settings = status.wrapper_pid.and_then(notify::Settings::load);
+    // Only the backend kind: webhook URLs may carry credentials
+    let notify_backend = notify_settings
+        .as_ref()
+        .map(|settings| settings.backend.split(':').next().unwrap_or_default().to_string());
+    let notify_events = notify_settings.and_then(|settings| settings.events);
+
+    let capabilities = json!({
+        "wrapper_pid": status.wrapper_pid,
+        "wrapper_running": status.wrapper_running,
+        "restart": status.wrapper_running,
+        "mcp_injection": mcp_injection,
+        "health_history": {
+            "enabled": health_history.is_some(),
+            "file": health_history,
+        },
+        "notify": {
+            "enabled": notify_backend.as_deref().is_some_and(|b| b != "none"),
+            "backend": notify_backend,
+            "events": notify_events,
+        },
+        "log_format": std::env::var(crate::LOG_FORMAT_ENV).unwrap_or_else(|_| "text".to_string()),
+        "version": env!("CARGO_PKG_VERSION"),
+    });
+    Ok(serde_json::to_string_pretty(&capabilities).unwrap_or_else(|_| capabilities.to_string()))
+}
+
+pub fn health_history(arguments: Option<&Value>) -> ToolResult {
+    let limit = arguments
+        .and_then(|a| a.get("limit"))
+        .and_then(|l| l.as_u64())
+        .unwrap_or(60) as usize;
+
+    let path = std::env::var_os(health::HEALTH_HISTORY_ENV).ok_or_else(|| {
+        ToolError::new("Health history is not enabled. Start the wrapper with --health-history-file <path>.")
+    })?;
+    let samples = health::read_recent(Path::new(&path), limit)
+        .map_err(|e| ToolError(format!("Failed to read health history: {}", e)))?;
+
+    Ok(serde_json::to_string_pretty(&samples).unwrap_or_else(|_| format!("{:?}", samples)))
+}
+
+pub async fn agent_spawn(pool: &AgentPool, arguments: Option<&Value>) -> ToolResult {
+    let description = required_str(arguments, "description")?.to_string();
+
+    let agent_type = arguments
+        .and_then(|a| a.get("agent_type"))
+        .and_then(|t| t.as_str())
+        .unwrap_or("claude")
+        .to_string();
+
+    let working_directory = arguments
+        .and_then(|a| a.get("working_directory"))
+        .and_then(|d| d.as_str())
+        .map(std::path::PathBuf::from);
+
+    let max_iterations = arguments
+        .and_then(|a| a.get("max_iterations"))
+        .and_then(|m| m.as_u64())
+        .unwrap_or(50) as u32;
+
+    let priority = match arguments
+        .and_then(|a| a.get("priority"))
+        .and_then(|p| p.as_str())
+        .unwrap_or("normal")
+    {
+        "low" => TaskPriority::Low,
+        "high" => TaskPriority::High,
+        "urgent" => TaskPriority::Urgent,
+        _ => TaskPriority::Normal,
+    };
+
+    let mut task = Task::new(&description)
+        .with_agent_type(&agent_type)
+        .with_max_iterations(max_iterations)
+        .with_priority(priority);
+
+    if let Some(dir) = working_directory {
+        task = task.with_working_directory(dir);
+    }
+
+    let depends_on: Vec<&str> = arguments
+        .and_then(|a| a.get("depends_on"))
+        .and_then(|d| d.as_array())
+        .map(|ids| ids.iter().filter_map(|id| id.as_str()).collect())
+        .unwrap_or_default();
+    let has_dependencies = !depends_on.is_empty();
+    task = task.with_dependencies(depends_on);
+
+    let limit = |name: &str| arguments.and_then(|a| a.get(name)).and_then(|v| v.as_u64());
+    task = task.with_limits(ResourceLimits {
+        memory_limit_mb: limit("memory_limit_mb"),
+        cpu_time_limit_secs: limit("cpu_time_limit_secs"),
+    });
+
+    match pool.spawn(task).await {
+        Ok(SpawnOutcome::Started(agent_id)) => Ok(format!(
+            "Spawned background agent: {}\n\nTask: {}\nAgent type: {}\nMax iterations: {}",
+            agent_id, description, agent_type, max_iterations
+        )),
+        Ok(SpawnOutcome::Queued { agent_id, position }) if has_dependencies => Ok(format!(
+            "Queued background agent: {} (position {})\n\nTask: {}\nAgent type: {}\nMax iterations: {}\n\nIt will start once its dependencies have completed successfully, or fail if one of them fails.",
+            agent_id, position, description, agent_type, max_iterations
+        )),
+        Ok(SpawnOutcome::Queued { agent_id, position }) => Ok(format!(
+            "Agent pool is full; queued background agent: {} (position {})\n\nTask: {}\nAgent type: {}\nMax iterations: {}\n\nIt will start automatically when a slot frees up.",
+            agent_id, position, description, agent_type, max_iterations
+        )),
+        Err(e) => Err(ToolError(format!("Failed to spawn agent: {}", e))),
+    }
+}
+
+pub async fn agent_list(pool: &AgentPool) -> ToolResult {
+    let agents = pool.list().await;
+
+    if agents.is_empty() {
+        return Ok("No active background agents".to_string());
+    }
+
+    let mut output = format!("{} active agent(s):\n\n", agents.len());
+    for (id, status) in agents {
+        let icon = match &status {
+            AgentStatus::Starting => "🔄",
+            AgentStatus::Running { .. } => "▶️",
+            AgentStatus::Completed { .. } => "✅",
+            AgentStatus::Failed { .. } => "❌",
+            AgentStatus::Stopped => "⏹️",
+            AgentStatus::Paused => "⏸️",
+            AgentStatus::Queued { .. } => "⏳",
+        };
+        output.push_str(&format!("{} {} - {}\n", icon, id, status));
+    }
+
+    Ok(output)
+}
+
+pub async fn agent_status(pool: &AgentPool, arguments: Option<&Value>) -> ToolResult {
+    let agent_id = required_str(arguments, "agent_id")?;
+
+    match pool.status(agent_id).await {
+        Some(status) => Ok(format!("Agent {}: {}", agent_id, status)),
+        None => Err(ToolError(format!("Agent {} not found", agent_id))),
+    }
+}
+
+pub async fn agent_await(pool: &AgentPool, arguments: Option<&Value>) -> ToolResult {
+    let agent_id = required_str(arguments, "agent_id")?;
+
+    let timeout_secs = arguments
+        .and_then(|a| a.get("timeout_secs"))
+        .and_then(|t| t.as_u64())
+        .map(std::time::Duration::from_secs);
+
+    let result = if let Some(timeout) = timeout_secs {
+        pool.await_completion_timeout(agent_id, timeout).await
+    } else {
+        pool.await_completion(agent_id).await
+    };
+
+    match result {
+        Ok(task_result) => {
+            let status = if task_result.success { "succeeded" } else { "failed" };
+            let error_msg = task_result.error.map(|e| format!("\nError: {}", e)).unwrap_or_default();
+            let text = format!(
+                "Agent {} {} after {} iterations.\n\nSummary: {}{}",
+                agent_id, status, task_result.iterations, task_result.summary, error_msg
+            );
+            if task_result.success {
+                Ok(text)
+            } else {
+                Err(ToolError(text))
+            }
+        }
+        Err(e) => Err(ToolError(format!("Error waiting for agent: {}", e))),
+    }
+}
+
+pub async fn agent_stop(pool: &AgentPool, arguments: Option<&Value>) -> ToolResult {
+    let agent_id = required_str(arguments, "agent_id")?;
+
+    match pool.stop(agent_id).await {
+        Ok(()) => Ok(format!("Stopped agent {}", agent_id)),
+        Err(e) => Err(ToolError(format!("Failed to stop agent: {}", e))),
+    }
+}
+
+pub async fn agent_retry(pool: &AgentPool, arguments: Option<&Value>) -> ToolResult {
+    let agent_id = required_str(arguments, "agent_id")?;
+    let include_error = arguments
+        .and_then(|a| a.get("include_error"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+
+    let (outcome, task) = pool.retry(agent_id, include_error).await.map_err(|e| {
+        ToolError(format!(
+            "Failed to retry agent: {}\n\nOnly the tasks of the most recently finished agents are kept. Use agent_spawn with the task's description, agent_type, working_directory and other settings to run it again.",
+            e
+        ))
+    })?;
+    let started = match outcome {
+        SpawnOutcome::Started(new_id) => format!("Spawned background agent: {}", new_id),
+        SpawnOutcome::Queued { agent_id: new_id, position } => {
+            format!("Queued background agent: {} (position {})", new_id, position)
+        }
+    };
+    Ok(format!(
+        "{}\n\nRetrying the task of {} (attempt {})\nTask: {}\nAgent type: {}",
+        started,
+        agent_id,
+        task.attempt + 1,
+        task.description,
+        task.agent_type
+    ))
+}
+
+pub async fn agent_pause(pool: &AgentPool, arguments: Option<&Value>) -> ToolResult {
+    let agent_id = required_str(arguments, "agent_id")?;
+
+    match pool.pause(agent_id).await {
+        Ok(()) => Ok(format!(
+            "Paused agent {}. It keeps its slot in the pool until resumed (agent_resume) or stopped.",
+            agent_id
+        )),
+        Err(e) => Err(ToolError(format!("Failed to pause agent: {}", e))),
+    }
+}
+
+pub async fn agent_resume(pool: &AgentPool, arguments: Option<&Value>) -> ToolResult {
+    let agent_id = required_str(arguments, "agent_id")?;
+
+    match pool.resume(agent_id).await {
+        Ok(()) => Ok(format!("Resumed agent {}", agent_id)),
+        Err(e) => Err(ToolError(format!("Failed to resume agent: {}", e))),
+    }
+}
+
+pub async fn agent_cancel(pool: &AgentPool, arguments: Option<&Value>) -> ToolResult {
+    let agent_id = required_str(arguments, "agent_id")?;
+
+    if pool.cancel_queued(agent_id).await {
+        return Ok(format!("Cancelled queued agent {} (it never started)", agent_id));
+    }
+    match pool.stop(agent_id).await {
+        Ok(()) => Ok(format!("Stopped running agent {}", agent_id)),
+        Err(e) => Err(ToolError(format!("Failed to cancel agent: {}", e))),
+    }
+}
+
+/// Status of an agent whose output may be read
+///
+/// The agent id becomes part of a file path, so only ids the pool handed out
+/// (and still knows about) are accepted.
+async fn output_status(pool: &AgentPool, agent_id: &str) -> Result<String, ToolError> {
+    let well_formed = agent_id
+        .strip_prefix("agent-")
+        .is_some_and(|uuid| uuid::Uuid::parse_str(uuid).is_ok());
+    let status = if well_formed { pool.known_status(agent_id).await } else { None };
+    status.ok_or_else(|| ToolError(format!("Unknown agent {}", agent_id)))
+}
+
+pub async fn agent_tail(pool: &AgentPool, arguments: Option<&Value>) -> ToolResult {
+    let agent_id = required_str(arguments, "agent_id")?;
+    let status = output_status(pool, agent_id).await?;
+
+    let from_offset = arguments
+        .and_then(|a| a.get("from_offset"))
+        .and_then(|o| o.as_u64())
+        .unwrap_or(0);
+
+    let max_bytes = arguments
+        .and_then(|a| a.get("max_bytes"))
+        .and_then(|m| m.as_u64())
+        .unwrap_or(65536) as usize;
+
+    let chunk = crate::pool::read_output(&crate::pool::output_path(agent_id), from_offset, max_bytes)
+        .map_err(|e| ToolError(format!("No output available for agent {}: {}", agent_id, e)))?;
+
+    let result = json!({
+        "agent_id": agent_id,
+        "status": status,
+        "from_offset": from_offset,
+        "next_offset": chunk.next_offset,
+        "total_bytes": chunk.total_bytes,
+        "output": chunk.output
+    });
+
+    Ok(serde_json::to_string_pretty(&result).unwrap_or_else(|_| result.to_string()))
+}
+
+pub async fn agent_logs(pool: &AgentPool, arguments: Option<&Value>) -> ToolResult {
+    let agent_id = required_str(arguments, "agent_id")?;
+    let status = output_status(pool, agent_id).await?;
+
+    let lines = arguments
+        .and_then(|a| a.get("lines"))
+        .and_then(|l| l.as_u64())
+        .unwrap_or(50) as usize;
+
+    let output = crate::pool::recent_lines(&crate::pool::output_path(agent_id), lines)
+        .map_err(|e| ToolError(format!("No output available for agent {}: {}", agent_id, e)))?;
+
+    if output.is_empty() {
+        return Ok(format!("Agent {} ({}) has not produced any output", agent_id, status));
+    }
+
+    Ok(format!(
+        "Last {} line(s) from agent {} ({}):\n\n{}",
+        output.len(),
+        agent_id,
+        status,
+        output.join("\n")
+    ))
+}
+
+pub fn agent_types(pool: &AgentPool) -> ToolResult {
+    let types = pool.agent_types();
+    serde_json::to_string_pretty(&types).map_err(|e| ToolError(format!("Failed to list agent types: {}", e)))
+}
+
+pub async fn agent_pool_stats(pool: &AgentPool) -> ToolResult {
+    let stats = pool.stats().await;
+
+    Ok(format!(
+        "Agent Pool Statistics:\n\
+         Max agents: {}\n\
+         Total agents: {}\n\
+         Running: {}\n\
+         Paused: {}\n\
+         Queued: {}\n\
+         Completed: {}\n\
+         Failed: {}",
+        stats.max_agents,
+        stats.total_agents,
+        stats.running,
+        stats.paused,
+        stats.queued,
+        stats.completed,
+        stats.failed
+    ))
+}
+
+pub async fn agent_file_locks(pool: &AgentPool) -> ToolResult {
+    let lock_manager = pool.lock_manager();
+    let locks = lock_manager.list_locks().await;
+
+    if locks.is_empty() {
+        return Ok("No file locks currently held".to_string());
+    }
+
+    let mut output = format!("{} file lock(s):\n\n", locks.len());
+    for (path, info) in locks {
+        output.push_str(&format!(
+            "- {} ({}) by {}\n",
+            path.display(),
+            lock_type_name(info.lock_type),
+            info.agent_id
+        ));
+    }
+
+    Ok(output)
+}
+
+/// Name of a lock type, as used in tool arguments
+fn lock_type_name(lock_type: LockType) -> &'static str {
+    match lock_type {
+        LockType::Read => "read",
+        LockType::Write => "write",
+    }
+}
+
+pub async fn agent_lock_file(pool: &AgentPool, arguments: Option<&Value>) -> ToolResult {
+    let agent_id = required_str(arguments, "agent_id")?;
+    let path = required_str(arguments, "path")?;
+    let lock_type = match arguments.and_then(|a| a.get("lock_type")).and_then(|v| v.as_str()) {
+        None | Some("write") => LockType::Write,
+        Some("read") => LockType::Read,
+        Some(other) => {
+            return Err(ToolError(format!("Invalid lock_type '{}' (expected read or write)", other)));
+        }
+    };
+    let timeout_secs = arguments
+        .and_then(|a| a.get("timeout_secs"))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0);
+
+    let locked = pool
+        .lock_file(agent_id, path, lock_type, std::time::Duration::from_secs(timeout_secs))
+        .await
+        .map_err(|e| ToolError(format!("Failed to lock file: {}", e)))?;
+    if locked {
+        return Ok(format!("Locked {} ({}) for {}", path, lock_type_name(lock_type), agent_id));
+    }
+
+    let holder = pool
+        .lock_manager()
+        .get_lock_info(path)
+        .await
+        .map(|info| info.agent_id)
+        .unwrap_or_else(|| "another agent".to_string());
+    if timeout_secs == 0 {
+        Err(ToolError(format!("{} is locked by {}", path, holder)))
+    } else {
+        Ok(format!(
+            "{} is locked by {}; {} waits up to {}s for it in the background.\n\nCheck agent_file_locks to see when it has the lock, and agent_deadlocks if agents may be waiting on each other.",
+            path, holder, agent_id, timeout_secs
+        ))
+    }
+}
+
+pub async fn agent_unlock_file(pool: &AgentPool, arguments: Option<&Value>) -> ToolResult {
+    let agent_id = required_str(arguments, "agent_id")?;
+    let path = required_str(arguments, "path")?;
+
+    if pool.lock_manager().release(path, agent_id).await {
+        Ok(format!("Unlocked {} for {}", path, agent_id))
+    } else {
+        Err(ToolError(format!("{} holds no lock on {}", agent_id, path)))
+    }
+}
+
+/// Number of files listed by `agent_lock_stats`
+const MOST_CONTENDED_FILES: usize = 10;
+
+pub async fn agent_lock_stats(pool: &AgentPool, arguments: Option<&Value>) -> ToolResult {
+    let reset = arguments
+        .and_then(|a| a.get("reset"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let stats = pool.lock_manager().lock_stats(reset).await;
+
+    let mut output = format!(
+        "Lock acquisitions: {}\nBlocked attempts: {}\n",
+        stats.acquisitions, stats.blocked
+    );
+    let contended = stats.most_contended();
+    if contended.is_empty() {
+        output.push_str("\nNo lock contention");
+    } else {
+        output.push_str("\nMost contended files:\n");
+        for (path, count) in contended.iter().take(MOST_CONTENDED_FILES) {
+            output.push_str(&format!("- {}: {} blocked attempt(s)\n", path.display(), count));
+        }
+        if contended.len() > 1 {
+            output.push_str("\nAgents that keep contending for the same files are better run one after another (depends_on).");
+        }
+    }
+    if reset {
+        output.push_str("\n(counters reset)");
+    }
+
+    Ok(output)
+}
+
+pub async fn agent_deadlocks(pool: &AgentPool) -> ToolResult {
+    let lock_manager = pool.lock_manager();
+    let Some(cycle) = lock_manager.detect_cycle().await else {
+        return Ok("No deadlocks detected".to_string());
+    };
+
+    let mut lines = vec![format!("Deadlock detected between {} agents:", cycle.len())];
+    for (i, agent_id) in cycle.iter().enumerate() {
+        let holder = &cycle[(i + 1) % cycle.len()];
+        let path = lock_manager
+            .waiting_for(agent_id)
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "?".to_string());
+        lines.push(format!("  {} waits for {} held by {}", agent_id, path, holder));
+    }
+    lines.push("Stop one of these agents (agent_stop) to break the cycle.".to_string());
+
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_missing_required_parameter() {
+        let pool = AgentPool::new(1);
+        let err = agent_status(&pool, None).await.unwrap_err();
+        assert_eq!(err.to_string(), "Missing required parameter: agent_id");
+
+        let err = agent_spawn(&pool, Some(&json!({}))).await.unwrap_err();
+        assert_eq!(err.to_string(), "Missing required parameter: description");
+
+        let args = json!({ "description": "run tests", "depends_on": ["no-such-agent"] });
+        let err = agent_spawn(&pool, Some(&args)).await.unwrap_err();
+        assert_eq!(err.to_string(), "Failed to spawn agent: Unknown dependency: no-such-agent");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_agent() {
+        let pool = AgentPool::new(1);
+        let args = json!({ "agent_id": "no-such-agent" });
+        assert_eq!(
+            agent_status(&pool, Some(&args)).await.unwrap_err(),
+            ToolError::new("Agent no-such-agent not found")
+        );
+        assert!(agent_stop(&pool, Some(&args)).await.is_err());
+        assert!(agent_cancel(&pool, Some(&args)).await.is_err());
+        assert!(agent_pause(&pool, Some(&args)).await.is_err());
+        assert!(agent_resume(&pool, Some(&args)).await.is_err());
+        assert_eq!(
+            agent_tail(&pool, Some(&args)).await.unwrap_err(),
+            ToolError::new("Unknown agent no-such-agent")
+        );
+
+        // Ids end up in a file path, so even well-formed ones must be known
+        for agent_id in ["agent-../../etc/passwd", "agent-0b8f4a52-3c1e-4d7a-9f2b-6e5d4c3b2a19"] {
+            let args = json!({ "agent_id": agent_id });
+            assert_eq!(
+                agent_logs(&pool, Some(&args)).await.unwrap_err(),
+                ToolError::new(format!("Unknown agent {}", agent_id))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_empty_pool() {
+        let pool = AgentPool::new(3);
+        assert_eq!(agent_list(&pool).await.unwrap(), "No active background agents");
+        assert_eq!(agent_file_locks(&pool).await.unwrap(), "No file locks currently held");
+        assert_eq!(agent_deadlocks(&pool).await.unwrap(), "No deadlocks detected");
+
+        let lock_manager = pool.lock_manager();
+        assert!(lock_manager.try_acquire("/tmp/a.txt", "agent-1", LockType::Write).await);
+        assert!(!lock_manager.try_acquire("/tmp/a.txt", "agent-2", LockType::Write).await);
+        let stats = agent_lock_stats(&pool, Some(&json!({ "reset": true }))).await.unwrap();
+        assert!(stats.contains("Blocked attempts: 1"));
+        assert!(stats.contains("- /tmp/a.txt: 1 blocked attempt(s)"));
+        let stats = agent_lock_stats(&pool, None).await.unwrap();
+        assert!(stats.contains("No lock contention"));
+
+        let stats = agent_pool_stats(&pool).await.unwrap();
+        assert!(stats.starts_with("Agent Pool Statistics:\n"));
+        assert!(stats.contains("Max agents: 3\n"));
+        assert!(stats.ends_with("Failed: 0"));
+    }
+
+    #[tokio::test]
+    async fn test_lock_file_arguments() {
+        let pool = AgentPool::new(1);
+        let args = json!({ "agent_id": "no-such-agent", "path": "/tmp/a.txt" });
+        assert_eq!(
+            agent_lock_file(&pool, Some(&args)).await.unwrap_err(),
+            ToolError::new("Failed to lock file: Agent no-such-agent not found")
+        );
+        let args = json!({ "agent_id": "no-such-agent", "path": "/tmp/a.txt", "lock_type": "exclusive" });
+        assert_eq!(
+            agent_lock_file(&pool, Some(&args)).await.unwrap_err(),
+            ToolError::new("Invalid lock_type 'exclusive' (expected read or write)")
+        );
+        let args = json!({ "agent_id": "agent-1", "path": "/tmp/a.txt" });
+        assert_eq!(
+            agent_unlock_file(&pool, Some(&args)).await.unwrap_err(),
+            ToolError::new("agent-1 holds no lock on /tmp/a.txt")
+        );
+    }
+
+    #[test]
+    fn test_server_capabilities_without_wrapper() {
+        let capabilities: Value = serde_json::from_str(&server_capabilities().unwrap()).unwrap();
+        assert_eq!(capabilities["wrapper_running"], false);
+        assert_eq!(capabilities["restart"], false);
+        assert!(capabilities["health_history"]["enabled"].is_boolean());
+        assert!(capabilities["log_format"].is_string());
+    }
+}
@@ -0,0 +1,159 @@
+//! Agent Health History
+//!
+//! Samples the supervised agent's memory, CPU usage and open file
+//! descriptors from /proc, and appends the samples to a JSONL file so the
+//! resource usage of a long session can be plotted afterwards.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::restart;
+use crate::wrapper::AgentState;
+
+/// Environment variable through which the wrapper tells the MCP server where the history is
+pub const HEALTH_HISTORY_ENV: &str = "LAZARUS_HEALTH_HISTORY";
+
+/// How often the wrapper records a sample
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The history file is rotated to `<path>.1` once it grows past this size
+const MAX_HISTORY_BYTES: u64 = 8 * 1024 * 1024;
+
+/// One health measurement of the agent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthSample {
+    /// When the sample was taken (unix epoch)
+    pub ts: u64,
+    /// Agent state at the time
+    pub state: AgentState,
+    /// Resident memory
+    pub memory_kb: Option<u64>,
+    /// CPU usage since the previous sample (100 = one full core)
+    pub cpu_percent: Option<f32>,
+    /// Number of open file descriptors
+    pub fds: Option<usize>,
+}
+
+/// Takes successive samples of one process, tracking CPU time between them
+pub struct HealthSampler {
+    pid: u32,
+    /// When the previous sample was taken and the CPU ticks used by then
+    last_cpu: Option<(Instant, u64)>,
+}
+
+impl HealthSampler {
+    pub fn new(pid: u32) -> Self {
+        Self { pid, last_cpu: None }
+    }
+
+    /// Measure the process now
+    ///
+    /// CPU usage needs a previous sample to compare with, so the first sample has none.
+    pub fn sample(&mut self, state: AgentState) -> HealthSample {
+        let now = Instant::now();
+        let ticks = cpu_ticks(self.pid);
+        let cpu_percent = match (self.last_cpu, ticks) {
+            (Some((then, before)), Some(after)) => {
+                let elapsed = now.duration_since(then).as_secs_f64();
+                let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as f64;
+                (elapsed > 0.0 && ticks_per_sec > 0.0)
+                    .then(|| (after.saturating_sub(before) as f64 / ticks_per_sec / elapsed * 100.0) as f32)
+            }
+            _ => None,
+        };
+        self.last_cpu = ticks.map(|t| (now, t));
+
+        HealthSample {
+            ts: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            state,
+            memory_kb: restart::get_rss_kb(self.pid),
+            cpu_percent,
+            fds: fs::read_dir(format!("/proc/{}/fd", self.pid)).ok().map(|d| d.count()),
+        }
+    }
+}
+
+/// Total user + system CPU time of a process, in clock ticks
+fn cpu_ticks(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // utime and stime are the 14th and 15th fields; count from after the command name
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(utime + stime)
+}
+
+/// Path the history file is rotated to
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+/// Append a sample to the history file, rotating it once it gets too large
+pub fn append_sample(path: &Path, sample: &HealthSample) -> Result<()> {
+    if fs::metadata(path).map(|m| m.len() >= MAX_HISTORY_BYTES).unwrap_or(false) {
+        fs::rename(path, rotated_path(path))?;
+    }
+
+    let mut line = serde_json::to_string(sample)?;
+    line.push('\n');
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// The most recent samples in the history file, oldest first
+pub fn read_recent(path: &Path, n: usize) -> std::io::Result<Vec<HealthSample>> {
+    Ok(crate::pool::recent_lines(path, n)?
+        .iter()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_self() {
+        let mut sampler = HealthSampler::new(std::process::id());
+        let first = sampler.sample(AgentState::Running);
+        assert!(first.memory_kb.unwrap() > 0);
+        assert!(first.fds.unwrap() > 0);
+        assert!(first.cpu_percent.is_none());
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(sampler.sample(AgentState::Running).cpu_percent.is_some());
+    }
+
+    #[test]
+    fn test_append_and_read_recent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("health.jsonl");
+
+        for ts in 0..5 {
+            let sample = HealthSample {
+                ts,
+                state: AgentState::Running,
+                memory_kb: Some(1024),
+                cpu_percent: Some(12.5),
+                fds: Some(8),
+            };
+            append_sample(&path, &sample).unwrap();
+        }
+
+        let recent = read_recent(&path, 2).unwrap();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].ts, 3);
+        assert_eq!(recent[1].ts, 4);
+        assert_eq!(recent[1].cpu_percent, Some(12.5));
+    }
+}
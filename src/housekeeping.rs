@@ -0,0 +1,190 @@
+//! Temp File Housekeeping
+//!
+//! Keeps lazarus-mcp's files in the runtime (and legacy temp) directories
+//! under a size budget.
+//! Only files belonging to dead processes are ever removed, oldest first.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::{info, warn};
+
+/// Prefix shared by every lazarus-mcp temp file
+pub const TEMP_FILE_PREFIX: &str = "lazarus-mcp-";
+
+/// Summary of a budget enforcement pass
+#[derive(Debug, Clone, Default)]
+pub struct ReclaimReport {
+    /// Total size of lazarus-mcp temp files before reclaiming
+    pub total_bytes: u64,
+    /// Number of files removed
+    pub files_removed: usize,
+    /// Bytes freed by removing files
+    pub bytes_reclaimed: u64,
+}
+
+/// A lazarus-mcp temp file found during a scan
+struct TempFile {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+    owner_pid: Option<u32>,
+}
+
+/// Extract the owning PID from a temp file name
+///
+/// File names end with the owning process ID, optionally followed by an
+/// extension (e.g. `lazarus-mcp-state-1234` or `lazarus-mcp-pool-1234.json`).
+fn owner_pid(filename: &str) -> Option<u32> {
+    let stem = filename.split('.').next()?;
+    stem.rsplit('-').next()?.parse().ok()
+}
+
+/// Check whether a process is still alive
+fn is_pid_alive(pid: u32) -> bool {
+    fs::metadata(format!("/proc/{}", pid)).is_ok()
+}
+
+/// List all lazarus-mcp temp files in a directory
+fn scan(dir: &Path) -> Vec<TempFile> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(TEMP_FILE_PREFIX) {
+                return None;
+            }
+            let meta = entry.metadata().ok()?;
+            if !meta.is_file() {
+                return None;
+            }
+            Some(TempFile {
+                path: entry.path(),
+                size: meta.len(),
+                modified: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                owner_pid: owner_pid(&name),
+            })
+        })
+        .collect()
+}
+
+/// Delete the oldest files of dead sessions until the total is within budget
+///
+/// The budget covers the files in all of `dirs` together. Files whose owner
+/// is still running (or whose owner can't be determined) are never touched,
+/// so the total may remain over budget.
+pub fn enforce_tmp_budget(dirs: &[PathBuf], budget: u64) -> ReclaimReport {
+    let mut files: Vec<TempFile> = dirs.iter().flat_map(|dir| scan(dir)).collect();
+    let total_bytes: u64 = files.iter().map(|f| f.size).sum();
+
+    let mut report = ReclaimReport {
+        total_bytes,
+        ..Default::default()
+    };
+
+    if total_bytes <= budget {
+        return report;
+    }
+
+    files.sort_by_key(|f| f.modified);
+
+    let mut remaining = total_bytes;
+    for file in files {
+        if remaining <= budget {
+            break;
+        }
+        match file.owner_pid {
+            Some(pid) if !is_pid_alive(pid) => {}
+            _ => continue,
+        }
+        match fs::remove_file(&file.path) {
+            Ok(()) => {
+                remaining -= file.size;
+                report.files_removed += 1;
+                report.bytes_reclaimed += file.size;
+            }
+            Err(e) => warn!("Failed to remove {}: {}", file.path.display(), e),
+        }
+    }
+
+    if report.files_removed > 0 {
+        info!(
+            "Reclaimed {} bytes from {} stale temp file(s)",
+            report.bytes_reclaimed, report.files_removed
+        );
+    }
+    if remaining > budget {
+        warn!(
+            "Temp files still use {} bytes (budget {}); remaining files belong to live sessions",
+            remaining, budget
+        );
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PID far above any kernel's pid_max
+    const DEAD_PID: u32 = 999_999_999;
+
+    #[test]
+    fn test_owner_pid() {
+        assert_eq!(owner_pid("lazarus-mcp-1234"), Some(1234));
+        assert_eq!(owner_pid("lazarus-mcp-state-1234"), Some(1234));
+        assert_eq!(owner_pid("lazarus-mcp-pool-1234.json"), Some(1234));
+        assert_eq!(owner_pid("lazarus-mcp-state-abc"), None);
+    }
+
+    #[test]
+    fn test_removes_only_dead_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let dead = dir.path().join(format!("lazarus-mcp-state-{}", DEAD_PID));
+        let live = dir.path().join(format!("lazarus-mcp-state-{}", std::process::id()));
+        let other = dir.path().join("unrelated-file");
+        fs::write(&dead, "x".repeat(100)).unwrap();
+        fs::write(&live, "x".repeat(100)).unwrap();
+        fs::write(&other, "x".repeat(100)).unwrap();
+
+        let report = enforce_tmp_budget(&[dir.path().to_path_buf()], 0);
+
+        assert_eq!(report.total_bytes, 200);
+        assert_eq!(report.files_removed, 1);
+        assert_eq!(report.bytes_reclaimed, 100);
+        assert!(!dead.exists());
+        assert!(live.exists());
+        assert!(other.exists());
+    }
+
+    #[test]
+    fn test_within_budget_is_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let dead = dir.path().join(format!("lazarus-mcp-{}", DEAD_PID));
+        fs::write(&dead, "x".repeat(100)).unwrap();
+
+        let report = enforce_tmp_budget(&[dir.path().to_path_buf()], 1000);
+
+        assert_eq!(report.files_removed, 0);
+        assert!(dead.exists());
+    }
+
+    #[test]
+    fn test_budget_spans_all_dirs() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        fs::write(first.path().join(format!("lazarus-mcp-{}", DEAD_PID)), "x".repeat(100)).unwrap();
+        fs::write(second.path().join(format!("lazarus-mcp-state-{}", DEAD_PID)), "x".repeat(100)).unwrap();
+
+        let dirs = [first.path().to_path_buf(), second.path().to_path_buf()];
+        let report = enforce_tmp_budget(&dirs, 150);
+
+        assert_eq!(report.total_bytes, 200);
+        assert_eq!(report.files_removed, 1);
+    }
+}
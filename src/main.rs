@@ -1,13 +1,26 @@
+mod benchmark;
+mod handlers;
+mod health;
+mod housekeeping;
 mod mcp_server;
+mod notify;
+mod paths;
+// The pool and privilege modules expose more API than the binary currently calls
+#[allow(dead_code)]
 mod pool;
+#[allow(dead_code)]
 mod privileges;
+mod pty;
+mod report;
 mod restart;
 mod tui;
 mod wrapper;
 
 use anyhow::Result;
 use std::env;
-use std::path::PathBuf;
+use std::ffi::OsStr;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use tracing::Level;
 use tracing_subscriber::EnvFilter;
 
@@ -17,13 +30,156 @@ fn print_usage() {
     eprintln!("  lazarus-mcp [options] <command> [args...]   Run command with supervision");
     eprintln!("  lazarus-mcp --mcp-server                    Run as MCP server (used internally)");
     eprintln!("  lazarus-mcp --dashboard [wrapper-pid]       Run TUI dashboard");
-    eprintln!("  lazarus-mcp --version                       Show version information\n");
+    eprintln!("  lazarus-mcp --task <description> [options]  Run one background agent to completion");
+    eprintln!("  lazarus-mcp --version [--json]              Show version information\n");
     eprintln!("OPTIONS:");
-    eprintln!("  --no-inject-mcp        Don't auto-inject lazarus-mcp as an MCP server\n");
+    eprintln!("  --agent <type>         Agent for --task: claude (default), aider, cursor or one from agents.toml");
+    eprintln!("  --benchmark            Measure the agent's startup latency over several runs, then exit");
+    eprintln!("  --benchmark-runs <n>   Number of benchmark runs (default: 3)");
+    eprintln!("  --dry-run              Print the command, environment and .mcp.json that would be used, then exit");
+    eprintln!("  --env-allow <list>     Pass only these environment variables (plus PATH, HOME, USER, LOGNAME, TERM) to the agent");
+    eprintln!("  --env-clear            With --env-allow, don't keep PATH, HOME, etc. either");
+    eprintln!("  --health-history-file <path>  Append agent memory/CPU/fd samples to a JSONL file");
+    eprintln!("  --log-format <format>  Log format on stderr: text (default) or json (also: LAZARUS_LOG_FORMAT)");
+    eprintln!("  --max-iterations <n>   Maximum iterations for --task (default: 50)");
+    eprintln!("  --mcp-transform <cmd>  Pipe the injected .mcp.json through a shell command (stdin to stdout)");
+    eprintln!("  --no-color             Disable colored output (also honors NO_COLOR)");
+    eprintln!("  --no-inject-mcp        Don't auto-inject lazarus-mcp as an MCP server");
+    eprintln!("  --notify <backend>     Send notifications: desktop, webhook:<url>, slack:<url> or none");
+    eprintln!("  --notify-events <list> Events to notify about: restart,exit,agent (default: all)");
+    eprintln!("  --pty                  Run the agent attached to a pseudo-terminal");
+    eprintln!("  --report               Print a session summary (uptime, restarts, exit status) on exit");
+    eprintln!("  --report-file <path>   Write the session summary to a file on exit");
+    eprintln!("  --restart-on-reload    Restart the agent after SIGHUP reloads the injected .mcp.json");
+    eprintln!("  --run-as <user>        Run the agent as this user (name, uid or uid:gid) instead of the sudo user");
+    eprintln!("  --tmp-budget <bytes>   Cap total size of lazarus-mcp temp files (dead sessions' files are reclaimed)");
+    eprintln!("  --working-dir <path>   Directory the --task agent works in (default: current directory)\n");
     eprintln!("EXAMPLES:");
     eprintln!("  lazarus-mcp claude");
     eprintln!("  lazarus-mcp claude --continue");
     eprintln!("  lazarus-mcp --dashboard");
+    eprintln!("  lazarus-mcp --task \"fix the failing test\" --agent aider");
+}
+
+/// Wrapper options that take a value (`--opt value` or `--opt=value`)
+const VALUE_OPTIONS: &[&str] = &[
+    "--agent",
+    "--benchmark-runs",
+    "--env-allow",
+    "--health-history-file",
+    "--log-format",
+    "--max-iterations",
+    "--mcp-transform",
+    "--notify",
+    "--notify-events",
+    "--report-file",
+    "--run-as",
+    "--task",
+    "--tmp-budget",
+    "--working-dir",
+];
+
+/// Split the arguments (without the program name) into lazarus-mcp's options and the command
+///
+/// Options come first; the command starts at the first argument that isn't
+/// an option or an option's value, or after a `--` separator. The command is
+/// empty if there is none.
+fn split_args(args: &[String]) -> (&[String], &[String]) {
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        if arg == "--" {
+            return (&args[..i], &args[i + 1..]);
+        }
+        if !arg.starts_with("--") {
+            return (&args[..i], &args[i..]);
+        }
+        i += if VALUE_OPTIONS.contains(&arg.as_str()) { 2 } else { 1 };
+    }
+    (args, &[])
+}
+
+/// Get the value of a wrapper option from the parsed option list
+fn option_value<'a>(options: &'a [String], name: &str) -> Option<&'a str> {
+    let prefix = format!("{}=", name);
+    options.iter().enumerate().find_map(|(i, arg)| {
+        if arg == name {
+            options.get(i + 1).map(|v| v.as_str())
+        } else {
+            arg.strip_prefix(&prefix)
+        }
+    })
+}
+
+/// Decide whether output to a stream should be colored
+///
+/// Colors are used only on a terminal, and never when `--no-color` is given
+/// or `NO_COLOR` is set to a non-empty value (see https://no-color.org).
+fn use_color(no_color_flag: bool, no_color_env: Option<&OsStr>, is_terminal: bool) -> bool {
+    let env_disabled = no_color_env.is_some_and(|v| !v.is_empty());
+    is_terminal && !no_color_flag && !env_disabled
+}
+
+/// `use_color` for the current process environment
+fn color_for(no_color_flag: bool, is_terminal: bool) -> bool {
+    use_color(no_color_flag, env::var_os("NO_COLOR").as_deref(), is_terminal)
+}
+
+/// Environment variable selecting the log format (`text` or `json`)
+const LOG_FORMAT_ENV: &str = "LAZARUS_LOG_FORMAT";
+
+/// Format of log lines written to stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(LogFormat::Text),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        }
+    }
+}
+
+/// Pick the log format: the `--log-format` flag, else `LAZARUS_LOG_FORMAT`, else text
+fn log_format(flag: Option<&str>, env_value: Option<&str>) -> Result<LogFormat, String> {
+    match flag.or(env_value) {
+        Some(name) => LogFormat::parse(name)
+            .ok_or_else(|| format!("invalid log format '{}' (expected text or json)", name)),
+        None => Ok(LogFormat::Text),
+    }
+}
+
+/// `log_format` for the current environment, exiting on an invalid value
+fn log_format_or_exit(flag: Option<&str>) -> LogFormat {
+    let env_value = env::var(LOG_FORMAT_ENV).ok();
+    log_format(flag, env_value.as_deref()).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Log to stderr; stdout is left alone since it carries MCP traffic in server mode
+fn init_tracing(default_level: Level, format: LogFormat, ansi: bool) {
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env().add_directive(default_level.into()))
+        .with_writer(pty::log_writer)
+        .with_target(false);
+    match format {
+        LogFormat::Text => builder.with_ansi(ansi).init(),
+        LogFormat::Json => builder.json().init(),
+    }
 }
 
 fn main() -> Result<()> {
@@ -31,7 +187,11 @@ fn main() -> Result<()> {
 
     // Check for --version flag
     if args.iter().any(|arg| arg == "--version" || arg == "-V") {
-        wrapper::print_version_info();
+        if args.iter().any(|arg| arg == "--json") {
+            wrapper::print_version_json();
+        } else {
+            wrapper::print_version_info();
+        }
         return Ok(());
     }
 
@@ -43,14 +203,9 @@ fn main() -> Result<()> {
 
     // Check if running as MCP server
     if args.iter().any(|arg| arg == "--mcp-server") {
-        tracing_subscriber::fmt()
-            .with_env_filter(
-                EnvFilter::from_default_env()
-                    .add_directive(Level::INFO.into())
-            )
-            .with_writer(std::io::stderr)
-            .with_target(false)
-            .init();
+        // Inherited from the wrapper through the environment
+        let format = log_format_or_exit(option_value(&args, "--log-format"));
+        init_tracing(Level::INFO, format, color_for(false, std::io::stderr().is_terminal()));
 
         return mcp_server::run();
     }
@@ -67,7 +222,8 @@ fn main() -> Result<()> {
         match wrapper_pid {
             Some(pid) => {
                 eprintln!("Connecting to wrapper PID: {}", pid);
-                return tui::run_dashboard(pid);
+                let no_color = args.iter().any(|a| a == "--no-color");
+                return tui::run_dashboard(pid, color_for(no_color, std::io::stdout().is_terminal()));
             }
             None => {
                 eprintln!("Error: No running lazarus-mcp wrapper found.");
@@ -78,77 +234,257 @@ fn main() -> Result<()> {
         }
     }
 
-    // Wrapper mode - parse options and command
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::from_default_env()
-                .add_directive(Level::WARN.into())
-        )
-        .with_writer(std::io::stderr)
-        .with_target(false)
-        .init();
-
-    // Parse arguments: options come before the command, command is first non-option arg
-    // Optional -- separator is supported for compatibility
-    let separator_pos = args.iter().position(|a| a == "--");
-
-    let (aegis_args, command_args) = if let Some(pos) = separator_pos {
-        // Explicit -- separator: everything before is options, everything after is command
-        let aegis: Vec<String> = args[1..pos].to_vec();
-        let cmd: Vec<String> = args[pos + 1..].to_vec();
-        (aegis, cmd)
-    } else {
-        // No separator: find first non-option argument as the command
-        let first_cmd_pos = args[1..].iter().position(|a| !a.starts_with("--"));
-
-        match first_cmd_pos {
-            Some(pos) => {
-                let actual_pos = pos + 1; // Adjust for skipping args[0]
-                let aegis: Vec<String> = args[1..actual_pos].to_vec();
-                let cmd: Vec<String> = args[actual_pos..].to_vec();
-                (aegis, cmd)
-            }
-            None => {
-                // No command found - show usage
-                print_usage();
-                eprintln!("\nError: No command specified. Use: lazarus-mcp <command>");
+    // Options come before the command, so the command's own arguments
+    // (e.g. a `--task` meant for the agent) are passed through untouched
+    let (aegis_args, command_args) = split_args(&args[1..]);
+
+    // Run a single task to completion, without the wrapper or MCP server
+    if let Some(description) = option_value(aegis_args, "--task") {
+        if !command_args.is_empty() {
+            eprintln!("Error: --task runs an agent itself and takes no command");
+            std::process::exit(1);
+        }
+        let no_color = aegis_args.iter().any(|a| a == "--no-color");
+        let format = log_format_or_exit(option_value(aegis_args, "--log-format"));
+        init_tracing(Level::WARN, format, color_for(no_color, std::io::stderr().is_terminal()));
+
+        let max_iterations = match option_value(aegis_args, "--max-iterations").map(str::parse::<u32>) {
+            Some(Ok(n)) if n > 0 => n,
+            Some(_) => {
+                eprintln!("Error: --max-iterations expects a positive number");
                 std::process::exit(1);
             }
+            None => 50,
+        };
+        let mut task = pool::Task::new(description)
+            .with_agent_type(option_value(aegis_args, "--agent").unwrap_or("claude"))
+            .with_max_iterations(max_iterations);
+        if let Some(dir) = option_value(aegis_args, "--working-dir") {
+            task = task.with_working_directory(dir);
         }
-    };
 
-    // Must have a command
+        privileges::drop_privileges_for(option_value(aegis_args, "--run-as"))?;
+        let code = run_task(task)?;
+        std::process::exit(code);
+    }
+
+    // Wrapper mode - must have a command
     if command_args.is_empty() {
         print_usage();
-        eprintln!("\nError: No command specified");
+        eprintln!("\nError: No command specified. Use: lazarus-mcp <command>");
         std::process::exit(1);
     }
 
+    let no_color = aegis_args.iter().any(|a| a == "--no-color");
+
+    // Export the format so the MCP server and pool agents log the same way
+    let log_format = log_format_or_exit(option_value(aegis_args, "--log-format"));
+    env::set_var(LOG_FORMAT_ENV, log_format.as_str());
+    init_tracing(Level::WARN, log_format, color_for(no_color, std::io::stderr().is_terminal()));
+
     // Parse lazarus-mcp options
+    let tmp_budget = match option_value(aegis_args, "--tmp-budget") {
+        Some(value) => match value.parse::<u64>() {
+            Ok(bytes) => Some(bytes),
+            Err(_) => {
+                eprintln!("Error: --tmp-budget expects a size in bytes, got '{}'", value);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let notify = option_value(aegis_args, "--notify").map(String::from);
+    let notify_events = option_value(aegis_args, "--notify-events").map(String::from);
+    if let Some(spec) = &notify {
+        if let Err(e) = notify::Notifications::from_spec(spec, notify_events.as_deref()) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+
     let inject_mcp = !aegis_args.iter().any(|a| a == "--no-inject-mcp");
 
+    let run_as = option_value(aegis_args, "--run-as").map(String::from);
+    if let Some(user) = &run_as {
+        if let Err(e) = privileges::resolve_user(user) {
+            eprintln!("Error: --run-as {}: {}", user, e);
+            std::process::exit(1);
+        }
+    }
+
     // The command is the first element, rest are its arguments
-    let command = PathBuf::from(&command_args[0]);
+    let command = pool::resolve_command(Path::new(&command_args[0]));
     let cmd_args: Vec<String> = command_args[1..].to_vec();
 
-    wrapper::run_command(command, cmd_args, inject_mcp)
+    if aegis_args.iter().any(|a| a == "--benchmark") {
+        let runs = match option_value(aegis_args, "--benchmark-runs").map(str::parse::<usize>) {
+            Some(Ok(runs)) if runs > 0 => runs,
+            Some(_) => {
+                eprintln!("Error: --benchmark-runs expects a positive number");
+                std::process::exit(1);
+            }
+            None => 3,
+        };
+        return benchmark::run(&command, &cmd_args, runs, inject_mcp, run_as.as_deref());
+    }
+
+    let options = wrapper::WrapperOptions {
+        inject_mcp,
+        mcp_transform: option_value(aegis_args, "--mcp-transform").map(String::from),
+        health_history: option_value(aegis_args, "--health-history-file").map(PathBuf::from),
+        tmp_budget,
+        pty: aegis_args.iter().any(|a| a == "--pty"),
+        color: color_for(no_color, std::io::stdout().is_terminal()),
+        notify,
+        notify_events,
+        report: aegis_args.iter().any(|a| a == "--report"),
+        report_file: option_value(aegis_args, "--report-file").map(PathBuf::from),
+        run_as,
+        restart_on_reload: aegis_args.iter().any(|a| a == "--restart-on-reload"),
+        env_allow: option_value(aegis_args, "--env-allow")
+            .map(|list| list.split(',').map(str::trim).filter(|name| !name.is_empty()).map(String::from).collect())
+            .unwrap_or_default(),
+        env_clear: aegis_args.iter().any(|a| a == "--env-clear"),
+    };
+
+    if aegis_args.iter().any(|a| a == "--dry-run") {
+        return wrapper::dry_run(&command, &cmd_args, &options);
+    }
+
+    wrapper::run_command(command, cmd_args, options)
 }
 
-/// Find a running lazarus-mcp wrapper by scanning /tmp for state files
-fn find_running_wrapper() -> Option<u32> {
-    if let Ok(entries) = std::fs::read_dir("/tmp") {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if let Some(filename) = path.file_name().and_then(|n| n.to_str()) {
-                if let Some(pid_str) = filename.strip_prefix("lazarus-mcp-state-") {
-                    if let Ok(pid) = pid_str.parse::<u32>() {
-                        if std::fs::metadata(format!("/proc/{}", pid)).is_ok() {
-                            return Some(pid);
-                        }
-                    }
-                }
+/// Run one task with a background agent and wait for it
+///
+/// Uses a pool of one agent, as `agent_spawn` followed by `agent_await`
+/// would. The agent's output is printed once it finishes, and the pool is
+/// shut down afterwards (or on Ctrl+C) so its locks and files are cleaned up.
+/// Returns the exit code: 0 if the task succeeded, 1 if it failed.
+fn run_task(task: pool::Task) -> Result<i32> {
+    let rt = tokio::runtime::Runtime::new()?;
+    rt.block_on(async {
+        let pool = pool::AgentPool::new(1);
+        let agent_type = task.agent_type.clone();
+        let agent_id = pool.spawn(task).await?.agent_id().to_string();
+        eprintln!("Started {} agent {}", agent_type, agent_id);
+
+        let result = tokio::select! {
+            result = pool.await_completion(&agent_id) => result,
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("Interrupted; stopping agent {}", agent_id);
+                pool.shutdown().await;
+                return Ok(130);
             }
+        };
+
+        if let Ok(output) = std::fs::read_to_string(pool::output_path(&agent_id)) {
+            print!("{}", output);
+            let _ = std::io::stdout().flush();
+        }
+        pool.shutdown().await;
+
+        let result = result?;
+        if result.success {
+            eprintln!("Task succeeded: {}", result.summary);
+            Ok(0)
+        } else {
+            eprintln!("Task failed: {}", result.error.unwrap_or_default());
+            Ok(1)
+        }
+    })
+}
+
+/// Find the running lazarus-mcp wrapper to attach to
+///
+/// With several running, the user picks one from a numbered list.
+fn find_running_wrapper() -> Option<u32> {
+    let wrappers = wrapper::find_running_wrappers(&paths::search_dirs());
+    if wrappers.len() <= 1 {
+        return wrappers.first().map(|w| w.pid);
+    }
+
+    eprintln!("Multiple lazarus-mcp wrappers are running:");
+    for (i, wrapper) in wrappers.iter().enumerate() {
+        eprintln!("  {}) {}", i + 1, wrapper::describe_wrapper(wrapper));
+    }
+
+    if !std::io::stdin().is_terminal() {
+        eprintln!("Specify one with: lazarus-mcp --dashboard <pid>");
+        std::process::exit(1);
+    }
+
+    eprint!("Select a wrapper [1-{}]: ", wrappers.len());
+    let _ = std::io::stderr().flush();
+    let mut choice = String::new();
+    let _ = std::io::stdin().read_line(&mut choice);
+    match choice.trim().parse::<usize>() {
+        Ok(n) if (1..=wrappers.len()).contains(&n) => Some(wrappers[n - 1].pid),
+        _ => {
+            eprintln!("Error: invalid selection '{}'", choice.trim());
+            std::process::exit(1);
         }
     }
-    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_use_color() {
+        assert!(use_color(false, None, true));
+        assert!(!use_color(false, None, false));
+        assert!(!use_color(true, None, true));
+        assert!(!use_color(false, Some(OsStr::new("1")), true));
+        // An empty NO_COLOR doesn't disable colors
+        assert!(use_color(false, Some(OsStr::new("")), true));
+    }
+
+    #[test]
+    fn test_log_format() {
+        assert_eq!(log_format(None, None), Ok(LogFormat::Text));
+        assert_eq!(log_format(None, Some("json")), Ok(LogFormat::Json));
+        // The flag wins over the environment
+        assert_eq!(log_format(Some("text"), Some("json")), Ok(LogFormat::Text));
+        assert!(log_format(Some("yaml"), None).is_err());
+    }
+
+    #[test]
+    fn test_option_value() {
+        let options: Vec<String> = ["--pty", "--tmp-budget", "100", "--report-file=/tmp/r"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(option_value(&options, "--tmp-budget"), Some("100"));
+        assert_eq!(option_value(&options, "--report-file"), Some("/tmp/r"));
+        assert_eq!(option_value(&options, "--missing"), None);
+    }
+
+    #[test]
+    fn test_split_args() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        // The wrapped command's own --task is passed through
+        let wrapped = args(&["claude", "--task", "foo"]);
+        let (options, command) = split_args(&wrapped);
+        assert!(options.is_empty());
+        assert_eq!(command, &wrapped[..]);
+        assert_eq!(option_value(options, "--task"), None);
+
+        let wrapped = args(&["--tmp-budget", "100", "claude", "--task", "foo"]);
+        let (options, command) = split_args(&wrapped);
+        assert_eq!(option_value(options, "--tmp-budget"), Some("100"));
+        assert_eq!(command, &wrapped[2..]);
+
+        let task = args(&["--task", "fix it", "--agent", "aider"]);
+        let (options, command) = split_args(&task);
+        assert_eq!(option_value(options, "--task"), Some("fix it"));
+        assert!(command.is_empty());
+
+        let separated = args(&["--pty", "--", "claude", "--task", "y"]);
+        let (options, command) = split_args(&separated);
+        assert_eq!(options, &separated[..1]);
+        assert_eq!(command, &separated[2..]);
+    }
 }
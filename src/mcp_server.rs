@@ -7,11 +7,14 @@ use serde_json::{json, Value};
 use std::io::{BufRead, Write};
 use std::sync::Arc;
 use tokio::runtime::Runtime;
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc, RwLock};
 use tracing::{debug, error, info};
 
-use crate::pool::{AgentPool, AgentStatus, Task, TaskPriority};
+use crate::handlers::{self, ToolError, ToolResult};
+use crate::notify::{NotificationKind, Notifications};
+use crate::pool::AgentPool;
 use crate::restart;
+use crate::wrapper::SharedState;
 
 /// Lazy-initialized agent pool
 static POOL: std::sync::OnceLock<Arc<RwLock<AgentPool>>> = std::sync::OnceLock::new();
@@ -20,37 +23,251 @@ static POOL: std::sync::OnceLock<Arc<RwLock<AgentPool>>> = std::sync::OnceLock::
 fn get_pool() -> Arc<RwLock<AgentPool>> {
     POOL.get_or_init(|| {
         info!("Initializing agent pool");
-        Arc::new(RwLock::new(AgentPool::new(5)))
+        let mut pool = AgentPool::new(5);
+        // Let the wrapper's dashboard see the pool
+        let wrapper_pid = restart::find_wrapper_pid();
+        if let Some(wrapper_pid) = wrapper_pid {
+            pool.set_snapshot_path(crate::pool::snapshot_path(wrapper_pid));
+            pool.set_stop_request_path(crate::pool::stop_request_path(wrapper_pid));
+        }
+        if let Some(notifications) = wrapper_pid.and_then(Notifications::for_wrapper) {
+            pool.set_completion_callback(Arc::new(move |agent_id, result| {
+                let outcome = if result.success { "finished" } else { "failed" };
+                let detail = result.error.as_deref().unwrap_or(&result.summary);
+                notifications.notify(
+                    NotificationKind::Agent,
+                    agent_id,
+                    &format!("Background agent {}", outcome),
+                    &format!("{}: {}", agent_id, detail),
+                );
+            }));
+        }
+        Arc::new(RwLock::new(pool))
     })
     .clone()
 }
 
+/// How often the pool is checked for finished agents in the background
+const POOL_REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// How long running agents get to finish when the server shuts down
+const SHUTDOWN_GRACE_PERIOD: std::time::Duration = std::time::Duration::from_secs(5);
+
 /// MCP Server implementation
 pub fn run() -> Result<()> {
     info!("Starting lazarus-mcp MCP server");
 
+    // Rust already ignores SIGPIPE, but be explicit: a client that disconnects
+    // must show up as a BrokenPipe write error, not kill the server and orphan
+    // the agent pool
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_IGN);
+    }
+
     // Create tokio runtime for async operations
     let rt = Runtime::new()?;
 
+    // Notice finished agents (and start queued ones) even when nobody is awaiting them
+    rt.spawn(async {
+        loop {
+            tokio::time::sleep(POOL_REFRESH_INTERVAL).await;
+            get_pool().read().await.refresh().await;
+        }
+    });
+
     let stdin = std::io::stdin();
     let mut stdout = std::io::stdout();
+    let result = serve(&rt, stdin.lock(), &mut stdout);
+
+    // Cleanup
+    info!("MCP server shutting down");
+    rt.block_on(async {
+        let pool = get_pool();
+        let finished = pool.read().await.shutdown_graceful(SHUTDOWN_GRACE_PERIOD).await;
+        for (agent_id, result) in finished {
+            let outcome = if result.success { "succeeded" } else { "failed" };
+            info!("Agent {} {} before shutdown: {}", agent_id, outcome, result.summary);
+        }
+    });
+
+    result
+}
+
+/// How often progress is reported while a tool call with a progress token runs
+const PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Sends `notifications/progress` for the request that asked for them
+struct ProgressReporter {
+    token: Value,
+    sender: mpsc::UnboundedSender<Value>,
+    /// Last progress value sent; it must increase with every notification
+    progress: std::sync::atomic::AtomicU64,
+}
+
+impl ProgressReporter {
+    /// Reporter for a request carrying `params._meta.progressToken`, if any
+    fn for_request(request: &Value, sender: &mpsc::UnboundedSender<Value>) -> Option<Self> {
+        let token = request.pointer("/params/_meta/progressToken")?.clone();
+        Some(Self {
+            token,
+            sender: sender.clone(),
+            progress: std::sync::atomic::AtomicU64::new(0),
+        })
+    }
+
+    fn report(&self, message: String) {
+        let progress = self.progress.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+        let _ = self.sender.send(json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/progress",
+            "params": {
+                "progressToken": self.token,
+                "progress": progress,
+                "message": message
+            }
+        }));
+    }
+}
+
+/// Largest `Content-Length` accepted, so a bad header can't make us allocate unbounded memory
+const MAX_MESSAGE_LENGTH: usize = 64 * 1024 * 1024;
+
+/// How JSON-RPC messages are delimited on stdin and stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    /// One message per line
+    Lines,
+    /// Each message preceded by LSP-style headers: `Content-Length: N\r\n\r\n`
+    ContentLength,
+}
+
+impl Framing {
+    /// Tell the client's framing from its first message, without consuming it
+    ///
+    /// A message starts with `{` (or `[` for a batch); anything else is taken
+    /// to be headers. Returns `None` if the input ends first.
+    fn detect(input: &mut impl BufRead) -> std::io::Result<Option<Self>> {
+        loop {
+            let buf = input.fill_buf()?;
+            let Some(&first) = buf.first() else {
+                return Ok(None);
+            };
+            if first.is_ascii_whitespace() {
+                input.consume(1);
+                continue;
+            }
+            return Ok(Some(if first == b'{' || first == b'[' {
+                Framing::Lines
+            } else {
+                Framing::ContentLength
+            }));
+        }
+    }
+}
+
+/// Read the next JSON-RPC message, or `None` at the end of the input
+fn read_message(input: &mut impl BufRead, framing: Framing) -> std::io::Result<Option<String>> {
+    let mut line = String::new();
+    match framing {
+        Framing::Lines => loop {
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            let message = line.trim();
+            if !message.is_empty() {
+                return Ok(Some(message.to_string()));
+            }
+        },
+        Framing::ContentLength => {
+            let mut length = None;
+            let mut in_headers = false;
+            loop {
+                line.clear();
+                if input.read_line(&mut line)? == 0 {
+                    if in_headers {
+                        return Err(std::io::ErrorKind::UnexpectedEof.into());
+                    }
+                    return Ok(None);
+                }
+                let header = line.trim_end_matches(['\r', '\n']);
+                if header.is_empty() {
+                    if in_headers {
+                        break;
+                    }
+                    // Stray blank line between messages
+                    continue;
+                }
+                in_headers = true;
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.trim().eq_ignore_ascii_case("Content-Length") {
+                        length = value.trim().parse::<usize>().ok();
+                    }
+                }
+            }
+
+            let length = length.ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, "Message without a valid Content-Length header")
+            })?;
+            if length > MAX_MESSAGE_LENGTH {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Content-Length {} exceeds the {} byte limit", length, MAX_MESSAGE_LENGTH),
+                ));
+            }
+            let mut body = vec![0; length];
+            input.read_exact(&mut body)?;
+            String::from_utf8(body)
+                .map(Some)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        }
+    }
+}
 
-    for line in stdin.lock().lines() {
-        let line = match line {
-            Ok(l) => l,
+/// Write one JSON-RPC message, framed the way the client frames its own
+fn write_message(output: &mut impl Write, framing: Framing, message: &Value) -> std::io::Result<()> {
+    let text = serde_json::to_string(message)?;
+    debug!("Sending: {}", text);
+    match framing {
+        Framing::Lines => writeln!(output, "{}", text),
+        Framing::ContentLength => write!(output, "Content-Length: {}\r\n\r\n{}", text.len(), text),
+    }
+    .and_then(|()| output.flush())
+}
+
+/// Answer JSON-RPC requests until the input ends or the client disconnects
+///
+/// Messages are newline-delimited JSON, or framed with `Content-Length`
+/// headers if that is what the client sends. Progress notifications from the
+/// request being handled are written as they arrive, ahead of its response.
+fn serve(rt: &Runtime, mut input: impl BufRead, output: &mut impl Write) -> Result<()> {
+    let (progress_tx, mut progress_rx) = mpsc::unbounded_channel::<Value>();
+
+    let framing = match Framing::detect(&mut input) {
+        Ok(Some(framing)) => framing,
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            error!(error = %e, "Failed to read stdin");
+            return Ok(());
+        }
+    };
+    if framing == Framing::ContentLength {
+        info!("Client uses Content-Length framing");
+    }
+
+    loop {
+        let message = match read_message(&mut input, framing) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
             Err(e) => {
                 error!(error = %e, "Failed to read stdin");
                 break;
             }
         };
 
-        if line.is_empty() {
-            continue;
-        }
-
-        debug!("Received: {}", line);
+        debug!("Received: {}", message);
 
-        let request: Value = match serde_json::from_str(&line) {
+        let request: Value = match serde_json::from_str(&message) {
             Ok(v) => v,
             Err(e) => {
                 error!(error = %e, "Failed to parse JSON-RPC");
@@ -58,27 +275,40 @@ pub fn run() -> Result<()> {
             }
         };
 
-        let response = rt.block_on(handle_request(&request));
+        let progress = ProgressReporter::for_request(&request, &progress_tx);
+        let response = catch_request_panic(&request, || {
+            rt.block_on(async {
+                let handle = handle_request(&request, progress.as_ref());
+                tokio::pin!(handle);
+                loop {
+                    tokio::select! {
+                        response = &mut handle => break response,
+                        Some(notification) = progress_rx.recv() => {
+                            // A failed write shows up again on the response
+                            let _ = write_message(output, framing, &notification);
+                        }
+                    }
+                }
+            })
+        });
+        // Progress of a finished request is no longer of interest
+        while progress_rx.try_recv().is_ok() {}
 
         if let Some(resp) = response {
-            let resp_str = serde_json::to_string(&resp)?;
-            debug!("Sending: {}", resp_str);
-            writeln!(stdout, "{}", resp_str)?;
-            stdout.flush()?;
+            if let Err(e) = write_message(output, framing, &resp) {
+                if e.kind() == std::io::ErrorKind::BrokenPipe {
+                    info!("MCP client disconnected");
+                    break;
+                }
+                return Err(e.into());
+            }
         }
     }
 
-    // Cleanup
-    info!("MCP server shutting down");
-    rt.block_on(async {
-        let pool = get_pool();
-        pool.read().await.shutdown().await;
-    });
-
     Ok(())
 }
 
-async fn handle_request(request: &Value) -> Option<Value> {
+async fn handle_request(request: &Value, progress: Option<&ProgressReporter>) -> Option<Value> {
     let method = request.get("method")?.as_str()?;
     let id = request.get("id").cloned();
 
@@ -86,17 +316,19 @@ async fn handle_request(request: &Value) -> Option<Value> {
         "initialize" => handle_initialize(),
         "initialized" => return None, // Notification, no response
         "tools/list" => handle_tools_list(),
-        "tools/call" => handle_tools_call(request.get("params")).await,
+        "tools/call" => handle_tools_call(request.get("params"), progress).await,
+        "resources/list" => handle_resources_list(),
+        "resources/read" => match handle_resources_read(request.get("params")).await {
+            Ok(result) => result,
+            Err(message) => return Some(error_response(id, RESOURCE_NOT_FOUND, message)),
+        },
         "ping" => json!({}),
         _ => {
-            return Some(json!({
-                "jsonrpc": "2.0",
-                "id": id,
-                "error": {
-                    "code": -32601,
-                    "message": format!("Method not found: {}", method)
-                }
-            }));
+            return Some(error_response(
+                id,
+                METHOD_NOT_FOUND,
+                format!("Method not found: {}", method),
+            ));
         }
     };
 
@@ -107,11 +339,63 @@ async fn handle_request(request: &Value) -> Option<Value> {
     }))
 }
 
+/// Run a request handler, turning a panic into a JSON-RPC internal error
+///
+/// One bad tool call must not take down the server, and with it the agent's
+/// MCP connection and every background agent in the pool.
+fn catch_request_panic(request: &Value, handle: impl FnOnce() -> Option<Value>) -> Option<Value> {
+    let panic = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(handle)) {
+        Ok(response) => return response,
+        Err(panic) => panic,
+    };
+
+    let message = panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    let method = request.get("method").and_then(|m| m.as_str()).unwrap_or("?");
+    let tool = request
+        .pointer("/params/name")
+        .and_then(|n| n.as_str())
+        .unwrap_or("-");
+    error!(method, tool, "Request handler panicked: {}", message);
+
+    // Notifications get no response, not even an error
+    let id = request.get("id").cloned()?;
+    Some(error_response(
+        Some(id),
+        INTERNAL_ERROR,
+        format!("Internal error while handling {}: {}", method, message),
+    ))
+}
+
+/// JSON-RPC error code for an unknown method
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// JSON-RPC error code for a failure inside the server
+const INTERNAL_ERROR: i64 = -32603;
+
+/// MCP error code for an unknown or unavailable resource
+const RESOURCE_NOT_FOUND: i64 = -32002;
+
+fn error_response(id: Option<Value>, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {
+            "code": code,
+            "message": message
+        }
+    })
+}
+
 fn handle_initialize() -> Value {
     json!({
         "protocolVersion": "2024-11-05",
         "capabilities": {
-            "tools": {}
+            "tools": {},
+            "resources": {}
         },
         "serverInfo": {
             "name": "lazarus-mcp",
@@ -120,39 +404,70 @@ fn handle_initialize() -> Value {
     })
 }
 
+/// Input schema shared by `restart_agent` and its `restart_claude` alias
+fn restart_input_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "reason": {
+                "type": "string",
+                "description": "Optional reason for the restart (for logging)"
+            },
+            "prompt": {
+                "type": "string",
+                "description": "Optional prompt to automatically send after restart (e.g., 'Continue where we left off - MCP servers reloaded')"
+            }
+        }
+    })
+}
+
 fn handle_tools_list() -> Value {
     json!({
         "tools": [
             // Existing restart tools
+            {
+                "name": "restart_agent",
+                "description": "Restart the AI coding agent (Claude Code, aider, cursor, ...) to reconnect all MCP servers. Use this after making changes to an MCP server's code. Requires the agent to be started via the lazarus-mcp wrapper (e.g., 'lazarus-mcp claude'). The session will automatically continue if the agent supports it. Optionally include a prompt to auto-send after restart.",
+                "inputSchema": restart_input_schema()
+            },
             {
                 "name": "restart_claude",
-                "description": "Restart the AI coding agent to reconnect all MCP servers. Use this after making changes to an MCP server's code. Requires the agent to be started via the lazarus-mcp wrapper (e.g., 'lazarus-mcp claude'). The session will automatically continue if the agent supports it. Optionally include a prompt to auto-send after restart.",
+                "description": "Alias of restart_agent, kept for compatibility.",
+                "inputSchema": restart_input_schema()
+            },
+            {
+                "name": "server_status",
+                "description": "Get status information about the wrapper, Claude Code process, and whether restart is supported.",
                 "inputSchema": {
                     "type": "object",
-                    "properties": {
-                        "reason": {
-                            "type": "string",
-                            "description": "Optional reason for the restart (for logging)"
-                        },
-                        "prompt": {
-                            "type": "string",
-                            "description": "Optional prompt to automatically send after restart (e.g., 'Continue where we left off - MCP servers reloaded')"
-                        }
-                    }
+                    "properties": {}
                 }
             },
             {
-                "name": "server_status",
-                "description": "Get status information about the wrapper, Claude Code process, and whether restart is supported.",
+                "name": "server_capabilities",
+                "description": "Report which optional features are active in this session: MCP injection, health history, notifications, log format, and whether restart is available.",
                 "inputSchema": {
                     "type": "object",
                     "properties": {}
                 }
             },
+            {
+                "name": "health_history",
+                "description": "Get recent memory, CPU and open file descriptor samples of the supervised agent. Requires the wrapper to be started with --health-history-file.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "limit": {
+                            "type": "integer",
+                            "description": "Number of most recent samples to return (default: 60)"
+                        }
+                    }
+                }
+            },
             // Agent pool tools
             {
                 "name": "agent_spawn",
-                "description": "Spawn a background agent to work on a task autonomously. The agent will execute the task in the background and report results. Returns the agent ID immediately.",
+                "description": "Spawn a background agent to work on a task autonomously. The agent will execute the task in the background and report results. Returns the agent ID immediately. If the pool is full the task is queued by priority and starts when a slot frees up.",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
@@ -162,8 +477,7 @@ fn handle_tools_list() -> Value {
                         },
                         "agent_type": {
                             "type": "string",
-                            "enum": ["claude", "aider", "cursor"],
-                            "description": "Type of agent to spawn (default: claude)"
+                            "description": "Type of agent to spawn: claude (default), aider, cursor or one declared in agents.toml (see agent_types)"
                         },
                         "working_directory": {
                             "type": "string",
@@ -177,6 +491,19 @@ fn handle_tools_list() -> Value {
                             "type": "string",
                             "enum": ["low", "normal", "high", "urgent"],
                             "description": "Task priority (default: normal)"
+                        },
+                        "depends_on": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "IDs of agents that must complete successfully before this one starts. If one of them fails, this agent fails without starting."
+                        },
+                        "memory_limit_mb": {
+                            "type": "integer",
+                            "description": "Optional address space limit for the agent process, in MiB"
+                        },
+                        "cpu_time_limit_secs": {
+                            "type": "integer",
+                            "description": "Optional CPU time limit for the agent process, in seconds"
                         }
                     },
                     "required": ["description"]
@@ -236,6 +563,114 @@ fn handle_tools_list() -> Value {
                     "required": ["agent_id"]
                 }
             },
+            {
+                "name": "agent_retry",
+                "description": "Run the task of a finished (e.g. failed) background agent again as a new agent. Agents that support it continue the previous session. Returns the new agent ID.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "agent_id": {
+                            "type": "string",
+                            "description": "ID of the finished agent whose task to retry"
+                        },
+                        "include_error": {
+                            "type": "boolean",
+                            "description": "Append the previous attempt's error to the task description (default: true)"
+                        }
+                    },
+                    "required": ["agent_id"]
+                }
+            },
+            {
+                "name": "agent_pause",
+                "description": "Temporarily freeze a running background agent (SIGSTOP), e.g. to free up the CPU. The agent keeps its slot in the pool until resumed.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "agent_id": {
+                            "type": "string",
+                            "description": "ID of the agent to pause"
+                        }
+                    },
+                    "required": ["agent_id"]
+                }
+            },
+            {
+                "name": "agent_resume",
+                "description": "Continue a background agent paused with agent_pause (SIGCONT).",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "agent_id": {
+                            "type": "string",
+                            "description": "ID of the agent to resume"
+                        }
+                    },
+                    "required": ["agent_id"]
+                }
+            },
+            {
+                "name": "agent_cancel",
+                "description": "Cancel a background agent: a queued agent is removed from the queue before it starts, a running agent is stopped. Reports which case applied.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "agent_id": {
+                            "type": "string",
+                            "description": "ID of the agent to cancel"
+                        }
+                    },
+                    "required": ["agent_id"]
+                }
+            },
+            {
+                "name": "agent_tail",
+                "description": "Read a background agent's output incrementally. Returns output written since from_offset plus the next_offset to pass on the following call, so a client can stream output by polling.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "agent_id": {
+                            "type": "string",
+                            "description": "ID of the agent to read output from"
+                        },
+                        "from_offset": {
+                            "type": "integer",
+                            "description": "Byte offset to read from (default: 0, the start of the output)"
+                        },
+                        "max_bytes": {
+                            "type": "integer",
+                            "description": "Maximum number of bytes to return (default: 65536)"
+                        }
+                    },
+                    "required": ["agent_id"]
+                }
+            },
+            {
+                "name": "agent_logs",
+                "description": "Show the most recent lines a background agent printed to stdout/stderr. Works while the agent is running and after it finishes.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "agent_id": {
+                            "type": "string",
+                            "description": "ID of the agent to show output for"
+                        },
+                        "lines": {
+                            "type": "integer",
+                            "description": "Number of lines to return (default: 50)"
+                        }
+                    },
+                    "required": ["agent_id"]
+                }
+            },
+            {
+                "name": "agent_types",
+                "description": "List the agent types agent_spawn accepts, whether each one's executable was found on this machine, its path, and its capabilities (supports_continue, supports_skip_permissions). Use this to pick a valid agent_type.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {}
+                }
+            },
             {
                 "name": "agent_pool_stats",
                 "description": "Get statistics about the agent pool (active, running, completed agents).",
@@ -251,401 +686,395 @@ fn handle_tools_list() -> Value {
                     "type": "object",
                     "properties": {}
                 }
+            },
+            {
+                "name": "agent_lock_file",
+                "description": "Lock a file for a running background agent, so agents working in the same repo don't edit it at the same time. Locks are released when the agent finishes. If another agent holds the file, fails right away, or with timeout_secs waits for it in the background.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "agent_id": {
+                            "type": "string",
+                            "description": "The ID of the agent to lock the file for"
+                        },
+                        "path": {
+                            "type": "string",
+                            "description": "Absolute path of the file to lock"
+                        },
+                        "lock_type": {
+                            "type": "string",
+                            "enum": ["read", "write"],
+                            "description": "Read locks can be shared, write locks are exclusive (default: write)"
+                        },
+                        "timeout_secs": {
+                            "type": "integer",
+                            "description": "How long to wait for a held lock, in seconds (default: 0, don't wait)"
+                        }
+                    },
+                    "required": ["agent_id", "path"]
+                }
+            },
+            {
+                "name": "agent_unlock_file",
+                "description": "Release a file lock held by a background agent.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "agent_id": {
+                            "type": "string",
+                            "description": "The ID of the agent holding the lock"
+                        },
+                        "path": {
+                            "type": "string",
+                            "description": "Path of the locked file"
+                        }
+                    },
+                    "required": ["agent_id", "path"]
+                }
+            },
+            {
+                "name": "agent_lock_stats",
+                "description": "Show how often agents were blocked on each other's file locks, most contended files first. Agents that keep contending for the same files are better run one after another.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "reset": {
+                            "type": "boolean",
+                            "description": "Reset the counters after reading them (default: false, counters are cumulative)"
+                        }
+                    }
+                }
+            },
+            {
+                "name": "agent_deadlocks",
+                "description": "Check whether background agents are deadlocked waiting on each other's file locks. Names the agents and files in the cycle so one can be stopped.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {}
+                }
             }
         ]
     })
 }
 
-async fn handle_tools_call(params: Option<&Value>) -> Value {
-    let params = match params {
-        Some(p) => p,
-        None => {
-            return json!({
-                "content": [{
-                    "type": "text",
-                    "text": "Missing params"
-                }],
-                "isError": true
-            });
-        }
+/// Wrap a handler result in the MCP tool content envelope
+fn to_mcp_content(result: ToolResult) -> Value {
+    let (text, is_error) = match result {
+        Ok(text) => (text, false),
+        Err(ToolError(text)) => (text, true),
     };
-
-    let tool_name = params.get("name").and_then(|n| n.as_str()).unwrap_or("");
-    let arguments = params.get("arguments");
-
-    match tool_name {
-        // Existing tools
-        "restart_claude" => handle_restart_claude(arguments),
-        "server_status" => handle_server_status(),
-        // Agent pool tools
-        "agent_spawn" => handle_agent_spawn(arguments).await,
-        "agent_list" => handle_agent_list().await,
-        "agent_status" => handle_agent_status(arguments).await,
-        "agent_await" => handle_agent_await(arguments).await,
-        "agent_stop" => handle_agent_stop(arguments).await,
-        "agent_pool_stats" => handle_agent_pool_stats().await,
-        "agent_file_locks" => handle_agent_file_locks().await,
-        _ => json!({
-            "content": [{
-                "type": "text",
-                "text": format!("Unknown tool: {}", tool_name)
-            }],
-            "isError": true
-        }),
-    }
-}
-
-fn handle_restart_claude(arguments: Option<&Value>) -> Value {
-    let reason = arguments
-        .and_then(|a| a.get("reason"))
-        .and_then(|r| r.as_str())
-        .unwrap_or("MCP server restart requested")
-        .to_string();
-
-    let prompt = arguments
-        .and_then(|a| a.get("prompt"))
-        .and_then(|p| p.as_str());
-
-    info!(reason = %reason, prompt = ?prompt, "Triggering Claude Code restart via signal file");
-
-    match restart::send_restart_signal(&reason, prompt) {
-        Ok(info) => {
-            let prompt_msg = if prompt.is_some() {
-                "\nA prompt will be auto-sent after restart."
-            } else {
-                ""
-            };
-            json!({
-                "content": [{
-                    "type": "text",
-                    "text": format!(
-                        "Restart signal sent!\n\nWrapper PID: {}\nReason: {}{}\n\nClaude will restart momentarily and resume with --continue.",
-                        info.wrapper_pid,
-                        reason,
-                        prompt_msg
-                    )
-                }],
-                "isError": false
-            })
-        }
-        Err(e) => json!({
-            "content": [{
-                "type": "text",
-                "text": format!("Failed to trigger restart: {}\n\nMake sure you started your agent via the lazarus-mcp wrapper:\n  lazarus-mcp <agent> [args...]\n\nExample: lazarus-mcp claude --continue", e)
-            }],
-            "isError": true
-        }),
-    }
-}
-
-fn handle_server_status() -> Value {
-    let status = restart::get_status();
-
     json!({
         "content": [{
             "type": "text",
-            "text": serde_json::to_string_pretty(&status).unwrap_or_else(|_| format!("{:?}", status))
+            "text": text
         }],
-        "isError": false
+        "isError": is_error
     })
 }
 
-// Agent pool tool handlers
-
-async fn handle_agent_spawn(arguments: Option<&Value>) -> Value {
-    let description = match arguments.and_then(|a| a.get("description")).and_then(|d| d.as_str()) {
-        Some(d) => d.to_string(),
-        None => {
-            return json!({
-                "content": [{
-                    "type": "text",
-                    "text": "Missing required parameter: description"
-                }],
-                "isError": true
-            });
+fn handle_resources_list() -> Value {
+    json!({
+        "resources": [
+            {
+                "uri": "lazarus://state",
+                "name": "Wrapper state",
+                "description": "Shared state of the lazarus-mcp wrapper supervising this agent: agent PID and status, restart count, uptime, and the last restart explanation.",
+                "mimeType": "application/json"
+            },
+            {
+                "uri": "lazarus://status",
+                "name": "Server status",
+                "description": "Status of this MCP server and its wrapper (same as the server_status tool).",
+                "mimeType": "application/json"
+            },
+            {
+                "uri": "lazarus://agents",
+                "name": "Background agents",
+                "description": "Background agents in the pool and their current status.",
+                "mimeType": "application/json"
+            }
+        ]
+    })
+}
+
+async fn handle_resources_read(params: Option<&Value>) -> std::result::Result<Value, String> {
+    let uri = params
+        .and_then(|p| p.get("uri"))
+        .and_then(|u| u.as_str())
+        .ok_or_else(|| "Missing required parameter: uri".to_string())?;
+
+    let text = match uri {
+        "lazarus://state" => {
+            let wrapper_pid = restart::find_wrapper_pid()
+                .ok_or_else(|| "Not running under a lazarus-mcp wrapper".to_string())?;
+            let state = SharedState::load(wrapper_pid)
+                .map_err(|e| format!("Failed to read wrapper state: {}", e))?;
+            serde_json::to_string_pretty(&state).map_err(|e| e.to_string())?
         }
+        "lazarus://status" => handlers::server_status().map_err(|e| e.to_string())?,
+        "lazarus://agents" => {
+            let pool = get_pool();
+            let pool = pool.read().await;
+            let agents: Vec<Value> = pool
+                .list()
+                .await
+                .into_iter()
+                .map(|(id, status)| json!({ "agent_id": id, "status": status.to_string() }))
+                .collect();
+            serde_json::to_string_pretty(&agents).map_err(|e| e.to_string())?
+        }
+        _ => return Err(format!("Resource not found: {}", uri)),
     };
 
-    let agent_type = arguments
-        .and_then(|a| a.get("agent_type"))
-        .and_then(|t| t.as_str())
-        .unwrap_or("claude")
-        .to_string();
-
-    let working_directory = arguments
-        .and_then(|a| a.get("working_directory"))
-        .and_then(|d| d.as_str())
-        .map(std::path::PathBuf::from);
-
-    let max_iterations = arguments
-        .and_then(|a| a.get("max_iterations"))
-        .and_then(|m| m.as_u64())
-        .unwrap_or(50) as u32;
-
-    let priority = match arguments
-        .and_then(|a| a.get("priority"))
-        .and_then(|p| p.as_str())
-        .unwrap_or("normal")
-    {
-        "low" => TaskPriority::Low,
-        "high" => TaskPriority::High,
-        "urgent" => TaskPriority::Urgent,
-        _ => TaskPriority::Normal,
-    };
+    Ok(json!({
+        "contents": [{
+            "uri": uri,
+            "mimeType": "application/json",
+            "text": text
+        }]
+    }))
+}
 
-    let mut task = Task::new(&description)
-        .with_agent_type(&agent_type)
-        .with_max_iterations(max_iterations)
-        .with_priority(priority);
+/// Await an agent, reporting its status as progress until it finishes
+async fn agent_await_with_progress(
+    pool: &AgentPool,
+    arguments: Option<&Value>,
+    progress: &ProgressReporter,
+    interval: std::time::Duration,
+) -> ToolResult {
+    let agent_id = arguments
+        .and_then(|a| a.get("agent_id"))
+        .and_then(|id| id.as_str())
+        .unwrap_or_default();
+    let started = std::time::Instant::now();
 
-    if let Some(dir) = working_directory {
-        task = task.with_working_directory(dir);
+    let result = handlers::agent_await(pool, arguments);
+    tokio::pin!(result);
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    loop {
+        tokio::select! {
+            result = &mut result => return result,
+            _ = ticker.tick() => {
+                if let Some(status) = pool.status(agent_id).await {
+                    progress.report(format!(
+                        "Agent {}: {} ({}s)",
+                        agent_id,
+                        status,
+                        started.elapsed().as_secs()
+                    ));
+                }
+            }
+        }
     }
+}
+
+async fn handle_tools_call(params: Option<&Value>, progress: Option<&ProgressReporter>) -> Value {
+    let params = match params {
+        Some(p) => p,
+        None => return to_mcp_content(Err(ToolError::new("Missing params"))),
+    };
+
+    let tool_name = params.get("name").and_then(|n| n.as_str()).unwrap_or("");
+    let arguments = params.get("arguments");
 
     let pool = get_pool();
     let pool = pool.read().await;
 
-    match pool.spawn(task).await {
-        Ok(agent_id) => json!({
-            "content": [{
-                "type": "text",
-                "text": format!(
-                    "Spawned background agent: {}\n\nTask: {}\nAgent type: {}\nMax iterations: {}",
-                    agent_id, description, agent_type, max_iterations
-                )
-            }],
-            "isError": false
-        }),
-        Err(e) => json!({
-            "content": [{
-                "type": "text",
-                "text": format!("Failed to spawn agent: {}", e)
-            }],
-            "isError": true
-        }),
-    }
+    let result = match tool_name {
+        // Existing tools
+        "restart_agent" | "restart_claude" => handlers::restart_agent(arguments),
+        "server_status" => handlers::server_status(),
+        "server_capabilities" => handlers::server_capabilities(),
+        "health_history" => handlers::health_history(arguments),
+        // Agent pool tools
+        "agent_spawn" => handlers::agent_spawn(&pool, arguments).await,
+        "agent_list" => handlers::agent_list(&pool).await,
+        "agent_status" => handlers::agent_status(&pool, arguments).await,
+        "agent_await" => match progress {
+            Some(progress) => agent_await_with_progress(&pool, arguments, progress, PROGRESS_INTERVAL).await,
+            None => handlers::agent_await(&pool, arguments).await,
+        },
+        "agent_stop" => handlers::agent_stop(&pool, arguments).await,
+        "agent_retry" => handlers::agent_retry(&pool, arguments).await,
+        "agent_pause" => handlers::agent_pause(&pool, arguments).await,
+        "agent_resume" => handlers::agent_resume(&pool, arguments).await,
+        "agent_cancel" => handlers::agent_cancel(&pool, arguments).await,
+        "agent_tail" => handlers::agent_tail(&pool, arguments).await,
+        "agent_logs" => handlers::agent_logs(&pool, arguments).await,
+        "agent_types" => handlers::agent_types(&pool),
+        "agent_pool_stats" => handlers::agent_pool_stats(&pool).await,
+        "agent_file_locks" => handlers::agent_file_locks(&pool).await,
+        "agent_lock_file" => handlers::agent_lock_file(&pool, arguments).await,
+        "agent_unlock_file" => handlers::agent_unlock_file(&pool, arguments).await,
+        "agent_lock_stats" => handlers::agent_lock_stats(&pool, arguments).await,
+        "agent_deadlocks" => handlers::agent_deadlocks(&pool).await,
+        _ => Err(ToolError(format!("Unknown tool: {}", tool_name))),
+    };
+
+    to_mcp_content(result)
 }
 
-async fn handle_agent_list() -> Value {
-    let pool = get_pool();
-    let pool = pool.read().await;
-    let agents = pool.list().await;
-
-    if agents.is_empty() {
-        return json!({
-            "content": [{
-                "type": "text",
-                "text": "No active background agents"
-            }],
-            "isError": false
-        });
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let mut output = format!("{} active agent(s):\n\n", agents.len());
-    for (id, status) in agents {
-        let icon = match &status {
-            AgentStatus::Starting => "🔄",
-            AgentStatus::Running { .. } => "▶️",
-            AgentStatus::Completed { .. } => "✅",
-            AgentStatus::Failed { .. } => "❌",
-            AgentStatus::Stopped => "⏹️",
-        };
-        output.push_str(&format!("{} {} - {}\n", icon, id, status));
+    /// Output whose reader has gone away
+    struct ClosedPipe {
+        writes: usize,
     }
 
-    json!({
-        "content": [{
-            "type": "text",
-            "text": output
-        }],
-        "isError": false
-    })
-}
+    impl Write for ClosedPipe {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            self.writes += 1;
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
 
-async fn handle_agent_status(arguments: Option<&Value>) -> Value {
-    let agent_id = match arguments.and_then(|a| a.get("agent_id")).and_then(|i| i.as_str()) {
-        Some(id) => id,
-        None => {
-            return json!({
-                "content": [{
-                    "type": "text",
-                    "text": "Missing required parameter: agent_id"
-                }],
-                "isError": true
-            });
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
         }
-    };
+    }
 
-    let pool = get_pool();
-    let pool = pool.read().await;
+    #[test]
+    fn test_serve_stops_on_broken_pipe() {
+        let rt = Runtime::new().unwrap();
+        let input = "{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"ping\"}\n\
+                     {\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"ping\"}\n";
+        let mut output = ClosedPipe { writes: 0 };
 
-    match pool.status(agent_id).await {
-        Some(status) => json!({
-            "content": [{
-                "type": "text",
-                "text": format!("Agent {}: {}", agent_id, status)
-            }],
-            "isError": false
-        }),
-        None => json!({
-            "content": [{
-                "type": "text",
-                "text": format!("Agent {} not found", agent_id)
-            }],
-            "isError": true
-        }),
+        serve(&rt, input.as_bytes(), &mut output).unwrap();
+
+        // Gave up after the first failed write instead of erroring or continuing
+        assert_eq!(output.writes, 1);
     }
-}
 
-async fn handle_agent_await(arguments: Option<&Value>) -> Value {
-    let agent_id = match arguments.and_then(|a| a.get("agent_id")).and_then(|i| i.as_str()) {
-        Some(id) => id.to_string(),
-        None => {
-            return json!({
-                "content": [{
-                    "type": "text",
-                    "text": "Missing required parameter: agent_id"
-                }],
-                "isError": true
-            });
-        }
-    };
+    #[test]
+    fn test_serve_line_framing() {
+        let rt = Runtime::new().unwrap();
+        let input = "\n{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"ping\"}\n\n\
+                     {\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"ping\"}\n";
+        let mut output = Vec::new();
 
-    let timeout_secs = arguments
-        .and_then(|a| a.get("timeout_secs"))
-        .and_then(|t| t.as_u64())
-        .map(|t| std::time::Duration::from_secs(t));
+        serve(&rt, input.as_bytes(), &mut output).unwrap();
 
-    let pool = get_pool();
-    let pool = pool.read().await;
+        let output = String::from_utf8(output).unwrap();
+        let ids: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap()["id"].clone())
+            .collect();
+        assert_eq!(ids, [json!(1), json!(2)]);
+    }
 
-    let result = if let Some(timeout) = timeout_secs {
-        pool.await_completion_timeout(&agent_id, timeout).await
-    } else {
-        pool.await_completion(&agent_id).await
-    };
+    #[test]
+    fn test_serve_content_length_framing() {
+        let rt = Runtime::new().unwrap();
+        // A body spanning several lines, which line framing would split up
+        let multiline = "{\n  \"jsonrpc\": \"2.0\",\n  \"id\": 1,\n  \"method\": \"ping\"\n}";
+        let single = r#"{"jsonrpc":"2.0","id":2,"method":"ping"}"#;
+        let input = format!(
+            "Content-Length: {}\r\nContent-Type: application/vscode-jsonrpc; charset=utf-8\r\n\r\n{}\
+             content-length: {}\r\n\r\n{}",
+            multiline.len(),
+            multiline,
+            single.len(),
+            single
+        );
+        let mut output = Vec::new();
 
-    match result {
-        Ok(task_result) => {
-            let status = if task_result.success { "succeeded" } else { "failed" };
-            let error_msg = task_result.error.map(|e| format!("\nError: {}", e)).unwrap_or_default();
-            json!({
-                "content": [{
-                    "type": "text",
-                    "text": format!(
-                        "Agent {} {} after {} iterations.\n\nSummary: {}{}",
-                        agent_id, status, task_result.iterations, task_result.summary, error_msg
-                    )
-                }],
-                "isError": !task_result.success
-            })
+        serve(&rt, input.as_bytes(), &mut output).unwrap();
+
+        // Responses are framed the same way
+        let mut output = output.as_slice();
+        for id in [1, 2] {
+            let body = read_message(&mut output, Framing::ContentLength).unwrap().unwrap();
+            let response: Value = serde_json::from_str(&body).unwrap();
+            assert_eq!(response["id"], id);
+            assert_eq!(response["result"], json!({}));
         }
-        Err(e) => json!({
-            "content": [{
-                "type": "text",
-                "text": format!("Error waiting for agent: {}", e)
-            }],
-            "isError": true
-        }),
+        assert!(read_message(&mut output, Framing::ContentLength).unwrap().is_none());
+
+        // Absurd lengths are rejected before allocating
+        let input = format!("Content-Length: {}\r\n\r\n{{}}", MAX_MESSAGE_LENGTH + 1);
+        let err = read_message(&mut input.as_bytes(), Framing::ContentLength).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
     }
-}
 
-async fn handle_agent_stop(arguments: Option<&Value>) -> Value {
-    let agent_id = match arguments.and_then(|a| a.get("agent_id")).and_then(|i| i.as_str()) {
-        Some(id) => id,
-        None => {
-            return json!({
-                "content": [{
-                    "type": "text",
-                    "text": "Missing required parameter: agent_id"
-                }],
-                "isError": true
-            });
-        }
-    };
+    #[test]
+    fn test_panicking_request_becomes_internal_error() {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "method": "tools/call",
+            "params": { "name": "agent_spawn" }
+        });
+        let response = catch_request_panic(&request, || panic!("boom")).unwrap();
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["error"]["code"], INTERNAL_ERROR);
+        assert!(response["error"]["message"].as_str().unwrap().ends_with("boom"));
 
-    let pool = get_pool();
-    let pool = pool.read().await;
+        // Notifications stay silent
+        let notification = json!({ "jsonrpc": "2.0", "method": "initialized" });
+        assert!(catch_request_panic(&notification, || panic!("boom")).is_none());
 
-    match pool.stop(agent_id).await {
-        Ok(()) => json!({
-            "content": [{
-                "type": "text",
-                "text": format!("Stopped agent {}", agent_id)
-            }],
-            "isError": false
-        }),
-        Err(e) => json!({
-            "content": [{
-                "type": "text",
-                "text": format!("Failed to stop agent: {}", e)
-            }],
-            "isError": true
-        }),
+        // Non-panicking handlers pass through
+        let ok = catch_request_panic(&request, || Some(json!("fine")));
+        assert_eq!(ok, Some(json!("fine")));
     }
-}
 
-async fn handle_agent_pool_stats() -> Value {
-    let pool = get_pool();
-    let pool = pool.read().await;
-    let stats = pool.stats().await;
+    #[tokio::test]
+    async fn test_agent_await_reports_progress() {
+        let mut pool = AgentPool::new(1);
+        pool.set_agent_config(
+            "sh",
+            crate::pool::AgentConfig {
+                executable: "/bin/sh".into(),
+                args: vec!["-c".to_string(), "sleep 0.5".to_string()],
+                skip_permissions_flag: None,
+                prompt_template: None,
+                continue_flag: None,
+                limits: crate::pool::ResourceLimits::default(),
+            },
+        );
+        let spawned = pool.spawn(crate::pool::Task::new("wait").with_agent_type("sh")).await.unwrap();
 
-    json!({
-        "content": [{
-            "type": "text",
-            "text": format!(
-                "Agent Pool Statistics:\n\
-                 Max agents: {}\n\
-                 Total agents: {}\n\
-                 Running: {}\n\
-                 Completed: {}\n\
-                 Failed: {}",
-                stats.max_agents,
-                stats.total_agents,
-                stats.running,
-                stats.completed,
-                stats.failed
-            )
-        }],
-        "isError": false
-    })
-}
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let request = json!({ "params": { "_meta": { "progressToken": "tok-1" } } });
+        let progress = ProgressReporter::for_request(&request, &tx).unwrap();
+        let arguments = json!({ "agent_id": spawned.agent_id() });
+        let interval = std::time::Duration::from_millis(100);
+        agent_await_with_progress(&pool, Some(&arguments), &progress, interval).await.unwrap();
 
-async fn handle_agent_file_locks() -> Value {
-    let pool = get_pool();
-    let pool = pool.read().await;
-    let lock_manager = pool.lock_manager();
-    let locks = lock_manager.list_locks().await;
-
-    if locks.is_empty() {
-        return json!({
-            "content": [{
-                "type": "text",
-                "text": "No file locks currently held"
-            }],
-            "isError": false
-        });
+        let mut notifications = Vec::new();
+        while let Ok(notification) = rx.try_recv() {
+            notifications.push(notification);
+        }
+        assert!(notifications.len() >= 2);
+        assert_eq!(notifications[0]["method"], "notifications/progress");
+        assert_eq!(notifications[0]["params"]["progressToken"], "tok-1");
+        assert!(notifications[0]["params"]["message"].as_str().unwrap().contains("Running"));
+        // Progress always increases
+        assert_eq!(notifications[1]["params"]["progress"], 2);
+
+        // Requests without a token get no reporter
+        assert!(ProgressReporter::for_request(&json!({ "params": {} }), &tx).is_none());
     }
 
-    let mut output = format!("{} file lock(s):\n\n", locks.len());
-    for (path, info) in locks {
-        let lock_type = match info.lock_type {
-            crate::pool::LockType::Read => "read",
-            crate::pool::LockType::Write => "write",
-        };
-        output.push_str(&format!(
-            "- {} ({}) by {}\n",
-            path.display(),
-            lock_type,
-            info.agent_id
-        ));
+    #[test]
+    fn test_resources_list_uris() {
+        let list = handle_resources_list();
+        let uris: Vec<&str> = list["resources"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|r| r["uri"].as_str())
+            .collect();
+        assert_eq!(uris, ["lazarus://state", "lazarus://status", "lazarus://agents"]);
     }
 
-    json!({
-        "content": [{
-            "type": "text",
-            "text": output
-        }],
-        "isError": false
-    })
+    #[tokio::test]
+    async fn test_resources_read_unknown_uri() {
+        let params = json!({ "uri": "lazarus://nope" });
+        let err = handle_resources_read(Some(&params)).await.unwrap_err();
+        assert_eq!(err, "Resource not found: lazarus://nope");
+        assert!(handle_resources_read(None).await.is_err());
+    }
 }
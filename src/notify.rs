@@ -0,0 +1,345 @@
+//! Notifications
+//!
+//! Sends notifications for agent restarts, agent exits and background agent
+//! completions to a pluggable backend: desktop notifications (`notify-send`),
+//! a generic or Slack webhook (posted with `curl`), or nothing.
+//!
+//! The wrapper leaves its settings in a private file in the runtime directory
+//! so the MCP server (spawned by the agent) notifies through the same
+//! backend. They aren't exported to the agent's environment, since webhook
+//! URLs may carry credentials.
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::paths;
+
+/// Minimum time between two notifications of the same kind about the same subject
+const MIN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Kind of event being notified about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NotificationKind {
+    /// The supervised agent was restarted
+    Restart,
+    /// The supervised agent exited
+    Exit,
+    /// A background agent in the pool finished
+    Agent,
+}
+
+impl NotificationKind {
+    const ALL: [NotificationKind; 3] = [
+        NotificationKind::Restart,
+        NotificationKind::Exit,
+        NotificationKind::Agent,
+    ];
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "restart" => Some(NotificationKind::Restart),
+            "exit" => Some(NotificationKind::Exit),
+            "agent" => Some(NotificationKind::Agent),
+            _ => None,
+        }
+    }
+}
+
+/// A notification backend
+pub trait Notifier: Send + Sync {
+    /// Deliver a notification
+    fn send(&self, title: &str, body: &str) -> Result<()>;
+}
+
+/// Discards every notification
+pub struct NoopNotifier;
+
+impl Notifier for NoopNotifier {
+    fn send(&self, _title: &str, _body: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Desktop notifications via `notify-send`
+pub struct DesktopNotifier;
+
+impl Notifier for DesktopNotifier {
+    fn send(&self, title: &str, body: &str) -> Result<()> {
+        let status = Command::new("notify-send")
+            .args(["--app-name=lazarus-mcp", title, body])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?;
+        if !status.success() {
+            bail!("notify-send exited with {}", status);
+        }
+        Ok(())
+    }
+}
+
+/// JSON webhook posted with `curl`
+pub struct WebhookNotifier {
+    url: String,
+    /// Post Slack's `{"text": ...}` payload instead of `{"title", "body"}`
+    slack: bool,
+}
+
+impl WebhookNotifier {
+    fn payload(&self, title: &str, body: &str) -> serde_json::Value {
+        if self.slack {
+            serde_json::json!({ "text": format!("*{}*\n{}", title, body) })
+        } else {
+            serde_json::json!({ "title": title, "body": body })
+        }
+    }
+}
+
+/// Quote a string for a curl config file
+fn curl_quote(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+    format!("\"{}\"", escaped)
+}
+
+impl WebhookNotifier {
+    /// curl config carrying the URL and payload, so neither shows up in `ps`
+    fn curl_config(&self, title: &str, body: &str) -> String {
+        format!(
+            "url = {}\ndata = {}\n",
+            curl_quote(&self.url),
+            curl_quote(&self.payload(title, body).to_string())
+        )
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn send(&self, title: &str, body: &str) -> Result<()> {
+        let mut child = Command::new("curl")
+            .args(["-sS", "-m", "10", "-X", "POST", "-H", "Content-Type: application/json", "-K", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let written = child
+            .stdin
+            .take()
+            .map(|mut stdin| stdin.write_all(self.curl_config(title, body).as_bytes()));
+        let status = child.wait()?;
+        if let Some(Err(e)) = written {
+            bail!("failed to pass the request to curl: {}", e);
+        }
+        if !status.success() {
+            bail!("curl exited with {}", status);
+        }
+        Ok(())
+    }
+}
+
+/// Parse a backend spec: `none`, `desktop`, `webhook:<url>` or `slack:<url>`
+pub fn parse_backend(spec: &str) -> Result<Box<dyn Notifier>> {
+    match spec.split_once(':') {
+        _ if spec == "none" => Ok(Box::new(NoopNotifier)),
+        _ if spec == "desktop" => Ok(Box::new(DesktopNotifier)),
+        Some(("webhook", url)) if !url.is_empty() => Ok(Box::new(WebhookNotifier {
+            url: url.to_string(),
+            slack: false,
+        })),
+        Some(("slack", url)) if !url.is_empty() => Ok(Box::new(WebhookNotifier {
+            url: url.to_string(),
+            slack: true,
+        })),
+        _ => Err(anyhow!(
+            "invalid notification backend '{}' (expected none, desktop, webhook:<url> or slack:<url>)",
+            spec
+        )),
+    }
+}
+
+/// Parse a comma-separated list of event kinds (`restart`, `exit`, `agent`)
+pub fn parse_kinds(list: &str) -> Result<HashSet<NotificationKind>> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            NotificationKind::parse(name).ok_or_else(|| {
+                anyhow!("unknown notification event '{}' (expected restart, exit or agent)", name)
+            })
+        })
+        .collect()
+}
+
+/// Path of the file a wrapper leaves its notification settings in
+pub fn settings_path(wrapper_pid: u32) -> PathBuf {
+    paths::wrapper_dir(wrapper_pid).join(format!("lazarus-mcp-notify-{}.json", wrapper_pid))
+}
+
+/// Notification settings the wrapper shares with its MCP server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Settings {
+    /// Backend spec (see `parse_backend`)
+    pub backend: String,
+    /// Enabled event kinds (see `parse_kinds`), or all of them
+    pub events: Option<String>,
+}
+
+impl Settings {
+    /// Write the settings for this wrapper, readable only by the user
+    pub fn save(&self) -> Result<PathBuf> {
+        let path = paths::runtime_dir().join(format!("lazarus-mcp-notify-{}.json", std::process::id()));
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&path)?;
+        file.write_all(serde_json::to_string(self)?.as_bytes())?;
+        Ok(path)
+    }
+
+    /// Read the settings a wrapper left, if it has notifications enabled
+    pub fn load(wrapper_pid: u32) -> Option<Self> {
+        let content = fs::read_to_string(settings_path(wrapper_pid)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+}
+
+/// Sends enabled, rate-limited notifications to a backend
+pub struct Notifications {
+    backend: Arc<dyn Notifier>,
+    enabled: HashSet<NotificationKind>,
+    last_sent: Mutex<HashMap<(NotificationKind, String), Instant>>,
+}
+
+impl Notifications {
+    pub fn new(backend: Box<dyn Notifier>, enabled: HashSet<NotificationKind>) -> Self {
+        Self {
+            backend: Arc::from(backend),
+            enabled,
+            last_sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Build from a backend spec and an optional event list (default: all events)
+    pub fn from_spec(spec: &str, events: Option<&str>) -> Result<Self> {
+        let enabled = match events {
+            Some(list) => parse_kinds(list)?,
+            None => NotificationKind::ALL.into_iter().collect(),
+        };
+        Ok(Self::new(parse_backend(spec)?, enabled))
+    }
+
+    /// Build from the settings a wrapper left, if any
+    pub fn for_wrapper(wrapper_pid: u32) -> Option<Self> {
+        let settings = Settings::load(wrapper_pid)?;
+        match Self::from_spec(&settings.backend, settings.events.as_deref()) {
+            Ok(notifications) => Some(notifications),
+            Err(e) => {
+                warn!("Ignoring the wrapper's notification settings: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Whether a notification of this kind about `subject` would be sent now
+    fn should_send(&self, kind: NotificationKind, subject: &str) -> bool {
+        if !self.enabled.contains(&kind) {
+            return false;
+        }
+        let mut last_sent = self.last_sent.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let key = (kind, subject.to_string());
+        match last_sent.get(&key) {
+            Some(last) if now.duration_since(*last) < MIN_INTERVAL => false,
+            _ => {
+                last_sent.insert(key, now);
+                true
+            }
+        }
+    }
+
+    /// Send a notification unless its kind is disabled or was notified too recently
+    ///
+    /// The rate limit applies per `subject` (the agent the event is about),
+    /// so two background agents finishing close together are both notified.
+    /// Delivery happens on a background thread so a slow backend never
+    /// delays supervision; join the returned handle to wait for it.
+    pub fn notify(&self, kind: NotificationKind, subject: &str, title: &str, body: &str) -> Option<JoinHandle<()>> {
+        if !self.should_send(kind, subject) {
+            debug!("Skipping {:?} notification: {}", kind, title);
+            return None;
+        }
+        let backend = Arc::clone(&self.backend);
+        let (title, body) = (title.to_string(), body.to_string());
+        Some(std::thread::spawn(move || {
+            if let Err(e) = backend.send(&title, &body) {
+                warn!("Failed to send notification: {}", e);
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_backend() {
+        assert!(parse_backend("none").is_ok());
+        assert!(parse_backend("desktop").is_ok());
+        assert!(parse_backend("webhook:https://example.com/hook").is_ok());
+        assert!(parse_backend("slack:https://hooks.slack.com/x").is_ok());
+        assert!(parse_backend("slack:").is_err());
+        assert!(parse_backend("email").is_err());
+    }
+
+    #[test]
+    fn test_slack_payload() {
+        let slack = WebhookNotifier { url: String::new(), slack: true };
+        assert_eq!(slack.payload("Restarted", "why")["text"], "*Restarted*\nwhy");
+        let plain = WebhookNotifier { url: String::new(), slack: false };
+        assert_eq!(plain.payload("Restarted", "why")["body"], "why");
+    }
+
+    #[test]
+    fn test_curl_config() {
+        let webhook = WebhookNotifier { url: "https://example.com/hook?token=x".to_string(), slack: false };
+        let config = webhook.curl_config("Restarted", "said \"hi\"\nthen left");
+        assert_eq!(
+            config,
+            concat!(
+                "url = \"https://example.com/hook?token=x\"\n",
+                r#"data = "{\"body\":\"said \\\"hi\\\"\\nthen left\",\"title\":\"Restarted\"}""#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_disabled_and_rate_limited() {
+        let notifications = Notifications::new(Box::new(NoopNotifier), parse_kinds("restart, agent").unwrap());
+
+        assert!(!notifications.should_send(NotificationKind::Exit, "claude"));
+        assert!(notifications.should_send(NotificationKind::Restart, "claude"));
+        // A second restart right away is suppressed, other kinds aren't
+        assert!(!notifications.should_send(NotificationKind::Restart, "claude"));
+        assert!(notifications.should_send(NotificationKind::Agent, "agent-1"));
+        // Nor are events about another agent
+        assert!(notifications.should_send(NotificationKind::Agent, "agent-2"));
+        assert!(!notifications.should_send(NotificationKind::Agent, "agent-1"));
+
+        assert!(parse_kinds("restart,bogus").is_err());
+    }
+}
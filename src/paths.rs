@@ -0,0 +1,112 @@
+//! Runtime Paths
+//!
+//! The files other lazarus-mcp processes look for (the shared state and the
+//! restart signal) live in a directory only the user can access:
+//! `$XDG_RUNTIME_DIR/lazarus-mcp`, or `lazarus-mcp-<uid>` (mode 0700) in the
+//! temp directory when there is no usable runtime directory. Files left
+//! directly in /tmp by older versions are still found.
+
+use nix::unistd::geteuid;
+use std::fs;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+/// Where older versions kept their state and signal files
+pub const LEGACY_DIR: &str = "/tmp";
+
+/// Pick the runtime directory for `uid`
+///
+/// `$XDG_RUNTIME_DIR` is only used if it belongs to `uid`: after dropping
+/// privileges it may still point at the invoking user's directory.
+fn runtime_dir_for(xdg_runtime_dir: Option<&Path>, temp_dir: &Path, uid: u32) -> PathBuf {
+    match xdg_runtime_dir {
+        Some(dir)
+            if dir.is_absolute()
+                && fs::metadata(dir).is_ok_and(|meta| meta.is_dir() && meta.uid() == uid) =>
+        {
+            dir.join("lazarus-mcp")
+        }
+        _ => temp_dir.join(format!("lazarus-mcp-{}", uid)),
+    }
+}
+
+/// This user's runtime directory (which may not exist yet)
+pub fn runtime_dir() -> PathBuf {
+    let xdg_runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from);
+    runtime_dir_for(xdg_runtime_dir.as_deref(), &std::env::temp_dir(), geteuid().as_raw())
+}
+
+/// Create the runtime directory if needed, and make sure it is private
+///
+/// A directory that is owned by another user (or isn't a directory) is
+/// refused: in a shared temp directory it could have been planted to read or
+/// spoof our files.
+pub fn ensure_runtime_dir() -> io::Result<PathBuf> {
+    let dir = runtime_dir();
+    match fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+        _ => {}
+    }
+
+    let meta = fs::symlink_metadata(&dir)?;
+    if !meta.is_dir() || meta.uid() != geteuid().as_raw() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a directory owned by this user", dir.display()),
+        ));
+    }
+    if meta.mode() & 0o077 != 0 {
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(dir)
+}
+
+/// Directories to look for wrapper files in: the runtime directory, then the legacy one
+pub fn search_dirs() -> Vec<PathBuf> {
+    vec![runtime_dir(), PathBuf::from(LEGACY_DIR)]
+}
+
+/// File name of a wrapper's shared state
+pub fn state_file_name(wrapper_pid: u32) -> String {
+    format!("lazarus-mcp-state-{}", wrapper_pid)
+}
+
+/// File name of a wrapper's restart signal
+pub fn signal_file_name(wrapper_pid: u32) -> String {
+    format!("lazarus-mcp-{}", wrapper_pid)
+}
+
+/// Directory holding a wrapper's files: wherever its state file is
+///
+/// Falls back to the runtime directory if there is no state file.
+pub fn wrapper_dir(wrapper_pid: u32) -> PathBuf {
+    search_dirs()
+        .into_iter()
+        .find(|dir| dir.join(state_file_name(wrapper_pid)).exists())
+        .unwrap_or_else(runtime_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runtime_dir_for() {
+        let xdg = tempfile::tempdir().unwrap();
+        let temp = Path::new("/tmp");
+        let uid = geteuid().as_raw();
+
+        assert_eq!(runtime_dir_for(Some(xdg.path()), temp, uid), xdg.path().join("lazarus-mcp"));
+        // Someone else's runtime directory is not used
+        assert_eq!(
+            runtime_dir_for(Some(xdg.path()), temp, uid + 1),
+            temp.join(format!("lazarus-mcp-{}", uid + 1))
+        );
+        assert_eq!(runtime_dir_for(None, temp, uid), temp.join(format!("lazarus-mcp-{}", uid)));
+        assert_eq!(
+            runtime_dir_for(Some(Path::new("relative")), temp, uid),
+            temp.join(format!("lazarus-mcp-{}", uid))
+        );
+    }
+}
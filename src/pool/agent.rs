@@ -2,12 +2,14 @@
 //!
 //! Manages the lifecycle of individual background agents.
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use nix::sys::signal::{self, Signal};
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -15,7 +17,7 @@ use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 
 use super::locks::FileLockManager;
-use super::task::{Task, TaskResult};
+use super::task::{ResourceLimits, Task, TaskResult};
 
 /// Status of a running agent
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,6 +43,13 @@ pub enum AgentStatus {
     },
     /// Agent was stopped
     Stopped,
+    /// Agent process is frozen (SIGSTOP) until resumed
+    Paused,
+    /// Agent is waiting for a free slot in the pool
+    Queued {
+        /// Position in the queue (1 = next to start)
+        position: usize,
+    },
 }
 
 impl std::fmt::Display for AgentStatus {
@@ -53,6 +62,8 @@ impl std::fmt::Display for AgentStatus {
             AgentStatus::Completed { summary } => write!(f, "Completed: {}", summary),
             AgentStatus::Failed { error } => write!(f, "Failed: {}", error),
             AgentStatus::Stopped => write!(f, "Stopped"),
+            AgentStatus::Paused => write!(f, "Paused"),
+            AgentStatus::Queued { position } => write!(f, "Queued (position {})", position),
         }
     }
 }
@@ -66,8 +77,181 @@ pub struct AgentConfig {
     pub args: Vec<String>,
     /// Skip permissions flag (if supported)
     pub skip_permissions_flag: Option<String>,
+    /// Template wrapped around every task prompt; `{task}` is replaced by the description
+    pub prompt_template: Option<String>,
+    /// Flag that continues the agent's previous session (if supported)
+    pub continue_flag: Option<String>,
+    /// Default resource limits for agents of this type
+    pub limits: ResourceLimits,
+}
+
+impl AgentConfig {
+    /// Build the prompt for a task, applying the template if one is set
+    pub fn render_prompt(&self, task: &str) -> String {
+        match &self.prompt_template {
+            Some(template) if template.contains("{task}") => template.replace("{task}", task),
+            Some(template) => format!("{}\n\n{}", template, task),
+            None => task.to_string(),
+        }
+    }
+
+    /// Flag to resume the previous session for this run of a task, if any
+    ///
+    /// Only retries of tasks with `resume_on_retry` set are resumed, and only
+    /// for agents that support continuing a session.
+    pub fn resume_flag(&self, task: &Task) -> Option<&str> {
+        if task.is_retry() && task.resume_on_retry {
+            self.continue_flag.as_deref()
+        } else {
+            None
+        }
+    }
+}
+
+/// Have `cmd` set resource limits in the child before it execs
+///
+/// Limits above the current hard limit are lowered to it. A limit that
+/// can't be applied is skipped rather than failing the spawn.
+fn apply_limits(cmd: &mut Command, limits: ResourceLimits) {
+    use std::os::unix::process::CommandExt;
+
+    let mut rlimits = Vec::new();
+    if let Some(mb) = limits.memory_limit_mb {
+        rlimits.push((libc::RLIMIT_AS, mb.saturating_mul(1024 * 1024)));
+    }
+    if let Some(secs) = limits.cpu_time_limit_secs {
+        rlimits.push((libc::RLIMIT_CPU, secs));
+    }
+    // Resolve against the hard limits here, where logging is possible
+    let rlimits: Vec<_> = rlimits
+        .into_iter()
+        .filter_map(|(resource, value)| {
+            let mut current = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+            if unsafe { libc::getrlimit(resource, &mut current) } != 0 {
+                warn!("Can't read resource limit {}: {}", resource, std::io::Error::last_os_error());
+                return None;
+            }
+            let value = value.min(current.rlim_max);
+            Some((resource, libc::rlimit { rlim_cur: value, rlim_max: value }))
+        })
+        .collect();
+    if rlimits.is_empty() {
+        return;
+    }
+
+    // Only async-signal-safe calls between fork and exec; failures are ignored
+    unsafe {
+        cmd.pre_exec(move || {
+            for (resource, limit) in &rlimits {
+                libc::setrlimit(*resource, limit);
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Explain a failed agent's exit if it looks like a resource limit killed it
+///
+/// Only deaths by signal are considered: an agent that exits with a code
+/// handled its failure itself. Running out of address space usually shows up
+/// as a crash (SIGSEGV, or SIGABRT from a failed allocation).
+fn limit_breach_hint(status: std::process::ExitStatus, limits: ResourceLimits) -> Option<String> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let signal = status.signal()?;
+    if let Some(secs) = limits.cpu_time_limit_secs {
+        if matches!(signal, libc::SIGXCPU | libc::SIGKILL) {
+            return Some(format!("likely exceeded its CPU time limit of {}s", secs));
+        }
+    }
+    if let Some(mb) = limits.memory_limit_mb {
+        if matches!(signal, libc::SIGSEGV | libc::SIGABRT | libc::SIGKILL) {
+            return Some(format!("possibly exceeded its memory limit of {} MB", mb));
+        }
+    }
+    None
+}
+
+/// A chunk of captured agent output
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputChunk {
+    /// Output text read from the requested offset
+    pub output: String,
+    /// Offset to pass on the next read to continue where this one stopped
+    pub next_offset: u64,
+    /// Total size of the captured output so far
+    pub total_bytes: u64,
+}
+
+/// Path of the file capturing an agent's stdout and stderr
+///
+/// The file name ends with the pool owner's PID so stale files from dead
+/// sessions can be reclaimed by housekeeping.
+pub fn output_path(agent_id: &str) -> PathBuf {
+    crate::paths::runtime_dir().join(format!(
+        "lazarus-mcp-{}-{}.log",
+        agent_id,
+        std::process::id()
+    ))
 }
 
+/// Read captured output starting at a byte offset
+///
+/// At most `max_bytes` are returned. A UTF-8 sequence cut off at the end of
+/// the chunk is left for the next read rather than returned mangled.
+pub fn read_output(path: &Path, from_offset: u64, max_bytes: usize) -> std::io::Result<OutputChunk> {
+    let mut file = File::open(path)?;
+    let total_bytes = file.metadata()?.len();
+    let start = from_offset.min(total_bytes);
+
+    file.seek(SeekFrom::Start(start))?;
+    let mut buf = Vec::with_capacity(max_bytes.min((total_bytes - start) as usize));
+    file.take(max_bytes as u64).read_to_end(&mut buf)?;
+
+    let valid_len = match std::str::from_utf8(&buf) {
+        Ok(_) => buf.len(),
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(_) => buf.len(),
+    };
+    buf.truncate(valid_len);
+
+    Ok(OutputChunk {
+        output: String::from_utf8_lossy(&buf).into_owned(),
+        next_offset: start + valid_len as u64,
+        total_bytes,
+    })
+}
+
+/// How far back from the end of an output file `recent_lines` looks
+const RECENT_OUTPUT_WINDOW: u64 = 256 * 1024;
+
+/// Read the last `n` lines of captured output
+///
+/// Only the final 256 KiB of the file is scanned, so very long lines may
+/// yield fewer than `n` lines.
+pub fn recent_lines(path: &Path, n: usize) -> std::io::Result<Vec<String>> {
+    let mut file = File::open(path)?;
+    let total_bytes = file.metadata()?.len();
+    let start = total_bytes.saturating_sub(RECENT_OUTPUT_WINDOW);
+
+    file.seek(SeekFrom::Start(start))?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    let text = String::from_utf8_lossy(&buf);
+
+    let mut lines: Vec<&str> = text.lines().collect();
+    // The first line is probably cut off if we didn't start at the beginning
+    if start > 0 && !lines.is_empty() {
+        lines.remove(0);
+    }
+
+    let skip = lines.len().saturating_sub(n);
+    Ok(lines[skip..].iter().map(|l| l.to_string()).collect())
+}
+
+/// Called once with the agent ID and result when an agent finishes
+pub type CompletionCallback = Arc<dyn Fn(&str, &TaskResult) + Send + Sync>;
+
 /// Handle to a running background agent
 pub struct AgentHandle {
     /// Unique agent ID
@@ -82,6 +266,14 @@ pub struct AgentHandle {
     start_time: Instant,
     /// Reference to the file lock manager
     lock_manager: Arc<FileLockManager>,
+    /// Result of the task once the process has exited
+    result: Option<TaskResult>,
+    /// Called when the agent finishes
+    on_complete: Option<CompletionCallback>,
+    /// Status to go back to when a paused agent is resumed
+    paused_from: Option<AgentStatus>,
+    /// Resource limits the agent process was started with
+    limits: ResourceLimits,
 }
 
 impl AgentHandle {
@@ -94,9 +286,18 @@ impl AgentHandle {
             child: None,
             start_time: Instant::now(),
             lock_manager,
+            result: None,
+            on_complete: None,
+            paused_from: None,
+            limits: ResourceLimits::default(),
         }
     }
 
+    /// Set a callback to run when the agent finishes
+    pub fn set_on_complete(&mut self, callback: CompletionCallback) {
+        self.on_complete = Some(callback);
+    }
+
     /// Get the current status
     pub async fn status(&self) -> AgentStatus {
         self.status.read().await.clone()
@@ -126,6 +327,12 @@ impl AgentHandle {
         // Add any additional args
         cmd.args(&config.args);
 
+        // Pick up where the previous attempt left off
+        if let Some(flag) = config.resume_flag(&self.task) {
+            info!("Resuming previous session for agent {} (attempt {})", self.id, self.task.attempt + 1);
+            cmd.arg(flag);
+        }
+
         // Set working directory if specified
         if let Some(dir) = &self.task.working_directory {
             cmd.current_dir(dir);
@@ -133,11 +340,17 @@ impl AgentHandle {
 
         // Add the task as a prompt argument
         // For Claude, this would be passed via -p flag
-        cmd.arg("-p").arg(&self.task.description);
+        cmd.arg("-p").arg(config.render_prompt(&self.task.description));
 
-        // Capture stdout/stderr for monitoring
-        cmd.stdout(Stdio::piped());
-        cmd.stderr(Stdio::piped());
+        // Capture stdout/stderr to a file so output can be tailed, where only this user can read it
+        crate::paths::ensure_runtime_dir().context("Failed to set up the runtime directory")?;
+        let log_file = File::create(output_path(&self.id))
+            .context("Failed to create agent output file")?;
+        cmd.stdout(Stdio::from(log_file.try_clone()?));
+        cmd.stderr(Stdio::from(log_file));
+
+        self.limits = self.task.limits.or(config.limits);
+        apply_limits(&mut cmd, self.limits);
 
         let child = cmd.spawn().context("Failed to spawn agent process")?;
         self.child = Some(child);
@@ -150,6 +363,16 @@ impl AgentHandle {
         Ok(())
     }
 
+    /// Path of the file capturing this agent's output
+    pub fn output_path(&self) -> PathBuf {
+        output_path(&self.id)
+    }
+
+    /// Last `n` lines the agent printed to stdout or stderr
+    pub fn recent_output(&self, n: usize) -> Vec<String> {
+        recent_lines(&self.output_path(), n).unwrap_or_default()
+    }
+
     /// Check if the agent is still running
     pub fn is_running(&self) -> bool {
         self.child.is_some()
@@ -157,8 +380,22 @@ impl AgentHandle {
 
     /// Poll the agent for completion
     ///
-    /// Returns Some(result) if completed, None if still running
+    /// Returns Some(result) if completed, None if still running. Once the
+    /// agent has completed, every later poll returns the same result.
     pub async fn poll(&mut self) -> Option<TaskResult> {
+        if let Some(result) = &self.result {
+            return Some(result.clone());
+        }
+        let result = self.poll_child().await?;
+        if let Some(callback) = &self.on_complete {
+            callback(&self.id, &result);
+        }
+        self.result = Some(result.clone());
+        Some(result)
+    }
+
+    /// Check whether the child process has exited and build its result
+    async fn poll_child(&mut self) -> Option<TaskResult> {
         let child = self.child.as_mut()?;
 
         match child.try_wait() {
@@ -180,9 +417,13 @@ impl AgentHandle {
                     };
                     Some(result)
                 } else {
+                    let mut error = format!("Agent exited with code {}", code);
+                    if let Some(hint) = limit_breach_hint(status, self.limits) {
+                        error = format!("{} ({})", error, hint);
+                    }
                     let result = TaskResult::failure(
                         self.task.id.clone(),
-                        format!("Agent exited with code {}", code),
+                        error,
                         self.task.max_iterations,
                     );
                     *self.status.write().await = AgentStatus::Failed {
@@ -212,6 +453,49 @@ impl AgentHandle {
         }
     }
 
+    /// PID of the agent process, until it has been reaped
+    pub fn pid(&self) -> Option<u32> {
+        self.child.as_ref().map(|c| c.id())
+    }
+
+    /// PID of the agent process while it hasn't exited
+    async fn live_pid(&mut self) -> Result<Pid> {
+        if self.poll().await.is_some() {
+            return Err(anyhow!("Agent {} has already finished", self.id));
+        }
+        let child = self.child.as_ref().ok_or_else(|| anyhow!("Agent {} is not running", self.id))?;
+        Ok(Pid::from_raw(child.id() as i32))
+    }
+
+    /// Freeze the agent process (SIGSTOP) until `resume` is called
+    ///
+    /// A paused agent keeps its slot in the pool.
+    pub async fn pause(&mut self) -> Result<()> {
+        let pid = self.live_pid().await?;
+        if self.paused_from.is_some() {
+            return Err(anyhow!("Agent {} is already paused", self.id));
+        }
+        signal::kill(pid, Signal::SIGSTOP).with_context(|| format!("Failed to pause agent {}", self.id))?;
+
+        let mut status = self.status.write().await;
+        self.paused_from = Some(std::mem::replace(&mut *status, AgentStatus::Paused));
+        info!("Paused agent {}", self.id);
+        Ok(())
+    }
+
+    /// Continue a paused agent (SIGCONT)
+    pub async fn resume(&mut self) -> Result<()> {
+        let pid = self.live_pid().await?;
+        let Some(previous) = self.paused_from.take() else {
+            return Err(anyhow!("Agent {} is not paused", self.id));
+        };
+        signal::kill(pid, Signal::SIGCONT).with_context(|| format!("Failed to resume agent {}", self.id))?;
+
+        *self.status.write().await = previous;
+        info!("Resumed agent {}", self.id);
+        Ok(())
+    }
+
     /// Stop the agent gracefully
     pub async fn stop(&mut self) -> Result<()> {
         info!("Stopping agent {}", self.id);
@@ -219,8 +503,11 @@ impl AgentHandle {
         if let Some(child) = &self.child {
             let pid = Pid::from_raw(child.id() as i32);
 
-            // Try SIGINT first
+            // Try SIGINT first; a paused agent has to be continued to handle it
             let _ = signal::kill(pid, Signal::SIGINT);
+            if self.paused_from.take().is_some() {
+                let _ = signal::kill(pid, Signal::SIGCONT);
+            }
 
             // Wait with timeout escalation
             let start = Instant::now();
@@ -293,4 +580,165 @@ mod tests {
         assert_eq!(handle.id, "agent-1");
         matches!(handle.status().await, AgentStatus::Starting);
     }
+
+    #[test]
+    fn test_render_prompt() {
+        let mut config = AgentConfig {
+            executable: PathBuf::from("/bin/true"),
+            args: vec![],
+            skip_permissions_flag: None,
+            prompt_template: None,
+            continue_flag: None,
+            limits: ResourceLimits::default(),
+        };
+        assert_eq!(config.render_prompt("fix it"), "fix it");
+
+        config.prompt_template = Some("Follow CONVENTIONS.md.\n{task}\nSay DONE when finished.".to_string());
+        assert_eq!(
+            config.render_prompt("fix it"),
+            "Follow CONVENTIONS.md.\nfix it\nSay DONE when finished."
+        );
+
+        // A template without a placeholder is prepended
+        config.prompt_template = Some("Be brief.".to_string());
+        assert_eq!(config.render_prompt("fix it"), "Be brief.\n\nfix it");
+    }
+
+    #[test]
+    fn test_resume_flag_only_on_retry() {
+        let mut config = AgentConfig {
+            executable: PathBuf::from("/bin/true"),
+            args: vec![],
+            skip_permissions_flag: None,
+            prompt_template: None,
+            continue_flag: Some("--continue".to_string()),
+            limits: ResourceLimits::default(),
+        };
+        let mut task = Task::new("fix it");
+        assert_eq!(config.resume_flag(&task), None);
+
+        task.attempt = 1;
+        assert_eq!(config.resume_flag(&task), Some("--continue"));
+
+        task.resume_on_retry = false;
+        assert_eq!(config.resume_flag(&task), None);
+
+        // Agents without session support start fresh
+        task.resume_on_retry = true;
+        config.continue_flag = None;
+        assert_eq!(config.resume_flag(&task), None);
+    }
+
+    #[test]
+    fn test_limit_breach_hint() {
+        use std::os::unix::process::ExitStatusExt;
+        use std::process::ExitStatus;
+
+        let limits = ResourceLimits {
+            memory_limit_mb: Some(512),
+            cpu_time_limit_secs: Some(60),
+        };
+        let killed = ExitStatus::from_raw(libc::SIGXCPU);
+        assert_eq!(
+            limit_breach_hint(killed, limits).as_deref(),
+            Some("likely exceeded its CPU time limit of 60s")
+        );
+        let crashed = ExitStatus::from_raw(libc::SIGSEGV);
+        assert_eq!(
+            limit_breach_hint(crashed, limits).as_deref(),
+            Some("possibly exceeded its memory limit of 512 MB")
+        );
+        assert_eq!(limit_breach_hint(killed, ResourceLimits::default()), None);
+
+        // A SIGXCPU is only blamed on the CPU limit, and a crash on memory
+        let memory_only = ResourceLimits {
+            memory_limit_mb: Some(512),
+            cpu_time_limit_secs: None,
+        };
+        assert_eq!(limit_breach_hint(killed, memory_only), None);
+        assert_eq!(
+            limit_breach_hint(crashed, ResourceLimits { memory_limit_mb: None, ..limits }),
+            None
+        );
+
+        // A normal exit code is the agent's own failure
+        let failed = ExitStatus::from_raw(1 << 8);
+        assert_eq!(limit_breach_hint(failed, memory_only), None);
+        assert_eq!(limit_breach_hint(failed, limits), None);
+    }
+
+    #[tokio::test]
+    async fn test_start_applies_limits() {
+        let lock_manager = Arc::new(FileLockManager::new());
+        let config = AgentConfig {
+            executable: PathBuf::from("/bin/sh"),
+            args: vec!["-c".to_string(), "ulimit -v; ulimit -t".to_string()],
+            skip_permissions_flag: None,
+            prompt_template: None,
+            continue_flag: None,
+            limits: ResourceLimits {
+                memory_limit_mb: Some(1024),
+                cpu_time_limit_secs: Some(60),
+            },
+        };
+        // The task's own limit wins over the agent type's
+        let task = Task::new("limits").with_limits(ResourceLimits {
+            memory_limit_mb: None,
+            cpu_time_limit_secs: Some(30),
+        });
+        let mut handle = AgentHandle::new("agent-limits".to_string(), task, lock_manager);
+        handle.start(&config).await.unwrap();
+        while handle.poll().await.is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(handle.recent_output(2), ["1048576", "30"]);
+        let _ = std::fs::remove_file(handle.output_path());
+    }
+
+    #[test]
+    fn test_recent_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.log");
+        std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+
+        assert_eq!(recent_lines(&path, 2).unwrap(), ["two", "three"]);
+        assert_eq!(recent_lines(&path, 10).unwrap(), ["one", "two", "three"]);
+        assert!(recent_lines(&path, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_read_output_offsets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.log");
+        std::fs::write(&path, "hello world").unwrap();
+
+        let first = read_output(&path, 0, 5).unwrap();
+        assert_eq!(first.output, "hello");
+        assert_eq!(first.next_offset, 5);
+        assert_eq!(first.total_bytes, 11);
+
+        let rest = read_output(&path, first.next_offset, 1024).unwrap();
+        assert_eq!(rest.output, " world");
+        assert_eq!(rest.next_offset, 11);
+
+        let none = read_output(&path, rest.next_offset, 1024).unwrap();
+        assert!(none.output.is_empty());
+        assert_eq!(none.next_offset, 11);
+    }
+
+    #[test]
+    fn test_read_output_keeps_partial_utf8() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.log");
+        std::fs::write(&path, "aé").unwrap();
+
+        // Stop in the middle of the two-byte 'é'
+        let chunk = read_output(&path, 0, 2).unwrap();
+        assert_eq!(chunk.output, "a");
+        assert_eq!(chunk.next_offset, 1);
+
+        let chunk = read_output(&path, chunk.next_offset, 16).unwrap();
+        assert_eq!(chunk.output, "é");
+    }
 }
@@ -2,11 +2,16 @@
 //!
 //! Prevents concurrent file edits by multiple agents.
 //! Supports read/write lock types with agent-scoped locks.
+//! Agents blocked in `acquire_timeout` are tracked in a wait-for graph so
+//! deadlocks between agents can be detected.
+//! Acquisitions and blocked attempts are counted so contention between
+//! agents over the same files can be reported.
 
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Notify, RwLock};
 
 /// Type of lock held on a file
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -26,18 +31,99 @@ pub struct LockInfo {
     pub lock_type: LockType,
 }
 
+/// Lock contention counters
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LockStats {
+    /// Successful lock acquisitions
+    pub acquisitions: u64,
+    /// Attempts blocked by another agent's lock
+    pub blocked: u64,
+    /// Blocked attempts per file
+    pub contention: HashMap<PathBuf, u64>,
+}
+
+impl LockStats {
+    /// Files with blocked attempts, most contended first
+    pub fn most_contended(&self) -> Vec<(PathBuf, u64)> {
+        let mut files: Vec<(PathBuf, u64)> = self
+            .contention
+            .iter()
+            .map(|(path, count)| (path.clone(), *count))
+            .collect();
+        files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        files
+    }
+}
+
+/// Held locks, with the counters that are updated along with them
+#[derive(Debug, Default)]
+struct LockTable {
+    /// Map from file path to lock info
+    held: HashMap<PathBuf, LockInfo>,
+    stats: LockStats,
+}
+
 /// Manages file locks across all agents
 #[derive(Debug)]
 pub struct FileLockManager {
-    /// Map from file path to lock info
-    locks: Arc<RwLock<HashMap<PathBuf, LockInfo>>>,
+    locks: Arc<RwLock<LockTable>>,
+    /// Wakers for agents waiting on a path, present only while someone waits
+    waiters: Arc<Mutex<HashMap<PathBuf, Arc<Notify>>>>,
+    /// Wait-for graph: the path each blocked agent is waiting on
+    waiting: Arc<Mutex<HashMap<String, PathBuf>>>,
+}
+
+/// Registration of an agent waiting on a path; unregisters on drop
+struct Waiter<'a> {
+    manager: &'a FileLockManager,
+    agent_id: String,
+    path: PathBuf,
+    notify: Arc<Notify>,
+}
+
+impl<'a> Waiter<'a> {
+    fn register(manager: &'a FileLockManager, agent_id: &str, path: PathBuf) -> Self {
+        let notify = {
+            let mut map = manager.waiters.lock().unwrap_or_else(|e| e.into_inner());
+            Arc::clone(map.entry(path.clone()).or_default())
+        };
+        manager
+            .waiting
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(agent_id.to_string(), path.clone());
+        Self {
+            manager,
+            agent_id: agent_id.to_string(),
+            path,
+            notify,
+        }
+    }
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        let mut waiting = self.manager.waiting.lock().unwrap_or_else(|e| e.into_inner());
+        if waiting.get(&self.agent_id) == Some(&self.path) {
+            waiting.remove(&self.agent_id);
+        }
+        drop(waiting);
+
+        // Remove the entry once the last waiter on this path is gone
+        let mut map = self.manager.waiters.lock().unwrap_or_else(|e| e.into_inner());
+        if Arc::strong_count(&self.notify) == 2 {
+            map.remove(&self.path);
+        }
+    }
 }
 
 impl FileLockManager {
     /// Create a new file lock manager
     pub fn new() -> Self {
         Self {
-            locks: Arc::new(RwLock::new(HashMap::new())),
+            locks: Arc::new(RwLock::new(LockTable::default())),
+            waiters: Arc::new(Mutex::new(HashMap::new())),
+            waiting: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -51,15 +137,20 @@ impl FileLockManager {
         lock_type: LockType,
     ) -> bool {
         let path = path.as_ref().to_path_buf();
-        let mut locks = self.locks.write().await;
+        let mut table = self.locks.write().await;
+        let LockTable { held: locks, stats } = &mut *table;
 
         if let Some(existing) = locks.get(&path) {
             // Check if the existing lock blocks this request
             match (existing.lock_type, lock_type) {
                 // Multiple readers allowed
-                (LockType::Read, LockType::Read) => return true,
+                (LockType::Read, LockType::Read) => {
+                    stats.acquisitions += 1;
+                    return true;
+                }
                 // Same agent can upgrade/downgrade
                 _ if existing.agent_id == agent_id => {
+                    stats.acquisitions += 1;
                     locks.insert(
                         path,
                         LockInfo {
@@ -70,11 +161,16 @@ impl FileLockManager {
                     return true;
                 }
                 // Blocked by existing lock
-                _ => return false,
+                _ => {
+                    stats.blocked += 1;
+                    *stats.contention.entry(path).or_default() += 1;
+                    return false;
+                }
             }
         }
 
         // No existing lock, acquire it
+        stats.acquisitions += 1;
         locks.insert(
             path,
             LockInfo {
@@ -85,14 +181,93 @@ impl FileLockManager {
         true
     }
 
+    /// Acquire a lock on a file, waiting up to `timeout` for it to be released
+    ///
+    /// Returns true if the lock was acquired, false if the timeout elapsed.
+    pub async fn acquire_timeout(
+        &self,
+        path: impl AsRef<Path>,
+        agent_id: &str,
+        lock_type: LockType,
+        timeout: Duration,
+    ) -> bool {
+        let path = path.as_ref().to_path_buf();
+        let deadline = tokio::time::Instant::now() + timeout;
+        let waiter = Waiter::register(self, agent_id, path.clone());
+
+        loop {
+            // Register for wakeups before checking so a release in between isn't missed
+            let notified = waiter.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if self.try_acquire(&path, agent_id, lock_type).await {
+                return true;
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return false;
+            }
+        }
+    }
+
+    /// Path an agent is currently blocked waiting for, if any
+    pub fn waiting_for(&self, agent_id: &str) -> Option<PathBuf> {
+        let waiting = self.waiting.lock().unwrap_or_else(|e| e.into_inner());
+        waiting.get(agent_id).cloned()
+    }
+
+    /// Find a cycle of agents each waiting for a lock held by the next
+    ///
+    /// Returns the agents in wait order: each one waits on a file held by the
+    /// one after it, and the last waits on the first.
+    pub async fn detect_cycle(&self) -> Option<Vec<String>> {
+        let waiting = self.waiting.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let locks = &self.locks.read().await.held;
+
+        // Each blocked agent waits on one path, held by one agent
+        let waits_on = |agent: &str| -> Option<String> {
+            let path = waiting.get(agent)?;
+            locks
+                .get(path)
+                .map(|info| info.agent_id.clone())
+                .filter(|holder| holder != agent)
+        };
+
+        let mut starts: Vec<&String> = waiting.keys().collect();
+        starts.sort();
+        for start in starts {
+            let mut chain = vec![start.clone()];
+            while let Some(next) = chain.last().and_then(|agent| waits_on(agent)) {
+                if let Some(i) = chain.iter().position(|a| *a == next) {
+                    return Some(chain.split_off(i));
+                }
+                chain.push(next);
+            }
+        }
+        None
+    }
+
+    /// Wake everyone waiting on a path
+    ///
+    /// Only currently registered waiters are woken; no permit is stored, so a
+    /// waiter that gave up leaves nothing behind.
+    fn wake_waiters(&self, path: &Path) {
+        let waiters = self.waiters.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(notify) = waiters.get(path) {
+            notify.notify_waiters();
+        }
+    }
+
     /// Release a lock on a file
     pub async fn release(&self, path: impl AsRef<Path>, agent_id: &str) -> bool {
         let path = path.as_ref().to_path_buf();
-        let mut locks = self.locks.write().await;
+        let mut table = self.locks.write().await;
 
-        if let Some(info) = locks.get(&path) {
+        if let Some(info) = table.held.get(&path) {
             if info.agent_id == agent_id {
-                locks.remove(&path);
+                table.held.remove(&path);
+                drop(table);
+                self.wake_waiters(&path);
                 return true;
             }
         }
@@ -101,28 +276,51 @@ impl FileLockManager {
 
     /// Release all locks held by an agent
     pub async fn release_all(&self, agent_id: &str) {
-        let mut locks = self.locks.write().await;
-        locks.retain(|_, info| info.agent_id != agent_id);
+        let mut table = self.locks.write().await;
+        let mut released = Vec::new();
+        table.held.retain(|path, info| {
+            if info.agent_id == agent_id {
+                released.push(path.clone());
+                false
+            } else {
+                true
+            }
+        });
+        drop(table);
+
+        for path in released {
+            self.wake_waiters(&path);
+        }
     }
 
     /// List all currently held locks
     pub async fn list_locks(&self) -> Vec<(PathBuf, LockInfo)> {
-        let locks = self.locks.read().await;
+        let locks = &self.locks.read().await.held;
         locks
             .iter()
             .map(|(path, info)| (path.clone(), info.clone()))
             .collect()
     }
 
+    /// Lock contention counters, reset afterwards if `reset` is set
+    pub async fn lock_stats(&self, reset: bool) -> LockStats {
+        let mut table = self.locks.write().await;
+        if reset {
+            std::mem::take(&mut table.stats)
+        } else {
+            table.stats.clone()
+        }
+    }
+
     /// Get the lock info for a specific file
     pub async fn get_lock_info(&self, path: impl AsRef<Path>) -> Option<LockInfo> {
-        let locks = self.locks.read().await;
+        let locks = &self.locks.read().await.held;
         locks.get(path.as_ref()).cloned()
     }
 
     /// Check if an agent holds a lock on a file
     pub async fn is_locked_by(&self, path: impl AsRef<Path>, agent_id: &str) -> bool {
-        let locks = self.locks.read().await;
+        let locks = &self.locks.read().await.held;
         locks
             .get(path.as_ref())
             .map(|info| info.agent_id == agent_id)
@@ -131,7 +329,7 @@ impl FileLockManager {
 
     /// Get all locks held by a specific agent
     pub async fn locks_held_by(&self, agent_id: &str) -> Vec<(PathBuf, LockType)> {
-        let locks = self.locks.read().await;
+        let locks = &self.locks.read().await.held;
         locks
             .iter()
             .filter(|(_, info)| info.agent_id == agent_id)
@@ -202,6 +400,101 @@ mod tests {
         assert!(!manager.try_acquire("/tmp/c.txt", "agent-3", LockType::Write).await);
     }
 
+    #[tokio::test]
+    async fn test_acquire_timeout_waits_for_release() {
+        let manager = Arc::new(FileLockManager::new());
+        assert!(manager.try_acquire("/tmp/test.txt", "agent-1", LockType::Write).await);
+
+        let releaser = Arc::clone(&manager);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            releaser.release("/tmp/test.txt", "agent-1").await;
+        });
+
+        let acquired = manager
+            .acquire_timeout("/tmp/test.txt", "agent-2", LockType::Write, Duration::from_secs(2))
+            .await;
+        assert!(acquired);
+        assert!(manager.is_locked_by("/tmp/test.txt", "agent-2").await);
+        assert!(manager.waiters.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_acquire_timeout_gives_up() {
+        let manager = FileLockManager::new();
+        assert!(manager.try_acquire("/tmp/test.txt", "agent-1", LockType::Write).await);
+
+        let acquired = manager
+            .acquire_timeout("/tmp/test.txt", "agent-2", LockType::Read, Duration::from_millis(20))
+            .await;
+        assert!(!acquired);
+        // The timed-out waiter unregistered itself
+        assert!(manager.waiters.lock().unwrap().is_empty());
+
+        // A later release with nobody waiting doesn't leave a wakeup behind
+        manager.release_all("agent-1").await;
+        assert!(manager.waiters.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_detect_cycle() {
+        let manager = Arc::new(FileLockManager::new());
+        assert!(manager.try_acquire("/tmp/a.txt", "agent-1", LockType::Write).await);
+        assert!(manager.try_acquire("/tmp/b.txt", "agent-2", LockType::Write).await);
+        assert!(manager.detect_cycle().await.is_none());
+
+        let wait = |path: &'static str, agent: &'static str| {
+            let manager = Arc::clone(&manager);
+            tokio::spawn(async move {
+                manager
+                    .acquire_timeout(path, agent, LockType::Write, Duration::from_secs(5))
+                    .await
+            })
+        };
+
+        // A single waiter is not a deadlock
+        let first = wait("/tmp/b.txt", "agent-1");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(manager.detect_cycle().await.is_none());
+        assert_eq!(manager.waiting_for("agent-1"), Some(PathBuf::from("/tmp/b.txt")));
+
+        let second = wait("/tmp/a.txt", "agent-2");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(
+            manager.detect_cycle().await,
+            Some(vec!["agent-1".to_string(), "agent-2".to_string()])
+        );
+
+        // Releasing agent-1's locks breaks the cycle
+        first.abort();
+        manager.release_all("agent-1").await;
+        assert!(second.await.unwrap());
+        assert!(manager.detect_cycle().await.is_none());
+        assert!(manager.waiting_for("agent-1").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_lock_stats() {
+        let manager = FileLockManager::new();
+        assert!(manager.try_acquire("/tmp/a.txt", "agent-1", LockType::Write).await);
+        assert!(manager.try_acquire("/tmp/b.txt", "agent-1", LockType::Write).await);
+        assert!(!manager.try_acquire("/tmp/a.txt", "agent-2", LockType::Write).await);
+        assert!(!manager.try_acquire("/tmp/a.txt", "agent-3", LockType::Read).await);
+        assert!(!manager.try_acquire("/tmp/b.txt", "agent-2", LockType::Write).await);
+
+        let stats = manager.lock_stats(false).await;
+        assert_eq!(stats.acquisitions, 2);
+        assert_eq!(stats.blocked, 3);
+        assert_eq!(
+            stats.most_contended(),
+            vec![(PathBuf::from("/tmp/a.txt"), 2), (PathBuf::from("/tmp/b.txt"), 1)]
+        );
+
+        // Cumulative until reset
+        assert_eq!(manager.lock_stats(true).await, stats);
+        assert_eq!(manager.lock_stats(false).await, LockStats::default());
+    }
+
     #[tokio::test]
     async fn test_list_locks() {
         let manager = FileLockManager::new();
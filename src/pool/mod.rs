@@ -4,18 +4,34 @@
 
 mod agent;
 mod locks;
+mod registry;
 mod task;
 
-pub use agent::{AgentConfig, AgentHandle, AgentStatus};
+pub use agent::{
+    output_path, read_output, recent_lines, AgentConfig, AgentHandle, AgentStatus, CompletionCallback,
+};
 pub use locks::{FileLockManager, LockType};
-pub use task::{Task, TaskPriority, TaskResult};
+pub use registry::{continue_flag_for, resolve_command};
+pub use task::{ResourceLimits, Task, TaskPriority, TaskResult};
 
 use anyhow::{anyhow, Result};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
+
+/// Number of finished agents remembered, for their dependents and `AgentPool::retry`
+const RETAINED_TASKS: usize = 50;
+
+/// Separates a retried task's description from the previous attempt's error
+const RETRY_NOTE: &str = "\n\nNote: a previous attempt at this task failed: ";
+
+/// Whether an agent in this state may hold file locks (they are released when it finishes)
+fn holds_locks(status: &AgentStatus) -> bool {
+    matches!(status, AgentStatus::Starting | AgentStatus::Running { .. } | AgentStatus::Paused)
+}
 
 /// Statistics about the agent pool
 #[derive(Debug, Clone)]
@@ -26,10 +42,104 @@ pub struct PoolStats {
     pub total_agents: usize,
     /// Number of actively running agents
     pub running: usize,
+    /// Number of paused agents (still holding a slot)
+    pub paused: usize,
     /// Number of completed agents (waiting for cleanup)
     pub completed: usize,
     /// Number of failed agents
     pub failed: usize,
+    /// Number of tasks waiting for a free slot
+    pub queued: usize,
+}
+
+/// Availability and capabilities of an agent type
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentTypeInfo {
+    pub name: String,
+    /// Whether the agent's executable was found
+    pub available: bool,
+    /// Resolved path of the executable
+    pub path: Option<PathBuf>,
+    /// Whether retries can continue the previous session
+    pub supports_continue: bool,
+    /// Whether the agent can run without permission prompts
+    pub supports_skip_permissions: bool,
+}
+
+/// One agent in the pool snapshot read by the dashboard
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolAgentSnapshot {
+    pub id: String,
+    pub status: String,
+    pub task: String,
+    pub iterations: u32,
+    pub elapsed_secs: u64,
+}
+
+/// Path of the pool snapshot for the wrapper with the given PID
+pub fn snapshot_path(wrapper_pid: u32) -> PathBuf {
+    crate::paths::wrapper_dir(wrapper_pid).join(format!("lazarus-mcp-pool-{}.json", wrapper_pid))
+}
+
+/// Path of the file the dashboard appends agent stop requests to
+pub fn stop_request_path(wrapper_pid: u32) -> PathBuf {
+    crate::paths::wrapper_dir(wrapper_pid).join(format!("lazarus-mcp-pool-stop-{}.txt", wrapper_pid))
+}
+
+/// Ask the pool serving the wrapper with the given PID to stop an agent
+///
+/// The request is picked up on the pool's next refresh.
+pub fn request_stop(wrapper_pid: u32, agent_id: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(stop_request_path(wrapper_pid))?;
+    writeln!(file, "{}", agent_id)
+}
+
+/// What happened to a spawned task
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpawnOutcome {
+    /// The agent started immediately
+    Started(String),
+    /// The pool is full; the agent will start when a slot frees up
+    Queued {
+        agent_id: String,
+        /// Position in the queue (1 = next to start)
+        position: usize,
+    },
+}
+
+impl SpawnOutcome {
+    /// ID of the spawned agent, whether started or queued
+    pub fn agent_id(&self) -> &str {
+        match self {
+            SpawnOutcome::Started(id) => id,
+            SpawnOutcome::Queued { agent_id, .. } => agent_id,
+        }
+    }
+}
+
+/// Whether a task's dependencies allow it to start
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DependencyState {
+    /// All dependencies completed successfully
+    Met,
+    /// Some dependencies are still queued or running
+    Waiting,
+    /// A dependency failed, was stopped or cancelled
+    Failed(String),
+}
+
+/// An agent that has left the pool after finishing (or never started)
+struct FinishedTask {
+    agent_id: String,
+    task: Task,
+    /// Whether it completed successfully
+    success: bool,
+    /// Why the agent failed, if it did
+    error: Option<String>,
 }
 
 /// Manages a pool of background task agents
@@ -42,6 +152,20 @@ pub struct AgentPool {
     lock_manager: Arc<FileLockManager>,
     /// Agent configurations by type
     agent_configs: HashMap<String, AgentConfig>,
+    /// Tasks waiting for a free slot, highest priority first
+    pending: Arc<RwLock<VecDeque<(String, Task)>>>,
+    /// Queued tasks that never started (cancelled, or a dependency failed),
+    /// with their final status and result, until awaited
+    unstarted: Arc<RwLock<HashMap<String, (AgentStatus, TaskResult)>>>,
+    /// The most recently finished agents, oldest first: whether they
+    /// succeeded (for their dependents) and their tasks (so they can be retried)
+    finished_tasks: Arc<RwLock<VecDeque<FinishedTask>>>,
+    /// Where to write snapshots of the pool for the dashboard
+    snapshot_path: Option<PathBuf>,
+    /// Where the dashboard leaves requests to stop agents
+    stop_request_path: Option<PathBuf>,
+    /// Called whenever an agent finishes
+    on_complete: Option<CompletionCallback>,
 }
 
 impl AgentPool {
@@ -52,98 +176,213 @@ impl AgentPool {
             agents: Arc::new(RwLock::new(HashMap::new())),
             lock_manager: Arc::new(FileLockManager::new()),
             agent_configs: Self::default_agent_configs(),
+            pending: Arc::new(RwLock::new(VecDeque::new())),
+            unstarted: Arc::new(RwLock::new(HashMap::new())),
+            finished_tasks: Arc::new(RwLock::new(VecDeque::new())),
+            snapshot_path: None,
+            stop_request_path: None,
+            on_complete: None,
         }
     }
 
-    /// Get default agent configurations
+    /// Get default agent configurations for the registered agents found on this machine
     fn default_agent_configs() -> HashMap<String, AgentConfig> {
         let mut configs = HashMap::new();
 
-        // Try to find Claude
-        if let Some(path) = Self::find_agent_executable("claude") {
-            configs.insert(
-                "claude".to_string(),
-                AgentConfig {
-                    executable: path,
-                    args: vec![],
-                    skip_permissions_flag: Some("--dangerously-skip-permissions".to_string()),
-                },
-            );
+        for agent in registry::agents() {
+            if let Some(path) = agent.find_executable() {
+                configs.insert(agent.name.clone(), Self::agent_config(agent, path));
+            }
         }
 
-        // Try to find Aider
-        if let Some(path) = Self::find_agent_executable("aider") {
-            configs.insert(
-                "aider".to_string(),
-                AgentConfig {
-                    executable: path,
-                    args: vec![],
-                    skip_permissions_flag: Some("--yes".to_string()),
-                },
-            );
+        configs
+    }
+
+    /// Configuration for running a registered agent found at `executable`
+    fn agent_config(agent: &registry::AgentDefinition, executable: PathBuf) -> AgentConfig {
+        AgentConfig {
+            executable,
+            args: agent.args.clone(),
+            skip_permissions_flag: agent.skip_permissions_flag.clone(),
+            prompt_template: Self::prompt_template_from_env(&agent.name).or_else(|| agent.prompt_template.clone()),
+            continue_flag: agent.continue_flag.clone(),
+            limits: agent.limits,
         }
+    }
 
-        // Try to find Cursor
-        if let Some(path) = Self::find_agent_executable("cursor") {
-            configs.insert(
-                "cursor".to_string(),
-                AgentConfig {
-                    executable: path,
-                    args: vec![],
-                    skip_permissions_flag: None,
+    /// Describe every agent type: the registered ones (found or not) and any custom ones
+    pub fn agent_types(&self) -> Vec<AgentTypeInfo> {
+        let describe = |name: &str, config: &AgentConfig| AgentTypeInfo {
+            name: name.to_string(),
+            available: true,
+            path: Some(config.executable.clone()),
+            supports_continue: config.continue_flag.is_some(),
+            supports_skip_permissions: config.skip_permissions_flag.is_some(),
+        };
+
+        let registered = registry::agents();
+        let mut types: Vec<AgentTypeInfo> = registered
+            .iter()
+            .map(|agent| match self.agent_configs.get(&agent.name) {
+                Some(config) => describe(&agent.name, config),
+                None => AgentTypeInfo {
+                    name: agent.name.clone(),
+                    available: false,
+                    path: None,
+                    supports_continue: agent.continue_flag.is_some(),
+                    supports_skip_permissions: agent.skip_permissions_flag.is_some(),
                 },
-            );
+            })
+            .collect();
+
+        let mut custom: Vec<AgentTypeInfo> = self
+            .agent_configs
+            .iter()
+            .filter(|(name, _)| !registered.iter().any(|agent| &agent.name == *name))
+            .map(|(name, config)| describe(name, config))
+            .collect();
+        custom.sort_by(|a, b| a.name.cmp(&b.name));
+        types.extend(custom);
+
+        types
+    }
+
+    /// Read the prompt template for an agent type from the environment
+    ///
+    /// `LAZARUS_PROMPT_TEMPLATE_<TYPE>` (e.g. `LAZARUS_PROMPT_TEMPLATE_CLAUDE`)
+    /// takes precedence over the catch-all `LAZARUS_PROMPT_TEMPLATE`.
+    fn prompt_template_from_env(agent_type: &str) -> Option<String> {
+        let specific = format!("LAZARUS_PROMPT_TEMPLATE_{}", agent_type.to_uppercase());
+        std::env::var(specific)
+            .or_else(|_| std::env::var("LAZARUS_PROMPT_TEMPLATE"))
+            .ok()
+            .filter(|t| !t.is_empty())
+    }
+
+    /// Set the prompt template for an agent type
+    ///
+    /// Returns false if the agent type is not configured.
+    pub fn set_prompt_template(&mut self, agent_type: &str, template: Option<String>) -> bool {
+        match self.agent_configs.get_mut(agent_type) {
+            Some(config) => {
+                config.prompt_template = template;
+                true
+            }
+            None => false,
         }
+    }
 
-        configs
+    /// Write a JSON snapshot of the pool to this path whenever it changes
+    pub fn set_snapshot_path(&mut self, path: PathBuf) {
+        self.snapshot_path = Some(path);
+    }
+
+    /// Take stop requests (one agent ID per line) from this file on every refresh
+    pub fn set_stop_request_path(&mut self, path: PathBuf) {
+        self.stop_request_path = Some(path);
     }
 
-    /// Find an agent executable
-    fn find_agent_executable(name: &str) -> Option<PathBuf> {
-        // Try which first
-        if let Ok(path) = which::which(name) {
-            return Some(path);
+    /// Stop (or dequeue) the agents the dashboard asked to stop
+    async fn process_stop_requests(&self) {
+        let Some(path) = &self.stop_request_path else {
+            return;
+        };
+
+        // Move the file aside first so requests appended meanwhile aren't lost
+        let taken = path.with_extension("txt.processing");
+        if std::fs::rename(path, &taken).is_err() {
+            return;
         }
+        let requests = std::fs::read_to_string(&taken).unwrap_or_default();
+        let _ = std::fs::remove_file(&taken);
 
-        // Try common locations
-        let candidates = [
-            PathBuf::from(format!("/usr/local/bin/{}", name)),
-            PathBuf::from(format!("/usr/bin/{}", name)),
-        ];
+        for agent_id in requests.lines().map(str::trim).filter(|id| !id.is_empty()) {
+            if self.cancel_queued(agent_id).await {
+                info!("Cancelled queued agent {} on request from the dashboard", agent_id);
+            } else if let Err(e) = self.stop(agent_id).await {
+                warn!("Ignoring stop request from the dashboard: {}", e);
+            } else {
+                info!("Stopped agent {} on request from the dashboard", agent_id);
+            }
+        }
+    }
 
-        // Add home directory locations
-        let home_candidates = if let Some(home) = dirs::home_dir() {
-            vec![
-                home.join(format!(".local/bin/{}", name)),
-                home.join(format!(".local/share/{}/{}", name, name)),
-            ]
-        } else {
-            vec![]
+    /// Write the current pool state to the snapshot file, if one is configured
+    async fn save_snapshot(&self) {
+        let Some(path) = &self.snapshot_path else {
+            return;
         };
 
-        for candidate in candidates.iter().chain(home_candidates.iter()) {
-            if candidate.exists() && candidate.is_file() {
-                return Some(candidate.clone());
+        let mut entries = Vec::new();
+        {
+            let agents = self.agents.read().await;
+            for (id, handle) in agents.iter() {
+                let status = handle.status().await;
+                let iterations = match &status {
+                    AgentStatus::Running { iteration, .. } => *iteration,
+                    _ => 0,
+                };
+                entries.push(PoolAgentSnapshot {
+                    id: id.clone(),
+                    status: status.to_string(),
+                    task: handle.task().description.clone(),
+                    iterations,
+                    elapsed_secs: handle.elapsed().as_secs(),
+                });
             }
         }
+        for (i, (id, task)) in self.pending.read().await.iter().enumerate() {
+            entries.push(PoolAgentSnapshot {
+                id: id.clone(),
+                status: AgentStatus::Queued { position: i + 1 }.to_string(),
+                task: task.description.clone(),
+                iterations: 0,
+                elapsed_secs: 0,
+            });
+        }
+
+        // Write to a temp file and rename so readers never see a partial snapshot
+        let tmp_path = path.with_extension("json.tmp");
+        let result = serde_json::to_string_pretty(&entries)
+            .map_err(std::io::Error::other)
+            .and_then(|json| std::fs::write(&tmp_path, json))
+            .and_then(|()| std::fs::rename(&tmp_path, path));
+        if let Err(e) = result {
+            warn!("Failed to write pool snapshot {}: {}", path.display(), e);
+        }
+    }
 
-        None
+    /// Run a callback whenever an agent finishes
+    pub fn set_completion_callback(&mut self, callback: CompletionCallback) {
+        self.on_complete = Some(callback);
     }
 
-    /// Spawn a new background agent
-    ///
-    /// Returns the agent ID if successful.
-    pub async fn spawn(&self, task: Task) -> Result<String> {
-        let agents = self.agents.read().await;
-        if agents.len() >= self.max_agents {
-            return Err(anyhow!(
-                "Agent pool is full ({}/{})",
-                agents.len(),
-                self.max_agents
-            ));
+    /// Create a handle for a new agent
+    fn new_handle(&self, agent_id: String, task: Task) -> AgentHandle {
+        let mut handle = AgentHandle::new(agent_id, task, Arc::clone(&self.lock_manager));
+        if let Some(callback) = &self.on_complete {
+            handle.set_on_complete(Arc::clone(callback));
         }
-        drop(agents);
+        handle
+    }
+
+    /// Handle stop requests, notice finished agents and start queued tasks in the freed slots
+    pub async fn refresh(&self) {
+        self.process_stop_requests().await;
+        self.start_pending().await;
+    }
+
+    /// Set (or replace) the configuration for an agent type
+    pub fn set_agent_config(&mut self, agent_type: &str, config: AgentConfig) {
+        self.agent_configs.insert(agent_type.to_string(), config);
+    }
 
+    /// Spawn a new background agent
+    ///
+    /// If the pool is full the task is queued by priority (FIFO within the
+    /// same priority) and started when a slot frees up. A task with
+    /// dependencies is queued until they have all completed successfully.
+    pub async fn spawn(&self, task: Task) -> Result<SpawnOutcome> {
         // Get the agent config
         let config = self
             .agent_configs
@@ -152,21 +391,294 @@ impl AgentPool {
             .clone();
 
         let agent_id = format!("agent-{}", uuid::Uuid::new_v4());
-        let mut handle = AgentHandle::new(
-            agent_id.clone(),
-            task,
-            Arc::clone(&self.lock_manager),
-        );
+
+        let mut agents = self.agents.write().await;
+        for dependency in &task.depends_on {
+            if !self.is_known(&agents, dependency).await {
+                return Err(anyhow!("Unknown dependency: {}", dependency));
+            }
+        }
+        let dependencies = self.dependency_state(&mut agents, &task).await;
+        if let DependencyState::Failed(error) = dependencies {
+            return Err(anyhow!(error));
+        }
+
+        let running = Self::running_count(&mut agents).await;
+        if running >= self.max_agents || dependencies == DependencyState::Waiting {
+            let mut pending = self.pending.write().await;
+            let position = pending
+                .iter()
+                .position(|(_, queued)| queued.priority < task.priority)
+                .unwrap_or(pending.len());
+            if dependencies == DependencyState::Waiting {
+                info!("Queued agent {} until its dependencies complete", agent_id);
+            } else {
+                info!(
+                    "Agent pool is full ({}/{}), queued agent {} at position {}",
+                    running,
+                    self.max_agents,
+                    agent_id,
+                    position + 1
+                );
+            }
+            pending.insert(position, (agent_id.clone(), task));
+            drop(pending);
+            drop(agents);
+            self.save_snapshot().await;
+            return Ok(SpawnOutcome::Queued {
+                agent_id,
+                position: position + 1,
+            });
+        }
+
+        let mut handle = self.new_handle(agent_id.clone(), task);
 
         // Start the agent process
         handle.start(&config).await?;
 
         // Add to pool
-        let mut agents = self.agents.write().await;
         agents.insert(agent_id.clone(), handle);
+        drop(agents);
+        self.save_snapshot().await;
 
         info!("Spawned agent {}", agent_id);
-        Ok(agent_id)
+        Ok(SpawnOutcome::Started(agent_id))
+    }
+
+    /// Number of agents whose process is still running
+    ///
+    /// Polls every agent first so agents that have exited free their slot.
+    async fn running_count(agents: &mut HashMap<String, AgentHandle>) -> usize {
+        let mut running = 0;
+        for handle in agents.values_mut() {
+            if handle.poll().await.is_none() && handle.is_running() {
+                running += 1;
+            }
+        }
+        running
+    }
+
+    /// Whether an agent ID belongs to this pool (running, queued or finished)
+    async fn is_known(&self, agents: &HashMap<String, AgentHandle>, agent_id: &str) -> bool {
+        agents.contains_key(agent_id)
+            || self.queue_position(agent_id).await.is_some()
+            || self.finished_outcome(agent_id).await.is_some()
+            || self.unstarted.read().await.contains_key(agent_id)
+    }
+
+    /// Whether a task's dependencies have all completed successfully
+    async fn dependency_state(&self, agents: &mut HashMap<String, AgentHandle>, task: &Task) -> DependencyState {
+        let mut state = DependencyState::Met;
+        for dependency in &task.depends_on {
+            let succeeded = if let Some(handle) = agents.get_mut(dependency) {
+                handle.poll().await.map(|result| result.success)
+            } else if let Some(success) = self.finished_outcome(dependency).await {
+                Some(success)
+            } else if self.queue_position(dependency).await.is_some() {
+                None
+            } else {
+                // Never started, or no longer known
+                Some(false)
+            };
+
+            match succeeded {
+                Some(true) => {}
+                Some(false) => {
+                    return DependencyState::Failed(format!(
+                        "Dependency {} did not complete successfully",
+                        dependency
+                    ))
+                }
+                None => state = DependencyState::Waiting,
+            }
+        }
+        state
+    }
+
+    /// Record the final result of a queued task that will never start
+    async fn finish_unstarted(&self, agent_id: &str, task: Task, status: AgentStatus, error: String) {
+        let result = TaskResult::failure(task.id.clone(), error.clone(), 0);
+        self.record_finished(agent_id, task, false, Some(error)).await;
+        if let Some(callback) = &self.on_complete {
+            callback(agent_id, &result);
+        }
+        self.unstarted.write().await.insert(agent_id.to_string(), (status, result));
+    }
+
+    /// Remove a task from the queue
+    async fn take_pending(&self, agent_id: &str) -> Option<Task> {
+        let mut pending = self.pending.write().await;
+        let index = pending.iter().position(|(id, _)| id == agent_id)?;
+        pending.remove(index).map(|(_, task)| task)
+    }
+
+    /// Start queued tasks whose dependencies are met while there are free slots
+    ///
+    /// Tasks with a failed dependency are failed rather than left queued.
+    async fn start_pending(&self) {
+        let mut agents = self.agents.write().await;
+        let mut running = Self::running_count(&mut agents).await;
+
+        let queued: Vec<(String, Task)> = self.pending.read().await.iter().cloned().collect();
+        for (agent_id, task) in queued {
+            match self.dependency_state(&mut agents, &task).await {
+                DependencyState::Waiting => continue,
+                DependencyState::Failed(error) => {
+                    if let Some(task) = self.take_pending(&agent_id).await {
+                        warn!("Not starting queued agent {}: {}", agent_id, error);
+                        self.finish_unstarted(&agent_id, task, AgentStatus::Failed { error: error.clone() }, error)
+                            .await;
+                    }
+                    continue;
+                }
+                DependencyState::Met if running >= self.max_agents => continue,
+                DependencyState::Met => {}
+            }
+            let Some(task) = self.take_pending(&agent_id).await else {
+                continue;
+            };
+            let Some(config) = self.agent_configs.get(&task.agent_type) else {
+                let error = format!("Unknown agent type: {}", task.agent_type);
+                warn!("Not starting queued agent {}: {}", agent_id, error);
+                self.finish_unstarted(&agent_id, task, AgentStatus::Failed { error: error.clone() }, error)
+                    .await;
+                continue;
+            };
+
+            let mut handle = self.new_handle(agent_id.clone(), task);
+            match handle.start(config).await {
+                Ok(()) => {
+                    info!("Started queued agent {}", agent_id);
+                    agents.insert(agent_id, handle);
+                    running += 1;
+                }
+                Err(e) => {
+                    let error = format!("Failed to start: {:#}", e);
+                    warn!("Queued agent {}: {}", agent_id, error);
+                    let task = handle.task().clone();
+                    self.finish_unstarted(&agent_id, task, AgentStatus::Failed { error: error.clone() }, error)
+                        .await;
+                }
+            }
+        }
+        drop(agents);
+
+        self.save_snapshot().await;
+    }
+
+    /// Position of a queued agent (1 = next to start)
+    async fn queue_position(&self, agent_id: &str) -> Option<usize> {
+        self.pending
+            .read()
+            .await
+            .iter()
+            .position(|(id, _)| id == agent_id)
+            .map(|i| i + 1)
+    }
+
+    /// Remember a finished agent, forgetting the oldest beyond `RETAINED_TASKS`
+    async fn record_finished(&self, agent_id: &str, task: Task, success: bool, error: Option<String>) {
+        let mut finished = self.finished_tasks.write().await;
+        finished.push_back(FinishedTask {
+            agent_id: agent_id.to_string(),
+            task,
+            success,
+            error,
+        });
+        while finished.len() > RETAINED_TASKS {
+            finished.pop_front();
+        }
+    }
+
+    /// Whether a finished agent that has left the pool succeeded
+    async fn finished_outcome(&self, agent_id: &str) -> Option<bool> {
+        self.finished_tasks
+            .read()
+            .await
+            .iter()
+            .rev()
+            .find(|finished| finished.agent_id == agent_id)
+            .map(|finished| finished.success)
+    }
+
+    /// Remove a finished agent from the pool and remember it
+    ///
+    /// Takes the locked agent map, so the agent is recorded as finished
+    /// before anyone else can see it gone from the pool; otherwise its
+    /// dependents could be failed as depending on an unknown agent.
+    async fn remove_finished(
+        &self,
+        agents: &mut HashMap<String, AgentHandle>,
+        agent_id: &str,
+        success: bool,
+        error: Option<String>,
+    ) {
+        if let Some(handle) = agents.remove(agent_id) {
+            self.record_finished(agent_id, handle.task().clone(), success, error).await;
+        }
+    }
+
+    /// Run the task of a finished agent again, as a new agent
+    ///
+    /// The retry counts as the task's next attempt, so agents that support it
+    /// continue the previous session (see `Task::resume_on_retry`). With
+    /// `include_error`, the previous attempt's error is appended to the task
+    /// description. Returns the outcome and the task as spawned.
+    pub async fn retry(&self, agent_id: &str, include_error: bool) -> Result<(SpawnOutcome, Task)> {
+        {
+            // A finished agent still in the pool leaves it, as in `await_completion`
+            let mut agents = self.agents.write().await;
+            if let Some(handle) = agents.get_mut(agent_id) {
+                match handle.poll().await {
+                    Some(result) => {
+                        self.remove_finished(&mut agents, agent_id, result.success, result.error)
+                            .await
+                    }
+                    None => return Err(anyhow!("Agent {} is still running", agent_id)),
+                }
+            }
+        }
+        if self.queue_position(agent_id).await.is_some() {
+            return Err(anyhow!("Agent {} is still queued", agent_id));
+        }
+        let (mut task, error) = self
+            .finished_tasks
+            .read()
+            .await
+            .iter()
+            .rev()
+            .find(|finished| finished.agent_id == agent_id)
+            .map(|finished| (finished.task.clone(), finished.error.clone()))
+            .ok_or_else(|| anyhow!("No task is recorded for agent {}", agent_id))?;
+
+        task.attempt += 1;
+        // Retrying a retry replaces its note rather than adding another
+        if let Some((original, _)) = task.description.split_once(RETRY_NOTE) {
+            task.description = original.to_string();
+        }
+        if let Some(error) = error.filter(|_| include_error) {
+            task.description = format!("{}{}{}", task.description, RETRY_NOTE, error);
+        }
+        info!("Retrying the task of agent {} (attempt {})", agent_id, task.attempt + 1);
+        let outcome = self.spawn(task.clone()).await?;
+        Ok((outcome, task))
+    }
+
+    /// Cancel a queued task before it starts
+    ///
+    /// Returns false if the agent isn't queued (it may be running or unknown).
+    /// The cancellation is recorded as a failed result for `await_completion`.
+    pub async fn cancel_queued(&self, agent_id: &str) -> bool {
+        let Some(task) = self.take_pending(agent_id).await else {
+            return false;
+        };
+
+        info!("Cancelled queued agent {}", agent_id);
+        self.finish_unstarted(agent_id, task, AgentStatus::Stopped, "Cancelled before it started".to_string())
+            .await;
+        // Fails any tasks that depended on it
+        self.start_pending().await;
+        true
     }
 
     /// Get the status of an agent
@@ -174,11 +686,27 @@ impl AgentPool {
         let agents = self.agents.read().await;
         if let Some(handle) = agents.get(agent_id) {
             Some(handle.status().await)
+        } else if let Some(position) = self.queue_position(agent_id).await {
+            Some(AgentStatus::Queued { position })
+        } else if let Some((status, _)) = self.unstarted.read().await.get(agent_id) {
+            Some(status.clone())
         } else {
             None
         }
     }
 
+    /// Status of an agent in the pool, or of a finished one still remembered
+    pub async fn known_status(&self, agent_id: &str) -> Option<String> {
+        if let Some(status) = self.status(agent_id).await {
+            return Some(status.to_string());
+        }
+        // Finished agents are recorded before they leave the pool, so one
+        // that has just left is found here
+        self.finished_outcome(agent_id)
+            .await
+            .map(|success| if success { "Finished" } else { "Failed" }.to_string())
+    }
+
     /// List all agents with their status
     pub async fn list(&self) -> Vec<(String, AgentStatus)> {
         let agents = self.agents.read().await;
@@ -188,14 +716,50 @@ impl AgentPool {
             result.push((id.clone(), handle.status().await));
         }
 
+        for (i, (id, _)) in self.pending.read().await.iter().enumerate() {
+            result.push((id.clone(), AgentStatus::Queued { position: i + 1 }));
+        }
+
         result
     }
 
+    /// Freeze a running agent until it is resumed
+    pub async fn pause(&self, agent_id: &str) -> Result<()> {
+        let mut agents = self.agents.write().await;
+        let handle = agents.get_mut(agent_id).ok_or_else(|| anyhow!("Agent {} not found", agent_id))?;
+        handle.pause().await?;
+        drop(agents);
+        self.save_snapshot().await;
+        Ok(())
+    }
+
+    /// Continue a paused agent
+    pub async fn resume(&self, agent_id: &str) -> Result<()> {
+        let mut agents = self.agents.write().await;
+        let handle = agents.get_mut(agent_id).ok_or_else(|| anyhow!("Agent {} not found", agent_id))?;
+        handle.resume().await?;
+        drop(agents);
+        self.save_snapshot().await;
+        Ok(())
+    }
+
     /// Stop an agent
+    ///
+    /// Agents that already finished are left in the pool so their result can
+    /// still be awaited.
     pub async fn stop(&self, agent_id: &str) -> Result<()> {
         let mut agents = self.agents.write().await;
+        if let Some(handle) = agents.get_mut(agent_id) {
+            if handle.poll().await.is_some() {
+                return Err(anyhow!("Agent {} has already finished", agent_id));
+            }
+        }
         if let Some(mut handle) = agents.remove(agent_id) {
             handle.stop().await?;
+            let error = Some("Stopped before it finished".to_string());
+            self.record_finished(agent_id, handle.task().clone(), false, error).await;
+            drop(agents);
+            self.start_pending().await;
             Ok(())
         } else {
             Err(anyhow!("Agent {} not found", agent_id))
@@ -211,9 +775,18 @@ impl AgentPool {
                 if let Some(handle) = agents.get_mut(agent_id) {
                     if let Some(result) = handle.poll().await {
                         // Agent completed, remove from pool
-                        agents.remove(agent_id);
+                        let error = result.error.clone();
+                        self.remove_finished(&mut agents, agent_id, result.success, error).await;
+                        drop(agents);
+                        self.start_pending().await;
                         return Ok(result);
                     }
+                } else if self.queue_position(agent_id).await.is_some() {
+                    // Still queued; start it if a slot has freed up
+                    drop(agents);
+                    self.start_pending().await;
+                } else if let Some((_, result)) = self.unstarted.write().await.remove(agent_id) {
+                    return Ok(result);
                 } else {
                     return Err(anyhow!("Agent {} not found", agent_id));
                 }
@@ -239,15 +812,17 @@ impl AgentPool {
     pub async fn stats(&self) -> PoolStats {
         let agents = self.agents.read().await;
         let mut running = 0;
+        let mut paused = 0;
         let mut completed = 0;
         let mut failed = 0;
 
         for (_, handle) in agents.iter() {
             match handle.status().await {
                 AgentStatus::Running { .. } | AgentStatus::Starting => running += 1,
+                AgentStatus::Paused => paused += 1,
                 AgentStatus::Completed { .. } => completed += 1,
                 AgentStatus::Failed { .. } => failed += 1,
-                AgentStatus::Stopped => {}
+                AgentStatus::Stopped | AgentStatus::Queued { .. } => {}
             }
         }
 
@@ -255,8 +830,10 @@ impl AgentPool {
             max_agents: self.max_agents,
             total_agents: agents.len(),
             running,
+            paused,
             completed,
             failed,
+            queued: self.pending.read().await.len(),
         }
     }
 
@@ -265,10 +842,54 @@ impl AgentPool {
         Arc::clone(&self.lock_manager)
     }
 
+    /// Take a file lock for a running agent
+    ///
+    /// Returns whether the lock was taken right away. If it is held and
+    /// `timeout` isn't zero, the agent waits for it in the background (where
+    /// `agent_deadlocks` can see the wait); a lock only taken once the agent
+    /// has finished is dropped again, since its locks were already released.
+    pub async fn lock_file(
+        &self,
+        agent_id: &str,
+        path: impl AsRef<std::path::Path>,
+        lock_type: LockType,
+        timeout: std::time::Duration,
+    ) -> Result<bool> {
+        match self.status(agent_id).await {
+            Some(status) if holds_locks(&status) => {}
+            Some(status) => return Err(anyhow!("Agent {} is not running ({})", agent_id, status)),
+            None => return Err(anyhow!("Agent {} not found", agent_id)),
+        }
+        let path = path.as_ref().to_path_buf();
+        if self.lock_manager.try_acquire(&path, agent_id, lock_type).await {
+            return Ok(true);
+        }
+        if timeout.is_zero() {
+            return Ok(false);
+        }
+
+        let lock_manager = Arc::clone(&self.lock_manager);
+        let agents = Arc::clone(&self.agents);
+        let agent_id = agent_id.to_string();
+        tokio::spawn(async move {
+            if !lock_manager.acquire_timeout(&path, &agent_id, lock_type, timeout).await {
+                debug!("Agent {} gave up waiting for {}", agent_id, path.display());
+                return;
+            }
+            let running = match agents.read().await.get(&agent_id) {
+                Some(handle) => holds_locks(&handle.status().await),
+                None => false,
+            };
+            if !running {
+                lock_manager.release(&path, &agent_id).await;
+            }
+        });
+        Ok(false)
+    }
+
     /// Cleanup completed agents
     pub async fn cleanup_completed(&self) -> Vec<(String, TaskResult)> {
         let mut completed = Vec::new();
-        let mut to_remove = Vec::new();
 
         // First identify completed agents
         {
@@ -276,27 +897,86 @@ impl AgentPool {
             for (id, handle) in agents.iter_mut() {
                 if let Some(result) = handle.poll().await {
                     completed.push((id.clone(), result));
-                    to_remove.push(id.clone());
                 }
             }
 
             // Remove them
-            for id in to_remove {
-                agents.remove(&id);
+            for (id, result) in &completed {
+                self.remove_finished(&mut agents, id, result.success, result.error.clone()).await;
             }
         }
 
+        self.start_pending().await;
+
         completed
     }
 
     /// Shutdown the pool, stopping all agents
     pub async fn shutdown(&self) {
         info!("Shutting down agent pool");
+        self.pending.write().await.clear();
+        self.unstarted.write().await.clear();
+        self.finished_tasks.write().await.clear();
         let mut agents = self.agents.write().await;
         for (id, mut handle) in agents.drain() {
             debug!("Stopping agent {}", id);
             let _ = handle.stop().await;
         }
+        Self::remove_output_files();
+        if let Some(path) = &self.snapshot_path {
+            let _ = std::fs::remove_file(path);
+        }
+        if let Some(path) = &self.stop_request_path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    /// Shutdown the pool, giving running agents up to `timeout` to finish first
+    ///
+    /// Returns the results of agents that finished (including any that had
+    /// already finished but weren't awaited). Stragglers are stopped and their
+    /// locks released; queued tasks are dropped.
+    pub async fn shutdown_graceful(&self, timeout: std::time::Duration) -> Vec<(String, TaskResult)> {
+        info!("Draining agent pool (up to {:?})", timeout);
+        self.pending.write().await.clear();
+
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let running = Self::running_count(&mut *self.agents.write().await).await;
+            if running == 0 || tokio::time::Instant::now() >= deadline {
+                break;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        }
+
+        let mut finished = Vec::new();
+        {
+            let mut agents = self.agents.write().await;
+            for (id, handle) in agents.iter_mut() {
+                if let Some(result) = handle.poll().await {
+                    finished.push((id.clone(), result));
+                }
+            }
+            for (id, _) in &finished {
+                agents.remove(id);
+            }
+        }
+
+        self.shutdown().await;
+        finished
+    }
+
+    /// Remove the output files of every agent spawned by this process
+    fn remove_output_files() {
+        let suffix = format!("-{}.log", std::process::id());
+        if let Ok(entries) = std::fs::read_dir(crate::paths::runtime_dir()) {
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if name.starts_with("lazarus-mcp-agent-") && name.ends_with(&suffix) {
+                    let _ = std::fs::remove_file(entry.path());
+                }
+            }
+        }
     }
 
     /// Check if an agent is running
@@ -341,6 +1021,452 @@ mod tests {
         assert_eq!(stats.running, 0);
     }
 
+    /// Pool whose "sh" agents run a shell script
+    fn shell_pool(max_agents: usize, script: &str) -> AgentPool {
+        let mut pool = AgentPool::new(max_agents);
+        pool.set_agent_config(
+            "sh",
+            AgentConfig {
+                executable: PathBuf::from("/bin/sh"),
+                args: vec!["-c".to_string(), script.to_string()],
+                skip_permissions_flag: None,
+                prompt_template: None,
+                continue_flag: None,
+                limits: ResourceLimits::default(),
+            },
+        );
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_full_pool_queues_by_priority() {
+        let pool = shell_pool(1, "sleep 0.3");
+        let task = |priority| Task::new("work").with_agent_type("sh").with_priority(priority);
+
+        let first = pool.spawn(task(TaskPriority::Normal)).await.unwrap();
+        assert!(matches!(first, SpawnOutcome::Started(_)));
+
+        let low = pool.spawn(task(TaskPriority::Low)).await.unwrap();
+        let normal = pool.spawn(task(TaskPriority::Normal)).await.unwrap();
+        let urgent = pool.spawn(task(TaskPriority::Urgent)).await.unwrap();
+        assert!(matches!(low, SpawnOutcome::Queued { position: 1, .. }));
+        assert!(matches!(normal, SpawnOutcome::Queued { position: 1, .. }));
+        assert!(matches!(urgent, SpawnOutcome::Queued { position: 1, .. }));
+        assert_eq!(pool.stats().await.queued, 3);
+
+        // Urgent first, then normal, then low
+        assert!(matches!(
+            pool.status(low.agent_id()).await,
+            Some(AgentStatus::Queued { position: 3 })
+        ));
+
+        pool.await_completion(first.agent_id()).await.unwrap();
+        assert!(matches!(
+            pool.status(urgent.agent_id()).await,
+            Some(AgentStatus::Running { .. })
+        ));
+        assert!(matches!(
+            pool.status(normal.agent_id()).await,
+            Some(AgentStatus::Queued { position: 1 })
+        ));
+        assert_eq!(pool.stats().await.queued, 2);
+
+        // Awaiting a queued agent waits for it to start and finish
+        let result = pool.await_completion(low.agent_id()).await.unwrap();
+        assert!(result.success);
+        assert_eq!(pool.stats().await.queued, 0);
+
+        pool.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_cancel_queued() {
+        let pool = shell_pool(1, "exec sleep 5");
+        let running = pool.spawn(Task::new("first").with_agent_type("sh")).await.unwrap();
+        let queued = pool.spawn(Task::new("second").with_agent_type("sh")).await.unwrap();
+
+        // Only queued agents can be cancelled this way
+        assert!(!pool.cancel_queued(running.agent_id()).await);
+        assert!(!pool.cancel_queued("no-such-agent").await);
+
+        assert!(pool.cancel_queued(queued.agent_id()).await);
+        assert_eq!(pool.stats().await.queued, 0);
+        assert!(matches!(pool.status(queued.agent_id()).await, Some(AgentStatus::Stopped)));
+
+        let result = pool.await_completion(queued.agent_id()).await.unwrap();
+        assert!(!result.success);
+        assert_eq!(result.error.as_deref(), Some("Cancelled before it started"));
+        // The result is handed out once
+        assert!(pool.status(queued.agent_id()).await.is_none());
+
+        pool.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_dependencies_chain_agents() {
+        let pool = shell_pool(3, r#"case "$1" in fail) exit 1;; slow) exec sleep 5;; esac; sleep 0.2"#);
+
+        // A dependent waits in the queue even with free slots
+        let build = pool.spawn(Task::new("build").with_agent_type("sh")).await.unwrap();
+        let test = Task::new("test").with_agent_type("sh").with_dependencies([build.agent_id()]);
+        let test = pool.spawn(test).await.unwrap();
+        assert!(matches!(test, SpawnOutcome::Queued { .. }));
+        assert!(pool.await_completion(test.agent_id()).await.unwrap().success);
+
+        // A failed dependency fails its dependents without starting them
+        let fail = pool.spawn(Task::new("fail").with_agent_type("sh")).await.unwrap();
+        let after = Task::new("after").with_agent_type("sh").with_dependencies([fail.agent_id()]);
+        let after = pool.spawn(after).await.unwrap();
+        let result = pool.await_completion(after.agent_id()).await.unwrap();
+        assert!(!result.success);
+        assert_eq!(
+            result.error,
+            Some(format!("Dependency {} did not complete successfully", fail.agent_id()))
+        );
+
+        // Cancelling a queued task fails the chain behind it
+        let slow = pool.spawn(Task::new("slow").with_agent_type("sh")).await.unwrap();
+        let middle = Task::new("middle").with_agent_type("sh").with_dependencies([slow.agent_id()]);
+        let middle = pool.spawn(middle).await.unwrap();
+        let last = Task::new("last").with_agent_type("sh").with_dependencies([middle.agent_id()]);
+        let last = pool.spawn(last).await.unwrap();
+        assert!(pool.cancel_queued(middle.agent_id()).await);
+        assert!(matches!(pool.status(last.agent_id()).await, Some(AgentStatus::Failed { .. })));
+        assert_eq!(pool.stats().await.queued, 0);
+
+        let unknown = Task::new("orphan").with_agent_type("sh").with_dependencies(["no-such-agent"]);
+        assert!(pool.spawn(unknown).await.is_err());
+
+        pool.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_queued_task_that_fails_to_start() {
+        let mut pool = shell_pool(1, "sleep 0.2");
+        pool.set_agent_config(
+            "missing",
+            AgentConfig {
+                executable: PathBuf::from("/nonexistent/agent"),
+                args: Vec::new(),
+                skip_permissions_flag: None,
+                prompt_template: None,
+                continue_flag: None,
+                limits: ResourceLimits::default(),
+            },
+        );
+
+        pool.spawn(Task::new("first").with_agent_type("sh")).await.unwrap();
+        let broken = pool.spawn(Task::new("broken").with_agent_type("missing")).await.unwrap();
+        assert!(matches!(broken, SpawnOutcome::Queued { .. }));
+        let dependent = Task::new("after").with_agent_type("sh").with_dependencies([broken.agent_id()]);
+        let dependent = pool.spawn(dependent).await.unwrap();
+
+        // Once a slot frees up, the failed start is recorded like any other failure
+        for _ in 0..100 {
+            pool.refresh().await;
+            if matches!(pool.status(broken.agent_id()).await, Some(AgentStatus::Failed { .. })) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(matches!(pool.status(broken.agent_id()).await, Some(AgentStatus::Failed { .. })));
+        let result = pool.await_completion(broken.agent_id()).await.unwrap();
+        assert!(!result.success);
+        assert!(result.error.unwrap().starts_with("Failed to start"));
+        assert!(!pool.await_completion(dependent.agent_id()).await.unwrap().success);
+
+        pool.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_retry_finished_task() {
+        let pool = shell_pool(2, r#"case "$1" in *failed*) exit 0;; slow) exec sleep 5;; esac; echo broken >&2; exit 1"#);
+        let failed = pool.spawn(Task::new("flaky").with_agent_type("sh")).await.unwrap();
+        let first = pool.await_completion(failed.agent_id()).await.unwrap();
+        assert!(!first.success);
+
+        // The retry is the next attempt, told why the previous one failed
+        let (retry, task) = pool.retry(failed.agent_id(), true).await.unwrap();
+        assert_ne!(retry.agent_id(), failed.agent_id());
+        assert_eq!(task.attempt, 1);
+        assert!(task.description.starts_with("flaky\n\nNote: a previous attempt at this task failed:"));
+        assert!(pool.await_completion(retry.agent_id()).await.unwrap().success);
+
+        let slow = pool.spawn(Task::new("slow").with_agent_type("sh")).await.unwrap();
+        assert!(pool.retry(slow.agent_id(), true).await.is_err());
+        assert!(pool.retry("no-such-agent", true).await.is_err());
+
+        pool.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_retry_of_retry() {
+        let pool = shell_pool(1, "echo broken >&2; exit 1");
+        let failed = pool.spawn(Task::new("flaky").with_agent_type("sh")).await.unwrap();
+        for _ in 0..500 {
+            pool.refresh().await;
+            if matches!(pool.status(failed.agent_id()).await, Some(AgentStatus::Failed { .. })) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        // The finished agent leaves the pool instead of holding a slot
+        assert!(pool.agents.read().await.contains_key(failed.agent_id()));
+        let (retry, _) = pool.retry(failed.agent_id(), true).await.unwrap();
+        assert!(!pool.agents.read().await.contains_key(failed.agent_id()));
+        assert!(!pool.await_completion(retry.agent_id()).await.unwrap().success);
+
+        // Each retry carries only the latest error
+        let (_, task) = pool.retry(retry.agent_id(), true).await.unwrap();
+        assert_eq!(task.attempt, 2);
+        assert_eq!(task.description.matches("Note:").count(), 1);
+        assert!(task.description.starts_with("flaky\n\nNote:"));
+
+        let (_, task) = pool.retry(retry.agent_id(), false).await.unwrap();
+        assert_eq!(task.description, "flaky");
+
+        pool.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_finished_agents_are_capped() {
+        let pool = AgentPool::new(1);
+        pool.record_finished("agent-first", Task::new("first"), true, None).await;
+        assert_eq!(pool.finished_outcome("agent-first").await, Some(true));
+        assert_eq!(pool.known_status("agent-first").await.as_deref(), Some("Finished"));
+
+        for i in 0..RETAINED_TASKS {
+            let error = Some("failed".to_string());
+            pool.record_finished(&format!("agent-{}", i), Task::new("later"), false, error).await;
+        }
+        assert_eq!(pool.finished_tasks.read().await.len(), RETAINED_TASKS);
+        assert_eq!(pool.finished_outcome("agent-first").await, None);
+        assert_eq!(pool.known_status("agent-first").await, None);
+        assert_eq!(pool.finished_outcome("agent-0").await, Some(false));
+    }
+
+    /// Wait up to 500ms for a process to become stopped (or continued)
+    async fn wait_until_stopped(pid: u32, stopped: bool) -> bool {
+        for _ in 0..50 {
+            let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+            let state = stat.rsplit_once(')').and_then(|(_, rest)| rest.split_whitespace().next());
+            if (state == Some("T")) == stopped {
+                return true;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume() {
+        let pool = shell_pool(2, r#"case "$1" in quick) exit 0;; esac; exec sleep 5"#);
+        let agent = pool.spawn(Task::new("slow").with_agent_type("sh")).await.unwrap();
+        let id = agent.agent_id();
+        let pid = pool.agents.read().await[id].pid().unwrap();
+
+        pool.pause(id).await.unwrap();
+        assert!(matches!(pool.status(id).await, Some(AgentStatus::Paused)));
+        assert!(wait_until_stopped(pid, true).await);
+        assert!(pool.pause(id).await.is_err());
+        let stats = pool.stats().await;
+        assert_eq!((stats.running, stats.paused), (0, 1));
+
+        // A stopped (but not exited) child isn't mistaken for a finished one
+        assert!(pool.await_completion_timeout(id, std::time::Duration::from_millis(300)).await.is_err());
+
+        pool.resume(id).await.unwrap();
+        assert!(matches!(pool.status(id).await, Some(AgentStatus::Running { .. })));
+        assert!(wait_until_stopped(pid, false).await);
+        assert!(pool.resume(id).await.is_err());
+
+        // Paused agents can still be stopped
+        pool.pause(id).await.unwrap();
+        pool.stop(id).await.unwrap();
+
+        // Finished agents can't be paused or stopped
+        let quick = pool.spawn(Task::new("quick").with_agent_type("sh")).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        assert!(pool.pause(quick.agent_id()).await.unwrap_err().to_string().contains("already finished"));
+        assert!(pool.stop(quick.agent_id()).await.unwrap_err().to_string().contains("already finished"));
+        assert!(pool.await_completion(quick.agent_id()).await.unwrap().success);
+
+        pool.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_written_on_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pool.json");
+        let mut pool = shell_pool(1, "exec sleep 5");
+        pool.set_snapshot_path(path.clone());
+
+        let read = || -> Vec<PoolAgentSnapshot> {
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap()
+        };
+
+        let running = pool.spawn(Task::new("first").with_agent_type("sh")).await.unwrap();
+        pool.spawn(Task::new("second").with_agent_type("sh")).await.unwrap();
+        let snapshot = read();
+        assert_eq!(snapshot.len(), 2);
+        assert!(snapshot.iter().any(|a| a.task == "first" && a.status.starts_with("Running")));
+        assert!(snapshot.iter().any(|a| a.task == "second" && a.status == "Queued (position 1)"));
+
+        // Stopping the running agent starts the queued one
+        pool.stop(running.agent_id()).await.unwrap();
+        let snapshot = read();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].task, "second");
+        assert!(snapshot[0].status.starts_with("Running"));
+
+        pool.shutdown().await;
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_stop_requests_from_dashboard() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stop.txt");
+        let mut pool = shell_pool(1, "exec sleep 5");
+        pool.set_stop_request_path(path.clone());
+
+        let running = pool.spawn(Task::new("first").with_agent_type("sh")).await.unwrap();
+        let queued = pool.spawn(Task::new("second").with_agent_type("sh")).await.unwrap();
+        let third = pool.spawn(Task::new("third").with_agent_type("sh")).await.unwrap();
+
+        std::fs::write(&path, format!("{}\n{}\nno-such-agent\n", queued.agent_id(), running.agent_id())).unwrap();
+        pool.refresh().await;
+
+        assert!(!path.exists());
+        assert!(matches!(pool.status(queued.agent_id()).await, Some(AgentStatus::Stopped)));
+        assert!(pool.status(running.agent_id()).await.is_none());
+        // The freed slot went to the remaining queued task
+        assert!(matches!(pool.status(third.agent_id()).await, Some(AgentStatus::Running { .. })));
+
+        pool.shutdown().await;
+    }
+
+    #[test]
+    fn test_agent_types_lists_known_and_custom() {
+        let pool = shell_pool(1, "true");
+        let types = pool.agent_types();
+
+        let names: Vec<&str> = types.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["claude", "aider", "cursor", "sh"]);
+
+        let claude = &types[0];
+        assert!(claude.supports_continue);
+        assert!(claude.supports_skip_permissions);
+        assert_eq!(claude.available, claude.path.is_some());
+
+        let sh = &types[3];
+        assert!(sh.available);
+        assert_eq!(sh.path, Some(PathBuf::from("/bin/sh")));
+        assert!(!sh.supports_continue);
+    }
+
+    /// A declared agent, as read from agents.toml
+    fn declared_agent(name: &str) -> registry::AgentDefinition {
+        registry::AgentDefinition {
+            name: name.to_string(),
+            executable: Some(PathBuf::from("/bin/sh")),
+            search_names: Vec::new(),
+            continue_flag: None,
+            skip_permissions_flag: None,
+            args: Vec::new(),
+            prompt_template: Some("{task}\nSay DONE.".to_string()),
+            limits: ResourceLimits::default(),
+        }
+    }
+
+    #[test]
+    fn test_prompt_template_from_agents_toml() {
+        let agent = declared_agent("templated");
+        let config = AgentPool::agent_config(&agent, PathBuf::from("/bin/sh"));
+        assert_eq!(config.prompt_template.as_deref(), Some("{task}\nSay DONE."));
+
+        // The environment overrides the file
+        std::env::set_var("LAZARUS_PROMPT_TEMPLATE_TEMPLATED", "Be brief. {task}");
+        let config = AgentPool::agent_config(&agent, PathBuf::from("/bin/sh"));
+        std::env::remove_var("LAZARUS_PROMPT_TEMPLATE_TEMPLATED");
+        assert_eq!(config.prompt_template.as_deref(), Some("Be brief. {task}"));
+    }
+
+    #[test]
+    fn test_limits_from_agents_toml() {
+        let mut agent = declared_agent("limited");
+        agent.limits = ResourceLimits { memory_limit_mb: Some(2048), cpu_time_limit_secs: None };
+        let config = AgentPool::agent_config(&agent, PathBuf::from("/bin/sh"));
+        assert_eq!(config.limits, agent.limits);
+
+        // A task's own limits win, the rest come from the agent type
+        let task = ResourceLimits { memory_limit_mb: Some(512), cpu_time_limit_secs: Some(60) };
+        assert_eq!(task.or(config.limits), task);
+        assert_eq!(ResourceLimits::default().or(config.limits).memory_limit_mb, Some(2048));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_graceful_waits_then_stops() {
+        let mut pool = shell_pool(2, "exec sleep 0.2");
+        pool.set_agent_config(
+            "slow",
+            AgentConfig {
+                executable: PathBuf::from("/bin/sh"),
+                args: vec!["-c".to_string(), "exec sleep 30".to_string()],
+                skip_permissions_flag: None,
+                prompt_template: None,
+                continue_flag: None,
+                limits: ResourceLimits::default(),
+            },
+        );
+
+        let quick = pool.spawn(Task::new("quick").with_agent_type("sh")).await.unwrap();
+        let slow = pool.spawn(Task::new("slow").with_agent_type("slow")).await.unwrap();
+        pool.lock_manager()
+            .try_acquire(std::path::Path::new("/tmp/x"), slow.agent_id(), LockType::Write)
+            .await;
+
+        let results = pool.shutdown_graceful(std::time::Duration::from_secs(1)).await;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, quick.agent_id());
+        assert!(results[0].1.success);
+        assert_eq!(pool.active_count().await, 0);
+        assert!(pool.lock_manager().list_locks().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_lock_file_waits_in_background() {
+        let pool = shell_pool(2, "exec sleep 30");
+        let first = pool.spawn(Task::new("one").with_agent_type("sh")).await.unwrap();
+        let second = pool.spawn(Task::new("two").with_agent_type("sh")).await.unwrap();
+        let (first, second) = (first.agent_id(), second.agent_id());
+        let wait = std::time::Duration::from_secs(5);
+
+        assert!(pool.lock_file(first, "/tmp/a.txt", LockType::Write, wait).await.unwrap());
+        assert!(pool.lock_file(second, "/tmp/b.txt", LockType::Write, wait).await.unwrap());
+        // Without a timeout a held lock is just refused
+        assert!(!pool.lock_file(first, "/tmp/b.txt", LockType::Write, std::time::Duration::ZERO).await.unwrap());
+        assert!(pool.lock_manager().waiting_for(first).is_none());
+
+        // Waiting on each other is a deadlock
+        assert!(!pool.lock_file(first, "/tmp/b.txt", LockType::Write, wait).await.unwrap());
+        assert!(!pool.lock_file(second, "/tmp/a.txt", LockType::Write, wait).await.unwrap());
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(pool.lock_manager().detect_cycle().await.is_some());
+
+        // Stopping one agent releases its locks, and the other gets them
+        pool.stop(first).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(pool.lock_manager().detect_cycle().await.is_none());
+        assert!(pool.lock_manager().is_locked_by("/tmp/a.txt", second).await);
+        assert!(!pool.lock_manager().is_locked_by("/tmp/b.txt", first).await);
+        assert!(pool.lock_file(first, "/tmp/c.txt", LockType::Write, wait).await.is_err());
+
+        pool.stop(second).await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_pool_default() {
         let pool = AgentPool::default();
@@ -0,0 +1,255 @@
+//! Agent Registry
+//!
+//! The agent types lazarus-mcp knows how to drive: the built-in claude,
+//! aider and cursor, plus any declared in `~/.config/lazarus-mcp/agents.toml`.
+//! A declared agent with a built-in's name replaces the built-in.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tracing::warn;
+
+use super::task::ResourceLimits;
+
+/// An agent type and how to run it
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentDefinition {
+    /// Agent type name, as used by `agent_spawn` and `--task`
+    pub name: String,
+    /// Path of the executable (a bare name is looked up on PATH)
+    #[serde(default)]
+    pub executable: Option<PathBuf>,
+    /// Names to look for on PATH when no executable is given (default: the agent name)
+    #[serde(default)]
+    pub search_names: Vec<String>,
+    /// Flag that continues the agent's previous session (if supported)
+    #[serde(default)]
+    pub continue_flag: Option<String>,
+    /// Flag that skips permission prompts (if supported)
+    #[serde(default)]
+    pub skip_permissions_flag: Option<String>,
+    /// Arguments always passed to the agent
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Template wrapped around background task prompts (`{task}` is replaced by the description)
+    #[serde(default)]
+    pub prompt_template: Option<String>,
+    /// Resource limits for background tasks that don't set their own
+    #[serde(default)]
+    pub limits: ResourceLimits,
+}
+
+impl AgentDefinition {
+    fn builtin(name: &str, skip_permissions_flag: Option<&str>, continue_flag: Option<&str>) -> Self {
+        Self {
+            name: name.to_string(),
+            executable: None,
+            search_names: Vec::new(),
+            continue_flag: continue_flag.map(String::from),
+            skip_permissions_flag: skip_permissions_flag.map(String::from),
+            args: Vec::new(),
+            prompt_template: None,
+            limits: ResourceLimits::default(),
+        }
+    }
+
+    /// Names the executable may have
+    fn executable_names(&self) -> Vec<&str> {
+        match &self.executable {
+            Some(path) => path.file_name().and_then(|n| n.to_str()).into_iter().collect(),
+            None if self.search_names.is_empty() => vec![self.name.as_str()],
+            None => self.search_names.iter().map(String::as_str).collect(),
+        }
+    }
+
+    /// Whether a command run by the wrapper is this agent
+    ///
+    /// `command` is the executable's file name, e.g. `claude`.
+    pub fn matches_command(&self, command: &str) -> bool {
+        self.name == command || self.executable_names().contains(&command)
+    }
+
+    /// Find the agent's executable on this machine
+    pub fn find_executable(&self) -> Option<PathBuf> {
+        match &self.executable {
+            Some(path) if path.components().count() > 1 => path.is_file().then(|| path.clone()),
+            _ => self.executable_names().into_iter().find_map(find_executable),
+        }
+    }
+}
+
+/// Agent types that work out of the box
+fn builtin_agents() -> Vec<AgentDefinition> {
+    vec![
+        AgentDefinition::builtin("claude", Some("--dangerously-skip-permissions"), Some("--continue")),
+        AgentDefinition::builtin("aider", Some("--yes"), Some("--restore-chat-history")),
+        AgentDefinition::builtin("cursor", None, None),
+    ]
+}
+
+/// Find an executable on PATH or in the usual install locations
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    // Try which first
+    if let Ok(path) = which::which(name) {
+        return Some(path);
+    }
+
+    // Try common locations
+    let candidates = [
+        PathBuf::from(format!("/usr/local/bin/{}", name)),
+        PathBuf::from(format!("/usr/bin/{}", name)),
+    ];
+
+    // Add home directory locations
+    let home_candidates = if let Some(home) = dirs::home_dir() {
+        vec![
+            home.join(format!(".local/bin/{}", name)),
+            home.join(format!(".local/share/{}/{}", name, name)),
+        ]
+    } else {
+        vec![]
+    };
+
+    candidates
+        .iter()
+        .chain(home_candidates.iter())
+        .find(|candidate| candidate.is_file())
+        .cloned()
+}
+
+/// Contents of agents.toml: a list of `[[agent]]` tables
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AgentsFile {
+    #[serde(default)]
+    agent: Vec<AgentDefinition>,
+}
+
+/// Parse agents.toml
+fn parse(content: &str) -> Result<Vec<AgentDefinition>> {
+    let file: AgentsFile = toml::from_str(content)?;
+    Ok(file.agent)
+}
+
+/// Add declared agents to the built-in ones, replacing built-ins of the same name
+fn merge(mut agents: Vec<AgentDefinition>, declared: Vec<AgentDefinition>) -> Vec<AgentDefinition> {
+    for agent in declared {
+        match agents.iter_mut().find(|a| a.name == agent.name) {
+            Some(existing) => *existing = agent,
+            None => agents.push(agent),
+        }
+    }
+    agents
+}
+
+/// Location of agents.toml
+pub fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("lazarus-mcp").join("agents.toml"))
+}
+
+/// Load the built-in agents plus those declared in `path`
+///
+/// A missing file just means no declared agents. An unreadable or invalid
+/// one is reported and ignored, as are declared agents whose executable
+/// can't be found (they are still registered, in case it appears later).
+pub fn load(path: &Path) -> Vec<AgentDefinition> {
+    let declared = match std::fs::read_to_string(path) {
+        Ok(content) => parse(&content).with_context(|| format!("Invalid {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let declared = declared.unwrap_or_else(|e| {
+        warn!("{:#}; using the built-in agents only", e);
+        Vec::new()
+    });
+
+    for agent in &declared {
+        if agent.find_executable().is_none() {
+            warn!("Agent '{}' from {}: executable not found", agent.name, path.display());
+        }
+    }
+    merge(builtin_agents(), declared)
+}
+
+/// All known agent types, loaded once
+pub fn agents() -> &'static [AgentDefinition] {
+    static AGENTS: OnceLock<Vec<AgentDefinition>> = OnceLock::new();
+    AGENTS.get_or_init(|| match config_path() {
+        Some(path) => load(&path),
+        None => builtin_agents(),
+    })
+}
+
+/// Flag an agent resumes its previous session with, if any
+///
+/// `command` is the executable's file name, e.g. `claude`.
+pub fn continue_flag_for(command: &str) -> Option<&'static str> {
+    agents()
+        .iter()
+        .find(|agent| agent.matches_command(command))
+        .and_then(|agent| agent.continue_flag.as_deref())
+}
+
+/// Resolve the command the wrapper was given
+///
+/// A bare agent name (e.g. `mycli`) runs the executable registered for that
+/// agent, if it names one and it is found; anything else is run as given.
+pub fn resolve_command(command: &Path) -> PathBuf {
+    let registered = command
+        .to_str()
+        .filter(|name| !name.contains('/'))
+        .and_then(|name| agents().iter().find(|agent| agent.name == name))
+        .filter(|agent| agent.executable.is_some() || !agent.search_names.is_empty())
+        .and_then(AgentDefinition::find_executable);
+    registered.unwrap_or_else(|| command.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_merges_declared_agents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("agents.toml");
+        std::fs::write(
+            &path,
+            r#"
+[[agent]]
+name = "mycli"
+executable = "/bin/sh"
+continue_flag = "--resume"
+args = ["--quiet"]
+prompt_template = "{task}\nSay DONE when finished."
+limits = { memory_limit_mb = 2048 }
+
+[[agent]]
+name = "aider"
+search_names = ["aider-chat"]
+"#,
+        )
+        .unwrap();
+
+        let agents = load(&path);
+        let names: Vec<&str> = agents.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["claude", "aider", "cursor", "mycli"]);
+
+        let mycli = &agents[3];
+        assert_eq!(mycli.find_executable(), Some(PathBuf::from("/bin/sh")));
+        assert_eq!(mycli.args, ["--quiet"]);
+        assert_eq!(mycli.prompt_template.as_deref(), Some("{task}\nSay DONE when finished."));
+        assert_eq!(mycli.limits.memory_limit_mb, Some(2048));
+        assert_eq!(mycli.limits.cpu_time_limit_secs, None);
+        assert!(mycli.matches_command("sh"));
+        // The declared aider replaced the built-in one
+        assert_eq!(agents[1].continue_flag, None);
+        assert!(agents[1].matches_command("aider-chat"));
+
+        // Without a file (or with a broken one) the built-ins remain
+        assert_eq!(load(&dir.path().join("missing.toml")), builtin_agents());
+        std::fs::write(&path, "[[agent]]\nnmae = \"typo\"\n").unwrap();
+        assert_eq!(load(&path), builtin_agents());
+    }
+}
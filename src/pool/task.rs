@@ -6,20 +6,15 @@ use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 /// Priority level for tasks
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
 pub enum TaskPriority {
     Low,
+    #[default]
     Normal,
     High,
     Urgent,
 }
 
-impl Default for TaskPriority {
-    fn default() -> Self {
-        TaskPriority::Normal
-    }
-}
-
 /// A task to be executed by an agent
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Task {
@@ -35,6 +30,43 @@ pub struct Task {
     pub max_iterations: u32,
     /// Type of agent to use (claude, aider, cursor)
     pub agent_type: String,
+    /// Continue the agent's previous session when this task is retried
+    #[serde(default = "default_resume_on_retry")]
+    pub resume_on_retry: bool,
+    /// Number of previous attempts at this task (0 for the first run)
+    #[serde(default)]
+    pub attempt: u32,
+    /// Agents that must complete successfully before this task starts
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Resource limits for the agent, overriding the agent type's defaults
+    #[serde(default, flatten)]
+    pub limits: ResourceLimits,
+}
+
+/// Resource limits applied to an agent process (see `setrlimit(2)`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// Address space limit in MiB (`RLIMIT_AS`)
+    #[serde(default)]
+    pub memory_limit_mb: Option<u64>,
+    /// CPU time limit in seconds (`RLIMIT_CPU`)
+    #[serde(default)]
+    pub cpu_time_limit_secs: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// These limits, with unset ones taken from `defaults`
+    pub fn or(self, defaults: ResourceLimits) -> Self {
+        Self {
+            memory_limit_mb: self.memory_limit_mb.or(defaults.memory_limit_mb),
+            cpu_time_limit_secs: self.cpu_time_limit_secs.or(defaults.cpu_time_limit_secs),
+        }
+    }
+}
+
+fn default_resume_on_retry() -> bool {
+    true
 }
 
 impl Task {
@@ -47,9 +79,18 @@ impl Task {
             working_directory: None,
             max_iterations: 50,
             agent_type: "claude".to_string(),
+            resume_on_retry: true,
+            attempt: 0,
+            depends_on: Vec::new(),
+            limits: ResourceLimits::default(),
         }
     }
 
+    /// Whether this run is a retry of an earlier attempt
+    pub fn is_retry(&self) -> bool {
+        self.attempt > 0
+    }
+
     /// Set the working directory
     pub fn with_working_directory(mut self, dir: impl Into<PathBuf>) -> Self {
         self.working_directory = Some(dir.into());
@@ -73,6 +114,24 @@ impl Task {
         self.priority = priority;
         self
     }
+
+    /// Wait for these agents to complete successfully before starting
+    pub fn with_dependencies(mut self, agent_ids: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.depends_on = agent_ids.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set resource limits for the agent
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Set whether retries continue the previous session
+    pub fn with_resume_on_retry(mut self, resume: bool) -> Self {
+        self.resume_on_retry = resume;
+        self
+    }
 }
 
 /// Result of a completed task
@@ -133,8 +192,10 @@ mod tests {
             .with_working_directory("/tmp/test")
             .with_max_iterations(100)
             .with_agent_type("aider")
-            .with_priority(TaskPriority::High);
+            .with_priority(TaskPriority::High)
+            .with_dependencies(["agent-1"]);
 
+        assert_eq!(task.depends_on, vec!["agent-1".to_string()]);
         assert_eq!(task.working_directory, Some(PathBuf::from("/tmp/test")));
         assert_eq!(task.max_iterations, 100);
         assert_eq!(task.agent_type, "aider");
@@ -2,11 +2,13 @@
 //!
 //! Handles dropping root privileges when running with elevated permissions.
 //! By default, lazarus-mcp drops to the original user before spawning coding agents
-//! to prevent accidental damage from privileged operations.
+//! to prevent accidental damage from privileged operations. With `--run-as`, it
+//! drops to the given user instead (e.g. when started by a root daemon or systemd).
 
 use anyhow::{anyhow, Context, Result};
-use nix::unistd::{setgid, setuid, Gid, Uid};
+use nix::unistd::{initgroups, setgid, setgroups, setuid, Gid, Uid, User};
 use std::env;
+use std::ffi::CString;
 use tracing::info;
 
 /// Check if the current process is running as root
@@ -62,11 +64,112 @@ pub fn drop_privileges() -> Result<()> {
     Ok(())
 }
 
+/// A user to run as, resolved from a name or `uid[:gid]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetUser {
+    /// User name, if the user has a passwd entry
+    pub name: Option<String>,
+    pub uid: Uid,
+    pub gid: Gid,
+    /// Home directory, if the user has a passwd entry
+    pub home: Option<std::path::PathBuf>,
+}
+
+/// Resolve a user name, `uid` or `uid:gid` to the ids to run as
+///
+/// A bare uid takes its group from the passwd entry; `uid:gid` works for
+/// users without one.
+pub fn resolve_user(spec: &str) -> Result<TargetUser> {
+    let (user_part, gid_part) = match spec.split_once(':') {
+        Some((user, gid)) => (user, Some(gid)),
+        None => (spec, None),
+    };
+
+    let entry = match user_part.parse::<u32>() {
+        Ok(uid) => User::from_uid(Uid::from_raw(uid))?,
+        Err(_) => Some(User::from_name(user_part)?.ok_or_else(|| anyhow!("Unknown user: {}", user_part))?),
+    };
+
+    let uid = match &entry {
+        Some(user) => user.uid,
+        None => Uid::from_raw(user_part.parse()?),
+    };
+    let gid = match (gid_part, &entry) {
+        (Some(gid), _) => Gid::from_raw(gid.parse().with_context(|| format!("Invalid group id: {}", gid))?),
+        (None, Some(user)) => user.gid,
+        (None, None) => return Err(anyhow!("No passwd entry for uid {}; use uid:gid", uid)),
+    };
+
+    Ok(TargetUser {
+        name: entry.as_ref().map(|u| u.name.clone()),
+        uid,
+        gid,
+        home: entry.map(|u| u.dir),
+    })
+}
+
+/// Drop root privileges to a specific user (name, `uid` or `uid:gid`)
+///
+/// Sets the supplementary groups (from the group database when the user has
+/// a passwd entry, otherwise just the primary group), then the gid, then the
+/// uid. HOME, USER and LOGNAME are updated for the agent.
+///
+/// # Errors
+///
+/// Returns an error if the user can't be resolved, or if not running as root
+/// and the target isn't the current user.
+pub fn drop_privileges_to(spec: &str) -> Result<()> {
+    let target = resolve_user(spec)?;
+    if !is_root() {
+        if target.uid == Uid::current() {
+            return Ok(());
+        }
+        return Err(anyhow!("--run-as {} requires running as root", spec));
+    }
+
+    match &target.name {
+        Some(name) => initgroups(&CString::new(name.as_str())?, target.gid)
+            .context("Failed to set supplementary groups")?,
+        None => setgroups(&[target.gid]).context("Failed to set supplementary groups")?,
+    }
+    // Must do gid first, then uid (can't change gid after dropping uid)
+    setgid(target.gid).context("Failed to drop group privileges")?;
+    setuid(target.uid).context("Failed to drop user privileges")?;
+
+    if let Some(name) = &target.name {
+        env::set_var("USER", name);
+        env::set_var("LOGNAME", name);
+    }
+    if let Some(home) = &target.home {
+        env::set_var("HOME", home);
+    }
+
+    info!(
+        "Dropped privileges to {} (uid={}, gid={})",
+        target.name.as_deref().unwrap_or("?"),
+        target.uid,
+        target.gid
+    );
+    Ok(())
+}
+
+/// Drop root privileges before running agents
+///
+/// Drops to `run_as` if given, otherwise (when root) to the user who ran sudo.
+pub fn drop_privileges_for(run_as: Option<&str>) -> Result<()> {
+    match run_as {
+        Some(user) => drop_privileges_to(user),
+        None if is_root() => drop_privileges(),
+        None => Ok(()),
+    }
+}
+
 /// Get information about the current privilege state
 pub fn privilege_info() -> PrivilegeInfo {
     let effective_uid = Uid::effective();
     let effective_gid = Gid::effective();
     let is_root = effective_uid.is_root();
+    let user = User::from_uid(effective_uid).ok().flatten().map(|u| u.name);
 
     let sudo_user = env::var("SUDO_USER").ok();
     let sudo_uid = env::var("SUDO_UID").ok().and_then(|s| s.parse().ok());
@@ -76,6 +179,7 @@ pub fn privilege_info() -> PrivilegeInfo {
         effective_uid: effective_uid.as_raw(),
         effective_gid: effective_gid.as_raw(),
         is_root,
+        user,
         sudo_user,
         sudo_uid,
         sudo_gid,
@@ -88,6 +192,8 @@ pub struct PrivilegeInfo {
     pub effective_uid: u32,
     pub effective_gid: u32,
     pub is_root: bool,
+    /// Name of the effective user
+    pub user: Option<String>,
     pub sudo_user: Option<String>,
     pub sudo_uid: Option<u32>,
     pub sudo_gid: Option<u32>,
@@ -101,7 +207,25 @@ mod tests {
     fn test_privilege_info() {
         let info = privilege_info();
         // Basic sanity check - we should have valid UIDs
-        assert!(info.effective_uid < 65534 || info.effective_uid == 65534);
+        assert!(info.effective_uid <= 65534);
+    }
+
+    #[test]
+    fn test_resolve_user() {
+        let root = resolve_user("root").unwrap();
+        assert_eq!((root.uid, root.gid), (Uid::from_raw(0), Gid::from_raw(0)));
+        assert_eq!(root.name.as_deref(), Some("root"));
+        assert_eq!(resolve_user("0").unwrap(), root);
+
+        // An explicit gid wins, and works without a passwd entry
+        assert_eq!(resolve_user("0:5").unwrap().gid, Gid::from_raw(5));
+        let nobody = resolve_user("4000000:4000000").unwrap();
+        assert_eq!(nobody.uid, Uid::from_raw(4000000));
+        assert!(nobody.name.is_none());
+
+        assert!(resolve_user("4000000").is_err());
+        assert!(resolve_user("no-such-user-lazarus").is_err());
+        assert!(resolve_user("root:staff").is_err());
     }
 
     #[test]
@@ -0,0 +1,303 @@
+//! Pseudo-Terminal Support
+//!
+//! Runs the agent attached to a PTY instead of the wrapper's inherited stdio.
+//! Output from the PTY is copied to the wrapper's stdout, the wrapper's stdin
+//! is copied to the PTY, and terminal resizes (SIGWINCH) are relayed to it.
+//! While the terminal is in raw mode, the wrapper's own logs go through
+//! `log_writer`, which restores the carriage returns raw mode drops.
+
+use anyhow::{Context, Result};
+use nix::pty::{openpty, Winsize};
+use std::fs::File;
+use std::io::{IsTerminal, Read, Write};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use tracing::{debug, warn};
+
+/// How long relay threads wait for data before re-checking the stop flag
+const POLL_INTERVAL_MS: i32 = 100;
+
+/// Whether a relay has put the terminal in raw mode
+static RAW_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Writer for the wrapper's logs: stderr, with `\n` written as `\r\n` in raw mode
+///
+/// Raw mode turns off output post-processing, so a bare `\n` would move down
+/// a line without returning to the first column.
+pub fn log_writer() -> CrlfWriter<std::io::Stderr> {
+    CrlfWriter {
+        inner: std::io::stderr(),
+        convert: RAW_MODE.load(Ordering::SeqCst),
+    }
+}
+
+/// Writes `\n` as `\r\n` if `convert` is set, and passes data through otherwise
+pub struct CrlfWriter<W> {
+    inner: W,
+    convert: bool,
+}
+
+impl<W: Write> Write for CrlfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if !self.convert {
+            return self.inner.write(buf);
+        }
+        let mut converted = Vec::with_capacity(buf.len() + 8);
+        for &byte in buf {
+            if byte == b'\n' {
+                converted.push(b'\r');
+            }
+            converted.push(byte);
+        }
+        self.inner.write_all(&converted)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Copies data between the wrapper's terminal and an agent's PTY
+///
+/// Dropping the relay drains any remaining agent output, stops the relay
+/// threads, and restores the terminal mode.
+pub struct PtyRelay {
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+    raw_mode: bool,
+}
+
+impl Drop for PtyRelay {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+        if self.raw_mode {
+            let _ = crossterm::terminal::disable_raw_mode();
+            RAW_MODE.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Get the window size of a terminal
+fn get_winsize(fd: RawFd) -> Option<Winsize> {
+    let mut ws: Winsize = unsafe { std::mem::zeroed() };
+    let ret = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut ws) };
+    (ret == 0).then_some(ws)
+}
+
+/// Set the window size of a terminal
+fn set_winsize(fd: RawFd, ws: &Winsize) {
+    unsafe {
+        libc::ioctl(fd, libc::TIOCSWINSZ, ws);
+    }
+}
+
+/// Wait until a file descriptor is readable or the poll interval elapses
+fn poll_readable(fd: RawFd) -> bool {
+    let mut pfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let ret = unsafe { libc::poll(&mut pfd, 1, POLL_INTERVAL_MS) };
+    ret > 0 && pfd.revents & (libc::POLLIN | libc::POLLHUP | libc::POLLERR) != 0
+}
+
+/// Spawn a command attached to a new PTY and start relaying its I/O
+pub fn spawn(cmd: &mut Command) -> Result<(Child, PtyRelay)> {
+    spawn_with_output(cmd, std::io::stdout())
+}
+
+/// Spawn a command attached to a new PTY, copying its output to `output`
+fn spawn_with_output(cmd: &mut Command, output: impl Write + Send + 'static) -> Result<(Child, PtyRelay)> {
+    let stdin_fd = std::io::stdin().as_raw_fd();
+    let interactive = std::io::stdin().is_terminal();
+
+    let winsize = get_winsize(stdin_fd);
+    let pty = openpty(winsize.as_ref(), None).context("Failed to allocate PTY")?;
+
+    cmd.stdin(Stdio::from(pty.slave.try_clone()?));
+    cmd.stdout(Stdio::from(pty.slave.try_clone()?));
+    cmd.stderr(Stdio::from(pty.slave.try_clone()?));
+
+    // Make the PTY the controlling terminal of the agent's new session
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::setsid() < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            if libc::ioctl(0, libc::TIOCSCTTY, 0) < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+
+    let child = cmd.spawn().context("Failed to spawn agent in PTY")?;
+
+    // The agent holds its own copies of the slave side
+    drop(pty.slave);
+
+    // Pass keystrokes (including Ctrl+C) through untouched
+    let raw_mode = interactive && crossterm::terminal::enable_raw_mode().is_ok();
+    if raw_mode {
+        RAW_MODE.store(true, Ordering::SeqCst);
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let master = Arc::new(pty.master);
+
+    let mut threads = vec![spawn_output_relay(Arc::clone(&master), output, Arc::clone(&stop))];
+    if interactive {
+        threads.push(spawn_input_relay(master, Arc::clone(&stop))?);
+    }
+
+    debug!("Agent {} attached to PTY", child.id());
+    Ok((
+        child,
+        PtyRelay {
+            stop,
+            threads,
+            raw_mode,
+        },
+    ))
+}
+
+/// Copy agent output from the PTY to our stdout (or another output)
+///
+/// Keeps reading until the PTY closes, or until asked to stop and no output is pending.
+fn spawn_output_relay(
+    master: Arc<OwnedFd>,
+    mut output: impl Write + Send + 'static,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let fd = master.as_raw_fd();
+        let mut reader = match master.try_clone() {
+            Ok(fd) => File::from(fd),
+            Err(e) => {
+                warn!("Failed to clone PTY master: {}", e);
+                return;
+            }
+        };
+        let mut buf = [0u8; 4096];
+
+        loop {
+            if poll_readable(fd) {
+                match reader.read(&mut buf) {
+                    // EIO means the agent side has closed
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        let _ = output.write_all(&buf[..n]);
+                        let _ = output.flush();
+                    }
+                }
+            } else if stop.load(Ordering::SeqCst) {
+                break;
+            }
+        }
+    })
+}
+
+/// Copy our stdin to the PTY and relay terminal resizes
+fn spawn_input_relay(master: Arc<OwnedFd>, stop: Arc<AtomicBool>) -> Result<JoinHandle<()>> {
+    let resized = Arc::new(AtomicBool::new(false));
+    let sig_id = signal_hook::flag::register(signal_hook::consts::SIGWINCH, Arc::clone(&resized))
+        .context("Failed to register SIGWINCH handler")?;
+
+    // Read the raw descriptor: std's buffered Stdin could hold bytes that poll can't see
+    let mut stdin = File::from(
+        std::io::stdin()
+            .as_fd()
+            .try_clone_to_owned()
+            .context("Failed to duplicate stdin")?,
+    );
+
+    Ok(std::thread::spawn(move || {
+        let stdin_fd = stdin.as_raw_fd();
+        let master_fd = master.as_raw_fd();
+        let mut writer = match master.try_clone() {
+            Ok(fd) => File::from(fd),
+            Err(e) => {
+                warn!("Failed to clone PTY master: {}", e);
+                return;
+            }
+        };
+        let mut buf = [0u8; 1024];
+
+        while !stop.load(Ordering::SeqCst) {
+            if resized.swap(false, Ordering::SeqCst) {
+                if let Some(ws) = get_winsize(stdin_fd) {
+                    set_winsize(master_fd, &ws);
+                }
+            }
+
+            if poll_readable(stdin_fd) {
+                match stdin.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        if writer.write_all(&buf[..n]).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        signal_hook::low_level::unregister(sig_id);
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Output shared with the relay thread
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_crlf_writer() {
+        let mut raw = CrlfWriter { inner: Vec::new(), convert: true };
+        raw.write_all(b"WARN restarting\nsecond line\n").unwrap();
+        assert_eq!(raw.inner, b"WARN restarting\r\nsecond line\r\n");
+
+        let mut cooked = CrlfWriter { inner: Vec::new(), convert: false };
+        cooked.write_all(b"WARN restarting\n").unwrap();
+        assert_eq!(cooked.inner, b"WARN restarting\n");
+    }
+
+    #[test]
+    fn test_agent_sees_a_terminal() {
+        let output = SharedOutput::default();
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "test -t 0 && echo tty"]);
+
+        let (mut child, relay) = spawn_with_output(&mut cmd, output.clone()).unwrap();
+        let status = child.wait().unwrap();
+        // Dropping the relay drains the remaining output
+        drop(relay);
+
+        assert!(status.success());
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(output.trim_end(), "tty");
+    }
+}
@@ -0,0 +1,203 @@
+//! Session Report
+//!
+//! Summary of a wrapper session (uptime, restarts, peak agent memory and
+//! CPU, final exit status), printed when the wrapper exits if `--report` or
+//! `--report-file` is given.
+
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::health::HealthSample;
+use crate::restart::RestartExplanation;
+
+/// How the supervised agent's session ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEnd {
+    /// The agent exited on its own with this code
+    Exited(i32),
+    /// The wrapper was asked to shut down (SIGINT/SIGTERM)
+    WrapperShutdown,
+    /// The wrapper gave up with this error
+    WrapperFailed(String),
+}
+
+impl std::fmt::Display for SessionEnd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionEnd::Exited(code) => write!(f, "agent exited with code {}", code),
+            SessionEnd::WrapperShutdown => write!(f, "wrapper shut down"),
+            SessionEnd::WrapperFailed(error) => write!(f, "wrapper failed: {}", error),
+        }
+    }
+}
+
+/// Collects what happened during a wrapper session
+#[derive(Debug)]
+pub struct SessionReport {
+    agent_name: String,
+    started: Instant,
+    restarts: Vec<RestartExplanation>,
+    /// Highest agent memory seen, in KiB
+    peak_memory_kb: Option<u64>,
+    /// Highest agent CPU usage seen (100 = one full core)
+    peak_cpu_percent: Option<f32>,
+    end: Option<SessionEnd>,
+}
+
+impl SessionReport {
+    pub fn new(agent_name: &str) -> Self {
+        Self {
+            agent_name: agent_name.to_string(),
+            started: Instant::now(),
+            restarts: Vec::new(),
+            peak_memory_kb: None,
+            peak_cpu_percent: None,
+            end: None,
+        }
+    }
+
+    /// Record a restart of the agent
+    pub fn record_restart(&mut self, explanation: RestartExplanation) {
+        self.record_memory(explanation.metrics.memory_kb);
+        self.restarts.push(explanation);
+    }
+
+    /// Record a health sample of the agent, keeping its peaks
+    pub fn record_sample(&mut self, sample: &HealthSample) {
+        self.record_memory(sample.memory_kb);
+        if let Some(cpu) = sample.cpu_percent {
+            self.peak_cpu_percent = Some(self.peak_cpu_percent.map_or(cpu, |peak| peak.max(cpu)));
+        }
+    }
+
+    fn record_memory(&mut self, memory_kb: Option<u64>) {
+        self.peak_memory_kb = self.peak_memory_kb.max(memory_kb);
+    }
+
+    /// Record how the session ended
+    pub fn finish(&mut self, end: SessionEnd) {
+        self.end = Some(end);
+    }
+
+    /// Render the report as human-readable text
+    pub fn render(&self) -> String {
+        self.render_with_uptime(self.started.elapsed())
+    }
+
+    fn render_with_uptime(&self, uptime: Duration) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "=== lazarus-mcp session report ===");
+        let _ = writeln!(out, "Agent: {}", self.agent_name);
+        let _ = writeln!(out, "Uptime: {}", format_duration(uptime.as_secs()));
+        let _ = writeln!(out, "Restarts: {}", self.restarts.len());
+        for (i, restart) in self.restarts.iter().enumerate() {
+            let _ = writeln!(out, "  {}. [{}] {}", i + 1, restart.trigger, restart.reason);
+        }
+        if let Some(peak_kb) = self.peak_memory_kb {
+            let _ = writeln!(out, "Peak agent memory: {} MB", peak_kb / 1024);
+        }
+        if let Some(peak_cpu) = self.peak_cpu_percent {
+            let _ = writeln!(out, "Peak agent CPU: {:.0}%", peak_cpu);
+        }
+        let end = self
+            .end
+            .as_ref()
+            .map(|e| e.to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        let _ = writeln!(out, "Exit status: {}", end);
+        out
+    }
+
+    /// Print the report to stderr and/or write it to a file
+    pub fn emit(&self, to_stderr: bool, file: Option<&Path>) {
+        let text = self.render();
+        if to_stderr {
+            eprint!("\n{}", text);
+        }
+        if let Some(path) = file {
+            if let Err(e) = std::fs::write(path, &text) {
+                eprintln!("Failed to write session report to {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Format a number of seconds as e.g. "1h 2m 3s"
+pub fn format_duration(secs: u64) -> String {
+    let hours = secs / 3600;
+    let mins = (secs % 3600) / 60;
+    let secs = secs % 60;
+    if hours > 0 {
+        format!("{}h {}m {}s", hours, mins, secs)
+    } else if mins > 0 {
+        format!("{}m {}s", mins, secs)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::restart::{RestartMetrics, RestartTrigger};
+
+    fn explanation(trigger: RestartTrigger, reason: &str, memory_kb: Option<u64>) -> RestartExplanation {
+        RestartExplanation {
+            trigger,
+            reason: reason.to_string(),
+            timestamp: 0,
+            metrics: RestartMetrics {
+                memory_kb,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_render_report() {
+        let mut report = SessionReport::new("claude");
+        report.record_restart(explanation(RestartTrigger::McpCodeChange, "rebuilt server", Some(204800)));
+        report.record_restart(explanation(RestartTrigger::User, "from dashboard", Some(102400)));
+        report.finish(SessionEnd::Exited(3));
+
+        let text = report.render_with_uptime(Duration::from_secs(3723));
+        assert!(text.contains("Agent: claude\n"));
+        assert!(text.contains("Uptime: 1h 2m 3s\n"));
+        assert!(text.contains("Restarts: 2\n"));
+        assert!(text.contains("  1. [MCP code change] rebuilt server\n"));
+        assert!(text.contains("  2. [user-initiated] from dashboard\n"));
+        assert!(text.contains("Peak agent memory: 200 MB\n"));
+        assert!(text.ends_with("Exit status: agent exited with code 3\n"));
+    }
+
+    #[test]
+    fn test_render_without_restarts() {
+        let mut report = SessionReport::new("claude");
+        report.finish(SessionEnd::WrapperShutdown);
+
+        let text = report.render_with_uptime(Duration::from_secs(5));
+        assert!(text.contains("Uptime: 5s\n"));
+        assert!(text.contains("Restarts: 0\n"));
+        assert!(!text.contains("Peak agent"));
+        assert!(text.ends_with("Exit status: wrapper shut down\n"));
+
+        // Peaks come from the health samples, restarts or not
+        let sample = |memory_kb, cpu_percent| HealthSample {
+            ts: 0,
+            state: crate::wrapper::AgentState::Running,
+            memory_kb: Some(memory_kb),
+            cpu_percent,
+            fds: None,
+        };
+        report.record_sample(&sample(307200, None));
+        report.record_sample(&sample(102400, Some(150.0)));
+        report.record_sample(&sample(204800, Some(12.5)));
+        let text = report.render_with_uptime(Duration::from_secs(5));
+        assert!(text.contains("Restarts: 0\nPeak agent memory: 300 MB\nPeak agent CPU: 150%\n"));
+
+        report.finish(SessionEnd::WrapperFailed("Failed to spawn claude".to_string()));
+        let text = report.render_with_uptime(Duration::from_secs(5));
+        assert!(text.ends_with("Exit status: wrapper failed: Failed to spawn claude\n"));
+    }
+}
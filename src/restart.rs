@@ -4,12 +4,67 @@ use std::fs;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::info;
 
-const SIGNAL_FILE_PREFIX: &str = "/tmp/lazarus-mcp-";
+use crate::paths;
 
 #[derive(Debug, Serialize)]
 pub struct RestartSignalInfo {
     pub wrapper_pid: u32,
     pub signal_file: String,
+    pub explanation: RestartExplanation,
+}
+
+/// What caused a restart
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartTrigger {
+    /// Requested by the user (e.g. from the dashboard)
+    User,
+    /// Requested after an MCP server's code changed (through `restart_agent`)
+    #[default]
+    McpCodeChange,
+    /// The agent exited abnormally
+    Crash,
+}
+
+impl std::fmt::Display for RestartTrigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RestartTrigger::User => write!(f, "user-initiated"),
+            RestartTrigger::McpCodeChange => write!(f, "MCP code change"),
+            RestartTrigger::Crash => write!(f, "crash"),
+        }
+    }
+}
+
+/// Agent metrics captured at the moment a restart was requested
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RestartMetrics {
+    /// PID of the agent being restarted
+    pub agent_pid: Option<u32>,
+    /// Resident memory of the agent in KiB
+    pub memory_kb: Option<u64>,
+    /// How long the agent had been running, in seconds
+    pub uptime_secs: Option<u64>,
+}
+
+impl RestartMetrics {
+    /// Capture metrics for a running agent process
+    pub fn capture(agent_pid: u32) -> Self {
+        Self {
+            agent_pid: Some(agent_pid),
+            memory_kb: get_rss_kb(agent_pid),
+            uptime_secs: get_uptime_secs(agent_pid),
+        }
+    }
+}
+
+/// Structured account of why a restart happened
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestartExplanation {
+    pub trigger: RestartTrigger,
+    pub reason: String,
+    pub timestamp: u64,
+    pub metrics: RestartMetrics,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,6 +74,10 @@ pub struct RestartSignal {
     pub reason: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub prompt: Option<String>,
+    #[serde(default)]
+    pub trigger: RestartTrigger,
+    #[serde(default)]
+    pub metrics: RestartMetrics,
 }
 
 #[derive(Debug, Serialize)]
@@ -54,8 +113,42 @@ fn get_comm(pid: u32) -> Option<String> {
         .map(|s| s.trim().to_string())
 }
 
+/// Get the resident set size of a process in KiB
+pub fn get_rss_kb(pid: u32) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|kb| kb.parse().ok())
+}
+
+/// Get how long a process has been running, in seconds
+fn get_uptime_secs(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let close_paren = stat.rfind(')')?;
+    let after_comm = &stat[close_paren + 2..];
+    // starttime is field 22 overall, i.e. index 19 after the comm field
+    let start_ticks: u64 = after_comm.split_whitespace().nth(19)?.parse().ok()?;
+
+    let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks_per_sec <= 0 {
+        return None;
+    }
+
+    let system_uptime: f64 = fs::read_to_string("/proc/uptime")
+        .ok()?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+
+    let started_secs = start_ticks / ticks_per_sec as u64;
+    Some((system_uptime as u64).saturating_sub(started_secs))
+}
+
 /// Find the wrapper PID by walking up the process tree
-fn find_wrapper_pid() -> Option<u32> {
+pub fn find_wrapper_pid() -> Option<u32> {
     // The process tree should be:
     // wrapper (lazarus-mcp) -> claude -> MCP server (lazarus-mcp --mcp-server)
     //
@@ -89,11 +182,24 @@ fn find_wrapper_pid() -> Option<u32> {
 }
 
 /// Send a restart signal to the wrapper
-pub fn send_restart_signal(reason: &str, prompt: Option<&str>) -> Result<RestartSignalInfo> {
+pub fn send_restart_signal(
+    reason: &str,
+    prompt: Option<&str>,
+    trigger: RestartTrigger,
+) -> Result<RestartSignalInfo> {
     let wrapper_pid = find_wrapper_pid()
         .context("Could not find wrapper process. Make sure your agent was started via: lazarus-mcp <agent> [args...]")?;
 
-    let signal_file = format!("{}{}", SIGNAL_FILE_PREFIX, wrapper_pid);
+    // Next to the wrapper's state file, so wrappers using the legacy location are reached too
+    let signal_file = paths::wrapper_dir(wrapper_pid)
+        .join(paths::signal_file_name(wrapper_pid))
+        .to_string_lossy()
+        .into_owned();
+
+    // Our parent is the agent that is about to be restarted
+    let metrics = get_parent_pid()
+        .map(RestartMetrics::capture)
+        .unwrap_or_default();
 
     let signal = RestartSignal {
         action: "restart".to_string(),
@@ -103,6 +209,8 @@ pub fn send_restart_signal(reason: &str, prompt: Option<&str>) -> Result<Restart
             .as_secs(),
         reason: reason.to_string(),
         prompt: prompt.map(|s| s.to_string()),
+        trigger,
+        metrics,
     };
 
     let content = serde_json::to_string_pretty(&signal)?;
@@ -119,6 +227,12 @@ pub fn send_restart_signal(reason: &str, prompt: Option<&str>) -> Result<Restart
     Ok(RestartSignalInfo {
         wrapper_pid,
         signal_file,
+        explanation: RestartExplanation {
+            trigger: signal.trigger,
+            reason: signal.reason,
+            timestamp: signal.timestamp,
+            metrics: signal.metrics,
+        },
     })
 }
 
@@ -133,7 +247,12 @@ pub fn get_status() -> ServerStatus {
         .map(|pid| fs::metadata(format!("/proc/{}", pid)).is_ok())
         .unwrap_or(false);
 
-    let signal_file_path = wrapper_pid.map(|pid| format!("{}{}", SIGNAL_FILE_PREFIX, pid));
+    let signal_file_path = wrapper_pid.map(|pid| {
+        paths::wrapper_dir(pid)
+            .join(paths::signal_file_name(pid))
+            .to_string_lossy()
+            .into_owned()
+    });
 
     ServerStatus {
         mcp_server_pid,
@@ -144,3 +263,33 @@ pub fn get_status() -> ServerStatus {
         working_directory,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signal_without_trigger_defaults() {
+        let signal: RestartSignal = serde_json::from_str(
+            r#"{"action": "restart", "timestamp": 0, "reason": "old format"}"#,
+        )
+        .unwrap();
+        assert_eq!(signal.trigger, RestartTrigger::McpCodeChange);
+        assert!(signal.metrics.agent_pid.is_none());
+    }
+
+    #[test]
+    fn test_trigger_names() {
+        // The dashboard writes "user" into the signal file by name
+        let user: RestartTrigger = serde_json::from_str(r#""user""#).unwrap();
+        assert_eq!(user, RestartTrigger::User);
+        assert_eq!(serde_json::to_string(&RestartTrigger::Crash).unwrap(), r#""crash""#);
+    }
+
+    #[test]
+    fn test_metrics_capture_self() {
+        let metrics = RestartMetrics::capture(std::process::id());
+        assert_eq!(metrics.agent_pid, Some(std::process::id()));
+        assert!(metrics.memory_kb.unwrap_or(0) > 0);
+    }
+}
@@ -1,21 +1,31 @@
 //! Application state for the TUI dashboard
 
 use std::collections::VecDeque;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use crate::wrapper::SharedState;
+use crate::health::HealthSampler;
+use crate::paths;
+use crate::pool::PoolAgentSnapshot;
+use crate::wrapper::{self, AgentState, RunningWrapper, SharedState};
 
 /// Maximum number of log entries to keep
 const MAX_LOG_ENTRIES: usize = 100;
 
+/// Maximum number of agent resource samples to keep (more than any panel is wide)
+const MAX_RESOURCE_SAMPLES: usize = 300;
+
 /// Application state
 pub struct App {
     /// Wrapper PID to load shared state
     pub wrapper_pid: u32,
     /// Cached shared state
     pub shared_state: Option<SharedState>,
+    /// Whether the wrapper is still alive and updating its state
+    pub wrapper_health: WrapperHealth,
     /// Selected panel (for keyboard navigation)
     pub selected_panel: Panel,
+    /// Whether to render with colors
+    pub color: bool,
     /// Log entries
     pub logs: VecDeque<LogEntry>,
     /// Whether help overlay is shown
@@ -28,8 +38,35 @@ pub struct App {
     pub log_scroll: usize,
     /// Pool agents list (cached)
     pub pool_agents: Vec<PoolAgentInfo>,
+    /// Highlighted row in the pool panel
+    pub pool_selected: usize,
     /// File locks (cached)
     pub file_locks: Vec<FileLockInfo>,
+    /// Wrapper selection overlay, when open
+    pub wrapper_picker: Option<WrapperPicker>,
+    /// Recent (memory MB, CPU %) samples of the agent, oldest first
+    pub resource_history: VecDeque<(u64, f32)>,
+    /// Sampler for the agent process currently being measured
+    sampler: Option<(u32, HealthSampler)>,
+}
+
+/// Overlay listing the running wrappers to switch to
+pub struct WrapperPicker {
+    /// Live wrappers, oldest first
+    pub wrappers: Vec<RunningWrapper>,
+    /// Highlighted row
+    pub selected: usize,
+}
+
+/// Liveness of the monitored wrapper
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrapperHealth {
+    /// Wrapper is running and its heartbeat is fresh
+    Alive,
+    /// Wrapper process exists but has stopped updating its state
+    NotResponding,
+    /// Wrapper process is gone
+    Exited,
 }
 
 /// Selectable panel
@@ -99,14 +136,20 @@ impl App {
         let mut app = Self {
             wrapper_pid,
             shared_state: None,
+            wrapper_health: WrapperHealth::Alive,
             selected_panel: Panel::Agent,
+            color: true,
             logs: VecDeque::with_capacity(MAX_LOG_ENTRIES),
             show_help: false,
             last_update: now,
             should_quit: false,
             log_scroll: 0,
             pool_agents: Vec::new(),
+            pool_selected: 0,
             file_locks: Vec::new(),
+            wrapper_picker: None,
+            resource_history: VecDeque::with_capacity(MAX_RESOURCE_SAMPLES),
+            sampler: None,
         };
 
         app.log(LogLevel::Info, "Dashboard started");
@@ -137,6 +180,8 @@ impl App {
         if let Ok(state) = SharedState::load(self.wrapper_pid) {
             self.shared_state = Some(state);
         }
+        self.update_wrapper_health();
+        self.update_resource_history();
 
         // Update pool agents
         self.update_pool_agents();
@@ -145,9 +190,144 @@ impl App {
         self.update_file_locks();
     }
 
+    /// Detect a wrapper that exited or stopped updating its state
+    fn update_wrapper_health(&mut self) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let health = if std::fs::metadata(format!("/proc/{}", self.wrapper_pid)).is_err() {
+            WrapperHealth::Exited
+        } else if self.shared_state.as_ref().is_some_and(|s| s.is_stale(now)) {
+            WrapperHealth::NotResponding
+        } else {
+            WrapperHealth::Alive
+        };
+
+        if health != self.wrapper_health {
+            match health {
+                WrapperHealth::Alive => self.log(LogLevel::Info, "Wrapper is responding again"),
+                WrapperHealth::NotResponding => self.log(LogLevel::Error, "Wrapper is not responding (stale heartbeat)"),
+                WrapperHealth::Exited => self.log(
+                    LogLevel::Error,
+                    format!("Wrapper {} has exited (press w to switch to another)", self.wrapper_pid),
+                ),
+            }
+            self.wrapper_health = health;
+        }
+    }
+
+    /// Scan for running wrappers and show the selection overlay
+    fn open_wrapper_picker(&mut self) {
+        let wrappers = wrapper::find_running_wrappers(&paths::search_dirs());
+        let selected = wrappers.iter().position(|w| w.pid == self.wrapper_pid).unwrap_or(0);
+        self.wrapper_picker = Some(WrapperPicker { wrappers, selected });
+    }
+
+    /// Attach to another wrapper, dropping everything cached from the current one
+    pub fn switch_wrapper(&mut self, wrapper_pid: u32) {
+        if wrapper_pid == self.wrapper_pid {
+            return;
+        }
+        self.wrapper_pid = wrapper_pid;
+        self.shared_state = None;
+        self.wrapper_health = WrapperHealth::Alive;
+        self.pool_agents.clear();
+        self.pool_selected = 0;
+        self.file_locks.clear();
+        self.sampler = None;
+        self.resource_history.clear();
+        self.log(LogLevel::Info, format!("Switched to wrapper {}", wrapper_pid));
+
+        // Load the new wrapper's state on the next update rather than in 500ms
+        self.last_update = Instant::now() - Duration::from_secs(1);
+    }
+
+    /// Handle a key while the wrapper selection overlay is open
+    fn handle_picker_key(&mut self, key: crossterm::event::KeyCode) {
+        use crossterm::event::KeyCode;
+
+        let Some(picker) = &mut self.wrapper_picker else {
+            return;
+        };
+        match key {
+            KeyCode::Down | KeyCode::Char('j') if picker.selected + 1 < picker.wrappers.len() => {
+                picker.selected += 1;
+            }
+            KeyCode::Up | KeyCode::Char('k') => picker.selected = picker.selected.saturating_sub(1),
+            KeyCode::Enter => {
+                let pid = picker.wrappers.get(picker.selected).map(|w| w.pid);
+                self.wrapper_picker = None;
+                if let Some(pid) = pid {
+                    self.switch_wrapper(pid);
+                }
+            }
+            KeyCode::Char('q') | KeyCode::Char('w') | KeyCode::Esc => self.wrapper_picker = None,
+            _ => {}
+        }
+    }
+
+    /// Sample the agent's memory and CPU usage
+    ///
+    /// The history starts over whenever the agent process changes (e.g. after a restart).
+    fn update_resource_history(&mut self) {
+        let Some(state) = &self.shared_state else {
+            return;
+        };
+        let Some(pid) = state.agent_pid.filter(|_| state.agent_status == AgentState::Running) else {
+            return;
+        };
+        if self.sampler.as_ref().is_none_or(|(sampled, _)| *sampled != pid) {
+            self.sampler = Some((pid, HealthSampler::new(pid)));
+            self.resource_history.clear();
+        }
+
+        let Some((_, sampler)) = &mut self.sampler else {
+            return;
+        };
+        let sample = sampler.sample(state.agent_status);
+        // The first sample has no CPU usage yet; skip it rather than plotting a false zero
+        if let (Some(kb), Some(cpu)) = (sample.memory_kb, sample.cpu_percent) {
+            if self.resource_history.len() >= MAX_RESOURCE_SAMPLES {
+                self.resource_history.pop_front();
+            }
+            self.resource_history.push_back((kb / 1024, cpu));
+        }
+    }
+
     fn update_pool_agents(&mut self) {
-        // Pool info would need to be exposed via a file or IPC
-        // For now, we'll leave this as a placeholder
+        // The MCP server writes a snapshot whenever the pool changes
+        let path = crate::pool::snapshot_path(self.wrapper_pid);
+        let snapshot: Vec<PoolAgentSnapshot> = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+            // No agents spawned yet (or the MCP server has shut down)
+            Err(_) => Vec::new(),
+        };
+
+        self.pool_agents = snapshot
+            .into_iter()
+            .map(|agent| PoolAgentInfo {
+                id: agent.id,
+                status: agent.status,
+                task: agent.task,
+                iterations: agent.iterations,
+                elapsed_secs: agent.elapsed_secs,
+            })
+            .collect();
+        self.pool_selected = self.pool_selected.min(self.pool_agents.len().saturating_sub(1));
+    }
+
+    /// Ask the MCP server to stop the highlighted pool agent
+    fn stop_selected_agent(&mut self) {
+        let Some(agent) = self.pool_agents.get(self.pool_selected) else {
+            return;
+        };
+        let agent_id = agent.id.clone();
+        match crate::pool::request_stop(self.wrapper_pid, &agent_id) {
+            Ok(()) => self.log(LogLevel::Info, format!("Stop requested for agent {}", agent_id)),
+            Err(e) => self.log(LogLevel::Error, format!("Failed to request stop for agent {}: {}", agent_id, e)),
+        }
     }
 
     fn update_file_locks(&mut self) {
@@ -182,6 +362,10 @@ impl App {
             self.show_help = false;
             return;
         }
+        if self.wrapper_picker.is_some() {
+            self.handle_picker_key(key);
+            return;
+        }
 
         match key {
             KeyCode::Char('q') | KeyCode::Esc => self.should_quit = true,
@@ -190,9 +374,10 @@ impl App {
             KeyCode::BackTab => self.selected_panel = self.selected_panel.prev(),
             KeyCode::Char('r') => {
                 // Trigger restart via signal file
-                let signal_path = format!("/tmp/lazarus-mcp-{}", self.wrapper_pid);
+                let signal_path = paths::wrapper_dir(self.wrapper_pid).join(paths::signal_file_name(self.wrapper_pid));
                 let signal = serde_json::json!({
-                    "reason": "TUI restart request"
+                    "reason": "TUI restart request",
+                    "trigger": "user"
                 });
                 if std::fs::write(&signal_path, signal.to_string()).is_ok() {
                     self.log(LogLevel::Info, "Restart signal sent");
@@ -200,18 +385,26 @@ impl App {
                     self.log(LogLevel::Error, "Failed to send restart signal");
                 }
             }
-            KeyCode::Down | KeyCode::Char('j') => {
-                if self.selected_panel == Panel::Log {
-                    if self.log_scroll < self.logs.len().saturating_sub(1) {
-                        self.log_scroll += 1;
-                    }
-                }
+            KeyCode::Down | KeyCode::Char('j')
+                if self.selected_panel == Panel::Log
+                    && self.log_scroll < self.logs.len().saturating_sub(1) =>
+            {
+                self.log_scroll += 1;
             }
-            KeyCode::Up | KeyCode::Char('k') => {
-                if self.selected_panel == Panel::Log {
-                    self.log_scroll = self.log_scroll.saturating_sub(1);
-                }
+            KeyCode::Up | KeyCode::Char('k') if self.selected_panel == Panel::Log => {
+                self.log_scroll = self.log_scroll.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Char('j')
+                if self.selected_panel == Panel::Pool
+                    && self.pool_selected + 1 < self.pool_agents.len() =>
+            {
+                self.pool_selected += 1;
+            }
+            KeyCode::Up | KeyCode::Char('k') if self.selected_panel == Panel::Pool => {
+                self.pool_selected = self.pool_selected.saturating_sub(1);
             }
+            KeyCode::Char('x') if self.selected_panel == Panel::Pool => self.stop_selected_agent(),
+            KeyCode::Char('w') => self.open_wrapper_picker(),
             _ => {}
         }
     }
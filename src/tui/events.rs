@@ -1,7 +1,7 @@
 //! Event handling for the TUI dashboard
 
 use anyhow::Result;
-use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::event::{self, Event, KeyEventKind};
 use std::time::Duration;
 
 use super::app::App;
@@ -19,7 +19,7 @@ use std::io;
 use std::time::Duration;
 
 /// Run the TUI dashboard
-pub fn run_dashboard(wrapper_pid: u32) -> Result<()> {
+pub fn run_dashboard(wrapper_pid: u32, color: bool) -> Result<()> {
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -28,7 +28,8 @@ pub fn run_dashboard(wrapper_pid: u32) -> Result<()> {
     let mut terminal = Terminal::new(backend)?;
 
     // Create app state
-    let app = App::new(wrapper_pid);
+    let mut app = App::new(wrapper_pid);
+    app.color = color;
 
     // Run the main loop
     let res = run_app(&mut terminal, app);
@@ -1,14 +1,15 @@
 //! UI rendering for the TUI dashboard
 
 use ratatui::{
+    buffer::Buffer,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap},
+    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Sparkline, Wrap},
     Frame,
 };
 
-use super::app::{App, LogLevel, Panel};
+use super::app::{App, LogLevel, Panel, WrapperHealth};
 use crate::wrapper::AgentState;
 
 /// Draw the entire UI
@@ -29,10 +30,45 @@ pub fn draw(f: &mut Frame, app: &mut App) {
     if app.show_help {
         draw_help_overlay(f);
     }
+
+    if app.wrapper_picker.is_some() {
+        draw_wrapper_picker(f, app);
+    }
+
+    if !app.color {
+        strip_colors(f.buffer_mut());
+    }
+}
+
+/// Remove all foreground/background colors, keeping text and modifiers
+fn strip_colors(buf: &mut Buffer) {
+    for cell in buf.content.iter_mut() {
+        cell.set_fg(Color::Reset);
+        cell.set_bg(Color::Reset);
+    }
+}
+
+/// Bordered panel block; the selected panel is marked in the title as well
+/// as by color so it stays visible without colors
+fn panel_block(title: &str, selected: bool) -> Block<'static> {
+    if selected {
+        Block::default()
+            .title(format!(" ▸ {} ", title))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+    } else {
+        Block::default()
+            .title(format!(" {} ", title))
+            .borders(Borders::ALL)
+    }
 }
 
 fn draw_header(f: &mut Frame, app: &App, area: Rect) {
-    let status = if let Some(state) = &app.shared_state {
+    let status = if app.wrapper_health == WrapperHealth::Exited {
+        ("Wrapper exited", Color::Red)
+    } else if app.wrapper_health == WrapperHealth::NotResponding {
+        ("Wrapper not responding", Color::Red)
+    } else if let Some(state) = &app.shared_state {
         match state.agent_status {
             AgentState::Running => ("Running", Color::Green),
             AgentState::Starting => ("Starting", Color::Yellow),
@@ -49,7 +85,7 @@ fn draw_header(f: &mut Frame, app: &App, area: Rect) {
         Span::raw("| Status: "),
         Span::styled(status.0, Style::default().fg(status.1)),
         Span::raw(" | "),
-        Span::raw("[q]uit [h]elp [r]estart [Tab] switch panel"),
+        Span::raw("[q]uit [h]elp [r]estart [w]rappers [Tab] switch panel"),
     ]);
 
     let header = Paragraph::new(title)
@@ -85,23 +121,34 @@ fn draw_body(f: &mut Frame, app: &mut App, area: Rect) {
 }
 
 fn draw_agent_panel(f: &mut Frame, app: &App, area: Rect) {
-    let selected = app.selected_panel == Panel::Agent;
-    let border_style = if selected {
-        Style::default().fg(Color::Cyan)
-    } else {
-        Style::default()
-    };
-
-    let block = Block::default()
-        .title(" Primary Agent ")
-        .borders(Borders::ALL)
-        .border_style(border_style);
+    let block = panel_block("Primary Agent", app.selected_panel == Panel::Agent);
 
     let inner = block.inner(area);
     f.render_widget(block, area);
 
+    // Status text on top, resource history at the bottom
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(4)])
+        .split(inner);
+    let inner = chunks[0];
+    draw_resource_history(f, app, chunks[1]);
+
     let mut lines = vec![];
 
+    // The state below is the last thing the wrapper wrote; say so if it's gone quiet
+    let banner = match app.wrapper_health {
+        WrapperHealth::Alive => None,
+        WrapperHealth::NotResponding => Some("!! Wrapper not responding - state below may be stale"),
+        WrapperHealth::Exited => Some("!! Wrapper exited - showing last known state (w to switch)"),
+    };
+    if let Some(banner) = banner {
+        lines.push(Line::from(Span::styled(
+            banner,
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        )));
+    }
+
     if let Some(state) = &app.shared_state {
         // Status line with icon
         let (icon, status_color) = match state.agent_status {
@@ -126,6 +173,31 @@ fn draw_agent_panel(f: &mut Frame, app: &App, area: Rect) {
         lines.push(Line::from(format!("Wrapper PID: {}", state.wrapper_pid)));
         lines.push(Line::from(format!("Uptime: {}", app.uptime_str())));
         lines.push(Line::from(format!("Restarts: {}", state.restart_count)));
+
+        if let Some(detail) = &state.exit_detail {
+            let color = match state.exit_code {
+                Some(0) => Color::Green,
+                None if state.agent_status != AgentState::Failed => Color::Yellow,
+                _ => Color::Red,
+            };
+            lines.push(Line::from(vec![
+                Span::raw("Last exit: "),
+                Span::styled(detail.clone(), Style::default().fg(color)),
+            ]));
+        }
+
+        if let Some(restart) = &state.last_restart {
+            lines.push(Line::from(format!(
+                "Last restart: {} ({})",
+                restart.trigger, restart.reason
+            )));
+            if let Some(kb) = restart.metrics.memory_kb {
+                lines.push(Line::from(format!("  Memory at restart: {} MB", kb / 1024)));
+            }
+            if let Some(secs) = restart.metrics.uptime_secs {
+                lines.push(Line::from(format!("  Agent uptime: {}s", secs)));
+            }
+        }
     } else {
         lines.push(Line::from(Span::styled(
             "Waiting for agent data...",
@@ -137,18 +209,40 @@ fn draw_agent_panel(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(content, inner);
 }
 
-fn draw_pool_panel(f: &mut Frame, app: &App, area: Rect) {
-    let selected = app.selected_panel == Panel::Pool;
-    let border_style = if selected {
-        Style::default().fg(Color::Cyan)
-    } else {
-        Style::default()
-    };
+/// Memory and CPU sparklines of the agent, newest sample on the right
+fn draw_resource_history(f: &mut Frame, app: &App, area: Rect) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Length(1), Constraint::Length(1), Constraint::Length(1)])
+        .split(area);
 
-    let block = Block::default()
-        .title(" Agent Pool ")
-        .borders(Borders::ALL)
-        .border_style(border_style);
+    // Only as many samples as fit, so the newest is always visible
+    let visible = app.resource_history.len().saturating_sub(area.width as usize);
+    let samples: Vec<(u64, f32)> = app.resource_history.iter().skip(visible).copied().collect();
+    let memory: Vec<u64> = samples.iter().map(|(mb, _)| *mb).collect();
+    let cpu: Vec<u64> = samples.iter().map(|(_, cpu)| cpu.round() as u64).collect();
+
+    let current = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    let memory_label = current(samples.last().map(|(mb, _)| format!("{} MB", mb)));
+    let cpu_label = current(samples.last().map(|(_, cpu)| format!("{:.0}%", cpu)));
+
+    f.render_widget(Paragraph::new(format!("Memory: {}", memory_label)), rows[0]);
+    f.render_widget(
+        Sparkline::default().data(&memory).style(Style::default().fg(Color::Magenta)),
+        rows[1],
+    );
+    f.render_widget(Paragraph::new(format!("CPU: {}", cpu_label)), rows[2]);
+    f.render_widget(
+        Sparkline::default()
+            .data(&cpu)
+            .max(cpu.iter().copied().max().unwrap_or(0).max(100))
+            .style(Style::default().fg(Color::Cyan)),
+        rows[3],
+    );
+}
+
+fn draw_pool_panel(f: &mut Frame, app: &App, area: Rect) {
+    let block = panel_block("Agent Pool", app.selected_panel == Panel::Pool);
 
     let inner = block.inner(area);
     f.render_widget(block, area);
@@ -163,16 +257,29 @@ fn draw_pool_panel(f: &mut Frame, app: &App, area: Rect) {
         let items: Vec<ListItem> = app
             .pool_agents
             .iter()
-            .map(|agent| {
+            .enumerate()
+            .map(|(i, agent)| {
+                let icon = match agent.status.split_whitespace().next().unwrap_or("") {
+                    "Running" | "Starting" => "▶",
+                    "Queued" => "…",
+                    "Paused" => "⏸",
+                    "Completed:" => "✓",
+                    "Failed:" => "✗",
+                    _ => "○",
+                };
                 let content = format!(
                     "{} {} - {} (iter: {}, {}s)",
-                    if agent.status == "Running" { "▶" } else { "✓" },
+                    icon,
                     &agent.id[..8.min(agent.id.len())],
                     agent.task,
                     agent.iterations,
                     agent.elapsed_secs
                 );
-                ListItem::new(content)
+                if app.selected_panel == Panel::Pool && i == app.pool_selected {
+                    ListItem::new(content).style(Style::default().add_modifier(Modifier::REVERSED))
+                } else {
+                    ListItem::new(content)
+                }
             })
             .collect();
 
@@ -182,17 +289,7 @@ fn draw_pool_panel(f: &mut Frame, app: &App, area: Rect) {
 }
 
 fn draw_locks_panel(f: &mut Frame, app: &App, area: Rect) {
-    let selected = app.selected_panel == Panel::Locks;
-    let border_style = if selected {
-        Style::default().fg(Color::Cyan)
-    } else {
-        Style::default()
-    };
-
-    let block = Block::default()
-        .title(" File Locks ")
-        .borders(Borders::ALL)
-        .border_style(border_style);
+    let block = panel_block("File Locks", app.selected_panel == Panel::Locks);
 
     let inner = block.inner(area);
     f.render_widget(block, area);
@@ -221,17 +318,7 @@ fn draw_locks_panel(f: &mut Frame, app: &App, area: Rect) {
 }
 
 fn draw_log_panel(f: &mut Frame, app: &App, area: Rect) {
-    let selected = app.selected_panel == Panel::Log;
-    let border_style = if selected {
-        Style::default().fg(Color::Cyan)
-    } else {
-        Style::default()
-    };
-
-    let block = Block::default()
-        .title(" Log ")
-        .borders(Borders::ALL)
-        .border_style(border_style);
+    let block = panel_block("Log", app.selected_panel == Panel::Log);
 
     let inner = block.inner(area);
     f.render_widget(block, area);
@@ -279,8 +366,10 @@ fn draw_help_overlay(f: &mut Frame) {
         Line::from("  Tab        Next panel"),
         Line::from("  Shift+Tab  Previous panel"),
         Line::from("  r          Restart agent"),
-        Line::from("  j, Down    Scroll down (in log)"),
-        Line::from("  k, Up      Scroll up (in log)"),
+        Line::from("  j, Down    Scroll down (in log) / next agent (in pool)"),
+        Line::from("  k, Up      Scroll up (in log) / previous agent (in pool)"),
+        Line::from("  x          Stop selected pool agent"),
+        Line::from("  w          Switch to another running wrapper"),
         Line::from(""),
         Line::from(Span::styled("Press any key to close", Style::default().fg(Color::Gray))),
     ];
@@ -292,6 +381,48 @@ fn draw_help_overlay(f: &mut Frame) {
     f.render_widget(paragraph, area);
 }
 
+fn draw_wrapper_picker(f: &mut Frame, app: &App) {
+    let Some(picker) = &app.wrapper_picker else {
+        return;
+    };
+    let area = centered_rect(60, 50, f.area());
+
+    f.render_widget(Clear, area);
+
+    let block = Block::default()
+        .title(" Switch Wrapper ")
+        .borders(Borders::ALL)
+        .style(Style::default().bg(Color::DarkGray));
+
+    let mut items: Vec<ListItem> = picker
+        .wrappers
+        .iter()
+        .enumerate()
+        .map(|(i, wrapper)| {
+            let marker = if wrapper.pid == app.wrapper_pid { "*" } else { " " };
+            let content = format!("{} {}", marker, crate::wrapper::describe_wrapper(wrapper));
+            if i == picker.selected {
+                ListItem::new(content).style(Style::default().add_modifier(Modifier::REVERSED))
+            } else {
+                ListItem::new(content)
+            }
+        })
+        .collect();
+    if items.is_empty() {
+        items.push(ListItem::new(Span::styled(
+            "No running wrappers found",
+            Style::default().fg(Color::Gray),
+        )));
+    }
+    items.push(ListItem::new(""));
+    items.push(ListItem::new(Span::styled(
+        "Enter to attach, Esc to cancel (* = current)",
+        Style::default().fg(Color::Gray),
+    )));
+
+    f.render_widget(List::new(items).block(block), area);
+}
+
 /// Helper function to create a centered rect
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
@@ -4,15 +4,26 @@ use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
 use serde_json::{self, json};
 use std::fs;
-use std::io::Write;
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::os::unix::net::UnixStream;
 use std::path::{Path, PathBuf};
-use std::process::{self, Command};
+use std::process::{self, Command, Stdio};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 
+use crate::health::{self, HealthSample, HealthSampler};
+use crate::housekeeping;
 use crate::privileges;
+use crate::notify::{self, NotificationKind, Notifications};
+use crate::paths;
+use crate::pty;
+use crate::report::{self, SessionEnd, SessionReport};
+use crate::restart::{RestartExplanation, RestartMetrics, RestartTrigger};
 
 // ============================================================================
 // Crash Cleanup Registry
@@ -37,16 +48,14 @@ fn register_cleanup(backup_path: Option<PathBuf>, target_path: Option<PathBuf>)
     }
 }
 
-/// Perform emergency cleanup (called from panic hook or signal handler)
+/// Perform emergency cleanup (called from the panic hook)
 fn emergency_cleanup() {
     if let Ok(guard) = CLEANUP_REGISTRY.lock() {
         if let Some(ref registry) = *guard {
-            // Restore .mcp.json from backup
+            // Restore .mcp.json from backup, unless another wrapper still uses it.
+            // Never wait for the lock here: the main thread may be holding it.
             if let (Some(ref backup), Some(ref target)) = (&registry.mcp_backup_path, &registry.mcp_target_path) {
-                if backup.exists() {
-                    let _ = fs::copy(backup, target);
-                    let _ = fs::remove_file(backup);
-                }
+                let _ = release_mcp_injection_with(backup, target, Path::new(MCP_LOCK_FILE), false);
             }
         }
     }
@@ -75,14 +84,9 @@ pub const BUILD_TIME: &str = env!("LAZARUS_BUILD_TIME");
 /// Git commit hash
 pub const GIT_HASH: &str = env!("LAZARUS_GIT_HASH");
 
-const SIGNAL_FILE_PREFIX: &str = "/tmp/lazarus-mcp-";
-
 /// Target file for MCP config
 const MCP_TARGET_FILE: &str = ".mcp.json";
 
-/// Shared state file for TUI/MCP communication
-const SHARED_STATE_FILE: &str = "/tmp/lazarus-mcp-state-";
-
 /// Shared state accessible by TUI and MCP server
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct SharedState {
@@ -100,6 +104,31 @@ pub struct SharedState {
     pub uptime_secs: u64,
     /// Start timestamp (unix epoch)
     pub started_at: u64,
+    /// Why the most recent restart happened
+    #[serde(default)]
+    pub last_restart: Option<RestartExplanation>,
+    /// When the wrapper last wrote this state (unix epoch)
+    #[serde(default)]
+    pub heartbeat_ts: u64,
+    /// Why the agent last stopped, e.g. "exited with code 1" or "killed by SIGKILL"
+    #[serde(default)]
+    pub exit_detail: Option<String>,
+    /// The agent's last exit code, if it exited on its own
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+}
+
+/// How often the wrapper refreshes the shared state while the agent runs
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Heartbeats older than this mean the wrapper is no longer updating its state
+const HEARTBEAT_STALE_SECS: u64 = 5;
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
@@ -121,38 +150,105 @@ impl SharedState {
             agent_status: AgentState::Starting,
             restart_count: 0,
             uptime_secs: 0,
-            started_at: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map(|d| d.as_secs())
-                .unwrap_or(0),
+            started_at: unix_now(),
+            last_restart: None,
+            heartbeat_ts: 0,
+            exit_detail: None,
+            exit_code: None,
         }
     }
 
-    /// Get the shared state file path
+    /// Get the shared state file path (in the runtime directory, see `paths`)
     pub fn state_file_path() -> PathBuf {
-        PathBuf::from(format!("{}{}", SHARED_STATE_FILE, process::id()))
+        paths::runtime_dir().join(paths::state_file_name(process::id()))
     }
 
     /// Write state to file for other processes to read
-    pub fn save(&self) -> Result<()> {
+    ///
+    /// Also refreshes the uptime and heartbeat timestamp.
+    pub fn save(&mut self) -> Result<()> {
+        self.heartbeat_ts = unix_now();
+        self.uptime_secs = self.heartbeat_ts.saturating_sub(self.started_at);
         let path = Self::state_file_path();
         let json = serde_json::to_string_pretty(self)?;
         fs::write(&path, json)?;
         Ok(())
     }
 
+    /// Whether the heartbeat is too old to trust this state
+    ///
+    /// State files written before heartbeats existed are never considered stale.
+    pub fn is_stale(&self, now: u64) -> bool {
+        self.heartbeat_ts != 0 && now.saturating_sub(self.heartbeat_ts) > HEARTBEAT_STALE_SECS
+    }
+
     /// Load state from file
     pub fn load(wrapper_pid: u32) -> Result<Self> {
-        let path = PathBuf::from(format!("{}{}", SHARED_STATE_FILE, wrapper_pid));
+        let path = paths::wrapper_dir(wrapper_pid).join(paths::state_file_name(wrapper_pid));
         let content = fs::read_to_string(&path)?;
         let state: Self = serde_json::from_str(&content)?;
         Ok(state)
     }
 }
 
+/// A live wrapper found through its state file
+pub struct RunningWrapper {
+    pub pid: u32,
+    /// The wrapper's shared state, if it could be read
+    pub state: Option<SharedState>,
+}
+
+/// Find live wrappers by their state files in `dirs`, oldest first
+///
+/// State files left behind by wrappers that are no longer running are removed.
+pub fn find_running_wrappers(dirs: &[PathBuf]) -> Vec<RunningWrapper> {
+    let mut wrappers: Vec<RunningWrapper> = Vec::new();
+    for entries in dirs.iter().filter_map(|dir| fs::read_dir(dir).ok()) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(pid) = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_prefix("lazarus-mcp-state-"))
+                .and_then(|pid| pid.parse::<u32>().ok())
+            else {
+                continue;
+            };
+
+            if fs::metadata(format!("/proc/{}", pid)).is_err() {
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            if wrappers.iter().any(|w| w.pid == pid) {
+                continue;
+            }
+            let state = fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| serde_json::from_str::<SharedState>(&content).ok());
+            wrappers.push(RunningWrapper { pid, state });
+        }
+    }
+    wrappers.sort_by_key(|w| (w.state.as_ref().map(|s| s.started_at), w.pid));
+    wrappers
+}
+
+/// One line describing a running wrapper, for the picker
+pub fn describe_wrapper(wrapper: &RunningWrapper) -> String {
+    match &wrapper.state {
+        Some(state) => format!(
+            "PID {}  {}  {:?}  up {}",
+            wrapper.pid,
+            state.agent_name,
+            state.agent_status,
+            report::format_duration(state.uptime_secs)
+        ),
+        None => format!("PID {}", wrapper.pid),
+    }
+}
+
 /// Get the signal file path for this wrapper instance
 pub fn signal_file_path() -> PathBuf {
-    PathBuf::from(format!("{}{}", SIGNAL_FILE_PREFIX, process::id()))
+    paths::runtime_dir().join(paths::signal_file_name(process::id()))
 }
 
 /// Parsed restart signal
@@ -160,17 +256,25 @@ pub fn signal_file_path() -> PathBuf {
 struct ParsedRestartSignal {
     reason: String,
     prompt: Option<String>,
+    trigger: RestartTrigger,
+    metrics: RestartMetrics,
 }
 
-/// Backup path for .mcp.json
-fn mcp_backup_path() -> PathBuf {
-    PathBuf::from(".mcp.json.lazarus-backup")
+/// Name of the .mcp.json backup, which also marks an active injection
+pub const MCP_BACKUP_FILE: &str = ".mcp.json.lazarus-backup";
+
+/// Lockfile serializing wrappers that share one .mcp.json, listing their PIDs
+const MCP_LOCK_FILE: &str = ".mcp.json.lazarus.lock";
+
+/// Backup path for the .mcp.json in `dir`
+fn mcp_backup_path(dir: &Path) -> PathBuf {
+    dir.join(MCP_BACKUP_FILE)
 }
 
-/// Restore .mcp.json from backup if a previous run crashed
-fn restore_mcp_if_dirty() {
-    let backup = mcp_backup_path();
-    let target = Path::new(MCP_TARGET_FILE);
+/// Restore the .mcp.json in `dir` from backup if a previous run crashed
+pub fn restore_mcp_if_dirty(dir: &Path) {
+    let backup = mcp_backup_path(dir);
+    let target = dir.join(MCP_TARGET_FILE);
 
     if backup.exists() {
         warn!("Found .mcp.json backup from previous crash - restoring");
@@ -180,9 +284,9 @@ fn restore_mcp_if_dirty() {
 
         if is_empty_marker {
             // Original didn't exist, delete the injected file
-            let _ = fs::remove_file(target);
+            let _ = fs::remove_file(&target);
             info!("Removed injected .mcp.json (original didn't exist)");
-        } else if let Err(e) = fs::copy(&backup, target) {
+        } else if let Err(e) = fs::copy(&backup, &target) {
             warn!("Failed to restore .mcp.json from backup: {}", e);
         } else {
             info!("Restored .mcp.json from backup");
@@ -192,24 +296,345 @@ fn restore_mcp_if_dirty() {
     }
 }
 
+/// Find a lazarus-mcp MCP server the user already configured under another name
+///
+/// Injecting a second copy would make the agent see every lazarus-mcp tool twice.
+fn find_existing_lazarus_server(config: &serde_json::Value) -> Option<String> {
+    let servers = config.get("mcpServers")?.as_object()?;
+    servers.iter().find_map(|(name, server)| {
+        if name == "lazarus-mcp" {
+            // Our own key is overwritten, not duplicated
+            return None;
+        }
+        let command = server.get("command")?.as_str()?;
+        let is_lazarus = Path::new(command)
+            .file_name()
+            .map(|n| n.to_string_lossy().starts_with("lazarus-mcp"))
+            .unwrap_or(false);
+        let is_server_mode = server
+            .get("args")
+            .and_then(|a| a.as_array())
+            .map(|args| args.iter().any(|a| a.as_str() == Some("--mcp-server")))
+            .unwrap_or(false);
+        (is_lazarus && is_server_mode).then(|| name.clone())
+    })
+}
+
+/// The .mcp.json server entry that runs this executable as an MCP server
+fn lazarus_server_entry(exe: &Path) -> serde_json::Value {
+    json!({
+        "command": exe.to_string_lossy(),
+        "args": ["--mcp-server"]
+    })
+}
+
+/// Split a .mcp.json edited during the session into the user's own config
+/// (what to restore on exit) and what the agent should see (the user's
+/// config plus lazarus-mcp, unless they configured it themselves)
+fn remerge_mcp_config(edited: &serde_json::Value, exe: &Path) -> (serde_json::Value, serde_json::Value) {
+    let mut user = edited.clone();
+    if let Some(servers) = user.get_mut("mcpServers").and_then(|s| s.as_object_mut()) {
+        servers.remove("lazarus-mcp");
+    }
+
+    let mut merged = user.clone();
+    if find_existing_lazarus_server(&user).is_none() {
+        if !merged.get("mcpServers").is_some_and(|s| s.is_object()) {
+            merged["mcpServers"] = json!({});
+        }
+        merged["mcpServers"]["lazarus-mcp"] = lazarus_server_entry(exe);
+    }
+    (user, merged)
+}
+
+/// Replace a file's contents so readers never see a partial write
+fn write_atomic(path: &Path, content: &str) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".lazarus-tmp");
+    let tmp = PathBuf::from(tmp);
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, path)
+}
+
+/// Re-apply the injection after the user edits .mcp.json during the session
+///
+/// The user's edits become the new backup (so they survive the restore on
+/// exit) and lazarus-mcp is merged back in if the edit dropped it.
+/// Returns the content now in .mcp.json.
+fn reinject_after_edit(
+    backup_path: &Path,
+    target_path: &Path,
+    transform: Option<&str>,
+    last_written: &str,
+) -> Result<String> {
+    let content = fs::read_to_string(target_path).context("Failed to read .mcp.json")?;
+    if content == last_written {
+        return Ok(content);
+    }
+    let edited: serde_json::Value = serde_json::from_str(&content).context("Edited .mcp.json is not valid JSON")?;
+    let exe = std::env::current_exe().context("Failed to get current executable path")?;
+    let (user, mut merged) = remerge_mcp_config(&edited, &exe);
+
+    // An empty backup marks a .mcp.json that didn't exist; keep it that way
+    // unless the user has since added something of their own
+    let had_no_config = fs::metadata(backup_path).map(|m| m.len() == 0).unwrap_or(false);
+    if !(had_no_config && user == json!({ "mcpServers": {} })) {
+        write_atomic(backup_path, &serde_json::to_string_pretty(&user)?)?;
+    }
+
+    if let Some(command) = transform {
+        match transform_mcp_config(&merged, command) {
+            Ok(transformed) => merged = transformed,
+            Err(e) => warn!("{:#}; using the untransformed config", e),
+        }
+    }
+    if merged == edited {
+        return Ok(content);
+    }
+
+    let merged = serde_json::to_string_pretty(&merged)?;
+    write_atomic(target_path, &merged)?;
+    info!("Re-applied lazarus-mcp injection after .mcp.json was edited");
+    Ok(merged)
+}
+
+/// Re-apply the injection on request, e.g. after the MCP transform's output changed
+///
+/// If .mcp.json was edited since `last_written`, this is handled as an edit.
+/// Otherwise the config is rebuilt from the backup (the user's own config),
+/// so the previous transform output isn't mistaken for user edits.
+/// Returns the content now in .mcp.json.
+fn reload_mcp_config(
+    backup_path: &Path,
+    target_path: &Path,
+    transform: Option<&str>,
+    last_written: &str,
+) -> Result<String> {
+    let content = fs::read_to_string(target_path).context("Failed to read .mcp.json")?;
+    if content != last_written {
+        return reinject_after_edit(backup_path, target_path, transform, last_written);
+    }
+
+    let backup = fs::read_to_string(backup_path).context("Failed to read the .mcp.json backup")?;
+    let user: serde_json::Value = if backup.is_empty() {
+        json!({ "mcpServers": {} })
+    } else {
+        serde_json::from_str(&backup).context("The .mcp.json backup is not valid JSON")?
+    };
+    let exe = std::env::current_exe().context("Failed to get current executable path")?;
+    let (_, mut merged) = remerge_mcp_config(&user, &exe);
+    if let Some(command) = transform {
+        match transform_mcp_config(&merged, command) {
+            Ok(transformed) => merged = transformed,
+            Err(e) => warn!("{:#}; using the untransformed config", e),
+        }
+    }
+
+    let merged = serde_json::to_string_pretty(&merged)?;
+    write_atomic(target_path, &merged)?;
+    Ok(merged)
+}
+
+/// Ask the wrapper to restart the agent, as if through `restart_agent`
+fn request_own_restart(reason: &str) -> Result<()> {
+    let signal = json!({
+        "action": "restart",
+        "timestamp": unix_now(),
+        "reason": reason,
+        "trigger": RestartTrigger::McpCodeChange,
+    });
+    write_atomic(&signal_file_path(), &serde_json::to_string_pretty(&signal)?)?;
+    Ok(())
+}
+
+/// Watch .mcp.json for edits until `watching` is cleared
+///
+/// Uses inotify on the containing directory, since editors often replace the
+/// file rather than writing to it. Setting `reload` (done on SIGHUP) forces
+/// the injection to be re-applied even without an edit, re-running the MCP
+/// transform; with `restart_on_reload` the agent is then restarted so it
+/// reads the new config.
+fn spawn_mcp_watcher(
+    backup_path: PathBuf,
+    target_path: PathBuf,
+    transform: Option<String>,
+    watching: Arc<AtomicBool>,
+    reload: Arc<AtomicBool>,
+    restart_on_reload: bool,
+) -> Option<std::thread::JoinHandle<()>> {
+    let dir = match target_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let dir = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+    if fd < 0 {
+        warn!("Failed to watch .mcp.json for edits: {}", std::io::Error::last_os_error());
+        return None;
+    }
+    let inotify = unsafe { fs::File::from_raw_fd(fd) };
+    if unsafe { libc::inotify_add_watch(fd, dir.as_ptr(), libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO) } < 0 {
+        warn!("Failed to watch .mcp.json for edits: {}", std::io::Error::last_os_error());
+        return None;
+    }
+
+    Some(std::thread::spawn(move || {
+        let mut last_written = fs::read_to_string(&target_path).unwrap_or_default();
+        let mut buf = [0u8; 4096];
+        while watching.load(Ordering::SeqCst) {
+            let mut pfd = libc::pollfd {
+                fd: inotify.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let edited = unsafe { libc::poll(&mut pfd, 1, 500) } > 0;
+            if edited {
+                while matches!((&inotify).read(&mut buf), Ok(n) if n > 0) {}
+            }
+
+            if reload.swap(false, Ordering::SeqCst) {
+                match reload_mcp_config(&backup_path, &target_path, transform.as_deref(), &last_written) {
+                    Ok(content) => {
+                        last_written = content;
+                        info!("Reloaded the MCP config in .mcp.json");
+                        if restart_on_reload {
+                            if let Err(e) = request_own_restart("MCP config reloaded") {
+                                warn!("Failed to restart the agent after reloading the MCP config: {:#}", e);
+                            }
+                        }
+                    }
+                    Err(e) => warn!("Failed to reload the MCP config: {:#}", e),
+                }
+            } else if edited {
+                match reinject_after_edit(&backup_path, &target_path, transform.as_deref(), &last_written) {
+                    Ok(content) => last_written = content,
+                    Err(e) => debug!("Not re-injecting into .mcp.json: {:#}", e),
+                }
+            }
+        }
+    }))
+}
+
+/// How long an MCP transform may run before it is killed
+const MCP_TRANSFORM_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Pipe an MCP config through a user-supplied shell command (stdin to stdout)
+///
+/// The command's output must be a JSON object; anything else is an error.
+fn transform_mcp_config(config: &serde_json::Value, command: &str) -> Result<serde_json::Value> {
+    transform_mcp_config_within(config, command, MCP_TRANSFORM_TIMEOUT)
+}
+
+/// Run an MCP transform, killing it (and anything it started) after `timeout`
+fn transform_mcp_config_within(
+    config: &serde_json::Value,
+    command: &str,
+    timeout: Duration,
+) -> Result<serde_json::Value> {
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .process_group(0)
+        .spawn()
+        .with_context(|| format!("Failed to run MCP transform '{}'", command))?;
+
+    // Feed stdin and drain stdout from threads so a transform that writes
+    // before reading can't deadlock, and a hung one can't block us
+    let input = serde_json::to_vec_pretty(config)?;
+    let mut stdin = child.stdin.take().context("MCP transform has no stdin")?;
+    std::thread::spawn(move || stdin.write_all(&input));
+    let mut stdout = child.stdout.take().context("MCP transform has no stdout")?;
+    let (output_tx, output_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = output_tx.send(stdout.read_to_end(&mut output).map(|_| output));
+    });
+
+    let deadline = std::time::Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if std::time::Instant::now() >= deadline {
+            let _ = signal::kill(Pid::from_raw(-(child.id() as i32)), Signal::SIGKILL);
+            let _ = child.wait();
+            anyhow::bail!("MCP transform '{}' timed out after {}s", command, timeout.as_secs_f32());
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    if !status.success() {
+        anyhow::bail!("MCP transform '{}' exited with {}", command, status);
+    }
+    // Output still held open by a background process of the transform isn't waited for
+    let output = output_rx
+        .recv_timeout(deadline.saturating_duration_since(std::time::Instant::now()))
+        .map_err(|_| anyhow::anyhow!("MCP transform '{}' did not close its output", command))??;
+
+    let transformed: serde_json::Value = serde_json::from_slice(&output)
+        .with_context(|| format!("MCP transform '{}' did not output valid JSON", command))?;
+    if !transformed.is_object() {
+        anyhow::bail!("MCP transform '{}' did not output a JSON object", command);
+    }
+    Ok(transformed)
+}
+
+/// Add the lazarus-mcp server to a config, then pipe it through `transform`
+///
+/// A failing transform falls back to the untransformed config.
+fn injected_mcp_config(mut config: serde_json::Value, exe: &Path, transform: Option<&str>) -> serde_json::Value {
+    // Ensure mcpServers object exists
+    if config.get("mcpServers").is_none() {
+        config["mcpServers"] = json!({});
+    }
+
+    // Inject lazarus-mcp server
+    config["mcpServers"]["lazarus-mcp"] = lazarus_server_entry(exe);
+
+    if let Some(command) = transform {
+        match transform_mcp_config(&config, command) {
+            Ok(transformed) => {
+                info!("Applied MCP transform '{}'", command);
+                config = transformed;
+            }
+            Err(e) => warn!("{:#}; using the untransformed config", e),
+        }
+    }
+    config
+}
+
 /// Inject lazarus-mcp into .mcp.json (with backup for restore on exit)
-fn inject_mcp_server() -> Result<(PathBuf, PathBuf)> {
+///
+/// If `transform` is given, the resulting config is piped through it before
+/// being written; a failing transform falls back to the untransformed config.
+/// Returns `None` if lazarus-mcp is already configured, in which case nothing is modified.
+pub fn inject_mcp_server(dir: &Path, transform: Option<&str>) -> Result<Option<(PathBuf, PathBuf)>> {
     let aegis_path = std::env::current_exe()
         .context("Failed to get current executable path")?;
 
-    let mcp_path = PathBuf::from(MCP_TARGET_FILE);
-    let backup_path = mcp_backup_path();
+    let mcp_path = dir.join(MCP_TARGET_FILE);
+    let backup_path = mcp_backup_path(dir);
 
     // Read existing config or create empty one
-    let mut config: serde_json::Value = if mcp_path.exists() {
-        // Backup the original first
-        fs::copy(&mcp_path, &backup_path)
-            .context("Failed to backup .mcp.json")?;
-
+    let config: serde_json::Value = if mcp_path.exists() {
         let content = fs::read_to_string(&mcp_path)
             .context("Failed to read existing .mcp.json")?;
-        serde_json::from_str(&content)
-            .context("Failed to parse existing .mcp.json")?
+        let config = serde_json::from_str(&content)
+            .context("Failed to parse existing .mcp.json")?;
+
+        if let Some(name) = find_existing_lazarus_server(&config) {
+            warn!(
+                "lazarus-mcp is already configured in .mcp.json as '{}'; skipping injection to avoid duplicate tools",
+                name
+            );
+            return Ok(None);
+        }
+
+        // Backup the original before modifying it
+        fs::copy(&mcp_path, &backup_path)
+            .context("Failed to backup .mcp.json")?;
+        config
     } else {
         // Create backup marker (empty file) so we know to delete .mcp.json on restore
         fs::write(&backup_path, "")
@@ -217,27 +642,139 @@ fn inject_mcp_server() -> Result<(PathBuf, PathBuf)> {
         json!({ "mcpServers": {} })
     };
 
-    // Ensure mcpServers object exists
-    if config.get("mcpServers").is_none() {
-        config["mcpServers"] = json!({});
-    }
-
-    // Inject lazarus-mcp server
-    config["mcpServers"]["lazarus-mcp"] = json!({
-        "command": aegis_path.to_string_lossy(),
-        "args": ["--mcp-server"]
-    });
+    let config = injected_mcp_config(config, &aegis_path, transform);
 
     // Write modified config
     let content = serde_json::to_string_pretty(&config)?;
     fs::write(&mcp_path, &content)?;
 
     info!("Injected lazarus-mcp into .mcp.json (backup at {})", backup_path.display());
-    Ok((backup_path, mcp_path))
+    Ok(Some((backup_path, mcp_path)))
+}
+
+/// Exclusive advisory lock on the .mcp.json lockfile
+///
+/// The lockfile holds the PIDs of the wrappers using the current injection,
+/// one per line. The lock is released when this is dropped.
+struct McpLock {
+    file: fs::File,
+}
+
+impl McpLock {
+    /// Lock the lockfile, creating it if needed
+    ///
+    /// Without `wait`, returns `WouldBlock` if another process holds the lock.
+    fn acquire(path: &Path, wait: bool) -> std::io::Result<Self> {
+        use std::os::unix::fs::MetadataExt;
+
+        loop {
+            let file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+            let operation = if wait { libc::LOCK_EX } else { libc::LOCK_EX | libc::LOCK_NB };
+            if unsafe { libc::flock(file.as_raw_fd(), operation) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            // The last wrapper out removes the lockfile; if that happened while
+            // we waited, we locked a file nobody else will see
+            let locked = file.metadata()?;
+            match fs::metadata(path) {
+                Ok(current) if current.ino() == locked.ino() && current.dev() == locked.dev() => {
+                    return Ok(Self { file })
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    /// PIDs of the wrappers using the injection, skipping any that have died
+    fn holders(&mut self) -> Vec<u32> {
+        let mut content = String::new();
+        let _ = (&self.file).read_to_string(&mut content);
+        content
+            .lines()
+            .filter_map(|line| line.trim().parse::<u32>().ok())
+            .filter(|pid| fs::metadata(format!("/proc/{}", pid)).is_ok())
+            .collect()
+    }
+
+    fn set_holders(&mut self, pids: &[u32]) -> std::io::Result<()> {
+        use std::io::{Seek, SeekFrom};
+
+        let content: String = pids.iter().map(|pid| format!("{}\n", pid)).collect();
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(content.as_bytes())
+    }
+}
+
+/// Inject lazarus-mcp into .mcp.json, or share the injection of another wrapper
+/// already running in this directory
+///
+/// Only the first wrapper in backs up and modifies .mcp.json; later ones are
+/// recorded in the lockfile so the last one out restores it.
+pub fn acquire_mcp_injection(transform: Option<&str>) -> Result<Option<(PathBuf, PathBuf)>> {
+    // An empty directory keeps the paths relative to the current one
+    acquire_mcp_injection_in(Path::new(""), transform)
+}
+
+fn acquire_mcp_injection_in(dir: &Path, transform: Option<&str>) -> Result<Option<(PathBuf, PathBuf)>> {
+    let lock_path = dir.join(MCP_LOCK_FILE);
+    let mut lock = McpLock::acquire(&lock_path, true).context("Failed to lock .mcp.json")?;
+    let mut holders = lock.holders();
+
+    let paths = if holders.is_empty() {
+        // Nobody is using a leftover backup, so a previous run crashed
+        restore_mcp_if_dirty(dir);
+        inject_mcp_server(dir, transform)?
+    } else if mcp_backup_path(dir).exists() {
+        info!("Sharing the .mcp.json injection of wrapper {}", holders[0]);
+        Some((mcp_backup_path(dir), dir.join(MCP_TARGET_FILE)))
+    } else {
+        // The other wrapper found lazarus-mcp already configured
+        None
+    };
+
+    if paths.is_some() {
+        holders.push(process::id());
+        lock.set_holders(&holders).context("Failed to update .mcp.json lockfile")?;
+    } else if holders.is_empty() {
+        drop(lock);
+        let _ = fs::remove_file(&lock_path);
+    }
+    Ok(paths)
+}
+
+/// Stop using the injection; the last wrapper out restores .mcp.json
+pub fn release_mcp_injection(backup_path: &Path, target_path: &Path) {
+    if let Err(e) = release_mcp_injection_with(backup_path, target_path, Path::new(MCP_LOCK_FILE), true) {
+        warn!("Failed to lock .mcp.json ({}); restoring it anyway", e);
+        restore_mcp_config(backup_path, target_path);
+    }
+}
+
+fn release_mcp_injection_with(
+    backup_path: &Path,
+    target_path: &Path,
+    lock_path: &Path,
+    wait: bool,
+) -> std::io::Result<()> {
+    let mut lock = McpLock::acquire(lock_path, wait)?;
+    let own_pid = process::id();
+    let holders: Vec<u32> = lock.holders().into_iter().filter(|&pid| pid != own_pid).collect();
+
+    if holders.is_empty() {
+        restore_mcp_config(backup_path, target_path);
+        // Remove while locked; waiters notice and reopen
+        let _ = fs::remove_file(lock_path);
+    } else {
+        info!("Leaving .mcp.json injected for wrapper {}", holders[0]);
+        lock.set_holders(&holders)?;
+    }
+    Ok(())
 }
 
 /// Remove lazarus-mcp from .mcp.json (restore from backup)
-fn restore_mcp_config(backup_path: &Path, target_path: &Path) {
+pub fn restore_mcp_config(backup_path: &Path, target_path: &Path) {
     if backup_path.exists() {
         // Check if backup is empty (meaning original didn't exist)
         if fs::metadata(backup_path).map(|m| m.len() == 0).unwrap_or(false) {
@@ -263,6 +800,21 @@ pub fn print_version_info() {
     println!("  Git:   {}", GIT_HASH);
 }
 
+/// Version information as JSON, for scripts
+fn version_json() -> serde_json::Value {
+    json!({
+        "name": "lazarus-mcp",
+        "version": VERSION,
+        "build_time": BUILD_TIME,
+        "git_hash": GIT_HASH,
+    })
+}
+
+/// Display version information as a JSON object (`--version --json`)
+pub fn print_version_json() {
+    println!("{}", serde_json::to_string_pretty(&version_json()).unwrap_or_default());
+}
+
 /// Check if there's a restart signal and parse it
 fn check_restart_signal() -> Option<ParsedRestartSignal> {
     let path = signal_file_path();
@@ -280,24 +832,442 @@ fn check_restart_signal() -> Option<ParsedRestartSignal> {
                 let prompt = parsed.get("prompt")
                     .and_then(|p| p.as_str())
                     .map(|s| s.to_string());
-                return Some(ParsedRestartSignal { reason, prompt });
+                let trigger = parsed.get("trigger")
+                    .and_then(|t| serde_json::from_value(t.clone()).ok())
+                    .unwrap_or_default();
+                let metrics = parsed.get("metrics")
+                    .and_then(|m| serde_json::from_value(m.clone()).ok())
+                    .unwrap_or_default();
+                return Some(ParsedRestartSignal { reason, prompt, trigger, metrics });
             }
 
             // Fallback: treat content as reason
             return Some(ParsedRestartSignal {
                 reason: content,
                 prompt: None,
+                trigger: RestartTrigger::default(),
+                metrics: RestartMetrics::default(),
             });
         }
     }
     None
 }
 
+/// How often the temp file budget is re-checked while running
+const TMP_BUDGET_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Options controlling wrapper behavior
+#[derive(Debug, Clone)]
+pub struct WrapperOptions {
+    /// Auto-inject lazarus-mcp into .mcp.json
+    pub inject_mcp: bool,
+    /// Shell command the injected .mcp.json is piped through before writing
+    pub mcp_transform: Option<String>,
+    /// Append agent health samples to this JSONL file
+    pub health_history: Option<PathBuf>,
+    /// Maximum total size of lazarus-mcp temp files, in bytes
+    pub tmp_budget: Option<u64>,
+    /// Run the agent attached to a pseudo-terminal
+    pub pty: bool,
+    /// Use ANSI escape sequences on stdout (e.g. clearing the screen on restart)
+    pub color: bool,
+    /// Notification backend spec (see `notify::parse_backend`)
+    pub notify: Option<String>,
+    /// Events to notify about (default: all)
+    pub notify_events: Option<String>,
+    /// Print a session report to stderr on exit
+    pub report: bool,
+    /// Also write the session report to this file
+    pub report_file: Option<PathBuf>,
+    /// User to run the agent as (name, uid or uid:gid) instead of the sudo user
+    pub run_as: Option<String>,
+    /// Restart the agent after SIGHUP reloads the injected MCP config
+    pub restart_on_reload: bool,
+    /// Only pass these environment variables (plus the defaults) to the agent
+    pub env_allow: Vec<String>,
+    /// Don't keep the default variables (PATH, HOME, ...) when restricting the environment
+    pub env_clear: bool,
+}
+
+impl WrapperOptions {
+    /// Whether the agent's environment is restricted (`--env-allow`/`--env-clear`)
+    fn restricts_env(&self) -> bool {
+        self.env_clear || !self.env_allow.is_empty()
+    }
+}
+
+impl Default for WrapperOptions {
+    fn default() -> Self {
+        Self {
+            inject_mcp: true,
+            mcp_transform: None,
+            health_history: None,
+            tmp_budget: None,
+            pty: false,
+            color: true,
+            notify: None,
+            notify_events: None,
+            report: false,
+            report_file: None,
+            run_as: None,
+            restart_on_reload: false,
+            env_allow: Vec::new(),
+            env_clear: false,
+        }
+    }
+}
+
+/// Periodically enforce the temp file budget until the wrapper stops
+fn spawn_tmp_budget_thread(budget: u64, running: Arc<AtomicBool>) {
+    std::thread::spawn(move || {
+        // The runtime directory, and wherever older versions left their files
+        let dirs = paths::search_dirs();
+        while running.load(Ordering::SeqCst) {
+            let report = housekeeping::enforce_tmp_budget(&dirs, budget);
+            debug!("Temp files use {} bytes (budget {})", report.total_bytes, budget);
+
+            // Sleep in short steps so shutdown isn't delayed
+            let start = std::time::Instant::now();
+            while start.elapsed() < TMP_BUDGET_INTERVAL && running.load(Ordering::SeqCst) {
+                std::thread::sleep(Duration::from_millis(500));
+            }
+        }
+    });
+}
+
+/// Wakes the supervision loop as soon as something may need handling
+///
+/// Child state changes (SIGCHLD) and shutdown signals arrive through a
+/// self-pipe, and restart signal files through inotify on their directory,
+/// so the loop can block instead of polling the child. The directory also
+/// holds the state and health files, so only events for the signal file wake
+/// the loop.
+struct LoopWaker {
+    /// Read end of the self-pipe and the signal registrations feeding it
+    signals: Option<(UnixStream, Vec<signal_hook::SigId>)>,
+    /// Non-blocking inotify instance watching the signal file directory
+    inotify: Option<fs::File>,
+    /// Name of the signal file within that directory
+    signal_file: std::ffi::OsString,
+}
+
+impl LoopWaker {
+    fn new() -> Self {
+        Self::with_signal_file(paths::signal_file_name(process::id()))
+    }
+
+    fn with_signal_file(signal_file: impl Into<std::ffi::OsString>) -> Self {
+        let signals = Self::register_signals()
+            .map_err(|e| warn!("Failed to watch for child exits, falling back to polling: {}", e))
+            .ok();
+        let inotify = Self::watch_signal_dir()
+            .map_err(|e| debug!("Failed to watch for restart signals, checking periodically: {}", e))
+            .ok();
+        Self { signals, inotify, signal_file: signal_file.into() }
+    }
+
+    fn register_signals() -> std::io::Result<(UnixStream, Vec<signal_hook::SigId>)> {
+        use signal_hook::consts::{SIGCHLD, SIGINT, SIGTERM};
+
+        let (read, write) = UnixStream::pair()?;
+        read.set_nonblocking(true)?;
+        let mut ids = Vec::new();
+        for sig in [SIGCHLD, SIGINT, SIGTERM] {
+            match signal_hook::low_level::pipe::register(sig, write.try_clone()?) {
+                Ok(id) => ids.push(id),
+                Err(e) => {
+                    for id in ids {
+                        signal_hook::low_level::unregister(id);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok((read, ids))
+    }
+
+    fn watch_signal_dir() -> std::io::Result<fs::File> {
+        let dir = std::ffi::CString::new(paths::runtime_dir().as_os_str().as_bytes())?;
+
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let inotify = unsafe { fs::File::from_raw_fd(fd) };
+        let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+        if unsafe { libc::inotify_add_watch(fd, dir.as_ptr(), mask) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(inotify)
+    }
+
+    /// Block until woken or `timeout` elapses
+    fn wait(&self, timeout: Duration) {
+        let Some((pipe, _)) = &self.signals else {
+            std::thread::sleep(timeout.min(Duration::from_millis(100)));
+            return;
+        };
+
+        let deadline = std::time::Instant::now() + timeout;
+        let mut buf = [0u8; 4096];
+        loop {
+            let mut fds = vec![libc::pollfd {
+                fd: pipe.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            }];
+            if let Some(inotify) = &self.inotify {
+                fds.push(libc::pollfd {
+                    fd: inotify.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                });
+            }
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            let timeout_ms = remaining.as_millis().min(i32::MAX as u128) as libc::c_int;
+            unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout_ms) };
+
+            // Drain both so the next wait blocks again
+            let mut woken = false;
+            while let Ok(1..) = (&*pipe).read(&mut buf) {
+                woken = true;
+            }
+            if let Some(inotify) = &self.inotify {
+                while let Ok(n @ 1..) = (&*inotify).read(&mut buf) {
+                    woken |= inotify_names(&buf[..n], self.signal_file.as_bytes());
+                }
+            }
+            if woken || std::time::Instant::now() >= deadline {
+                return;
+            }
+        }
+    }
+}
+
+/// Whether a buffer of inotify events includes one for the file `name`
+///
+/// A queue overflow counts too, since the event may have been lost.
+fn inotify_names(events: &[u8], name: &[u8]) -> bool {
+    let header = std::mem::size_of::<libc::inotify_event>();
+    let mut offset = 0;
+    while offset + header <= events.len() {
+        let event: libc::inotify_event =
+            unsafe { std::ptr::read_unaligned(events[offset..].as_ptr().cast()) };
+        if event.mask & libc::IN_Q_OVERFLOW != 0 {
+            return true;
+        }
+        let start = offset + header;
+        let end = (start + event.len as usize).min(events.len());
+        // The name is padded with NULs
+        let event_name = events[start..end].split(|&b| b == 0).next().unwrap_or_default();
+        if event_name == name {
+            return true;
+        }
+        offset = end;
+    }
+    false
+}
+
+impl Drop for LoopWaker {
+    fn drop(&mut self) {
+        if let Some((_, ids)) = self.signals.take() {
+            for id in ids {
+                signal_hook::low_level::unregister(id);
+            }
+        }
+    }
+}
+
+/// Absolute path of the health history file, if agent health is recorded
+fn health_history_path(options: &WrapperOptions) -> Option<PathBuf> {
+    let path = options.health_history.as_ref()?;
+    Some(std::env::current_dir().map(|cwd| cwd.join(path)).unwrap_or_else(|_| path.clone()))
+}
+
+/// Environment variables the wrapper sets for the agent (and its MCP server)
+fn wrapper_env(health_history: Option<&Path>) -> Vec<(&'static str, String)> {
+    let mut env = Vec::new();
+    if let Some(path) = health_history {
+        env.push((health::HEALTH_HISTORY_ENV, path.to_string_lossy().into_owned()));
+    }
+    env
+}
+
+/// Variables the agent keeps in a restricted environment, unless `--env-clear` is given
+const DEFAULT_ENV_ALLOW: &[&str] = &["PATH", "HOME", "USER", "LOGNAME", "TERM"];
+
+/// Variables always passed on, since lazarus-mcp's MCP server needs them to find the wrapper
+const LAZARUS_ENV: &[&str] = &["XDG_RUNTIME_DIR", "TMPDIR"];
+
+/// Whether a variable is passed to the agent when its environment is restricted
+fn env_allowed(name: &str, options: &WrapperOptions) -> bool {
+    name.starts_with("LAZARUS_")
+        || LAZARUS_ENV.contains(&name)
+        || options.env_allow.iter().any(|allowed| allowed == name)
+        || (!options.env_clear && DEFAULT_ENV_ALLOW.contains(&name))
+}
+
+/// The agent's whole environment, if it is restricted
+///
+/// Taken from the wrapper's environment after privileges are dropped, so
+/// HOME, USER and LOGNAME are those of the user the agent runs as.
+fn restricted_agent_env(options: &WrapperOptions) -> Option<Vec<(std::ffi::OsString, std::ffi::OsString)>> {
+    if !options.restricts_env() {
+        return None;
+    }
+    let mut env: Vec<_> = std::env::vars_os()
+        .filter(|(name, _)| name.to_str().is_some_and(|name| env_allowed(name, options)))
+        .collect();
+    if let Some(path) = agent_path_env() {
+        if let Some((_, value)) = env.iter_mut().find(|(name, _)| name == "PATH") {
+            *value = path.into();
+        }
+    }
+    Some(env)
+}
+
+/// PATH for the agent, if ~/.local/bin (where tools like claude are installed) is missing from it
+fn agent_path_env() -> Option<String> {
+    let home = std::env::var("HOME").ok()?;
+    let local_bin = format!("{}/.local/bin", home);
+    let current_path = std::env::var("PATH").unwrap_or_default();
+    (PathBuf::from(&local_bin).exists() && !current_path.contains(&local_bin))
+        .then(|| format!("{}:{}", local_bin, current_path))
+}
+
+/// Quote a word for a POSIX shell, leaving it bare if that's safe
+fn shell_quote(word: &str) -> String {
+    let safe = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+    if safe {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Print what `run_command` would do, as a shell invocation, without running the agent
+///
+/// Nothing is written: .mcp.json is left alone and the injected config is
+/// printed instead. Privileges aren't dropped either; the user the agent
+/// would run as is shown.
+pub fn dry_run(command: &Path, cmd_args: &[String], options: &WrapperOptions) -> Result<()> {
+    let mut env: Vec<(String, String)> = Vec::new();
+    if let Ok(format) = std::env::var(crate::LOG_FORMAT_ENV) {
+        env.push((crate::LOG_FORMAT_ENV.to_string(), format));
+    }
+    env.extend(
+        wrapper_env(health_history_path(options).as_deref())
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value)),
+    );
+
+    match &options.run_as {
+        Some(spec) => {
+            let user = privileges::resolve_user(spec)?;
+            println!(
+                "# Agent runs as {} (uid={}, gid={})",
+                user.name.as_deref().unwrap_or("?"),
+                user.uid,
+                user.gid
+            );
+            if let Some(name) = &user.name {
+                env.push(("USER".to_string(), name.clone()));
+                env.push(("LOGNAME".to_string(), name.clone()));
+            }
+            if let Some(home) = &user.home {
+                env.push(("HOME".to_string(), home.to_string_lossy().into_owned()));
+            }
+        }
+        None if privileges::is_root() => {
+            println!(
+                "# Agent runs as the sudo user (uid={}, gid={})",
+                std::env::var("SUDO_UID").unwrap_or_else(|_| "?".into()),
+                std::env::var("SUDO_GID").unwrap_or_else(|_| "?".into())
+            );
+        }
+        None => {}
+    }
+    // Resolved after HOME changes, as the agent would see it
+    if let Some(path) = agent_path_env() {
+        env.push(("PATH".to_string(), path));
+    }
+    let clear_env = options.restricts_env();
+    if clear_env {
+        // The agent sees only the allowed variables, so list them all
+        for (name, value) in std::env::vars() {
+            if env_allowed(&name, options) && !env.iter().any(|(set, _)| *set == name) {
+                env.push((name, value));
+            }
+        }
+        env.retain(|(name, _)| env_allowed(name, options));
+        env.sort();
+        println!("# Environment restricted to the allowed variables");
+    }
+
+    if options.inject_mcp {
+        let exe = std::env::current_exe().context("Failed to get current executable path")?;
+        let target = Path::new(MCP_TARGET_FILE);
+        let existing = if target.exists() {
+            let content = fs::read_to_string(target).context("Failed to read existing .mcp.json")?;
+            Some(serde_json::from_str(&content).context("Failed to parse existing .mcp.json")?)
+        } else {
+            None
+        };
+        match existing.as_ref().and_then(find_existing_lazarus_server) {
+            Some(name) => println!("# .mcp.json already configures lazarus-mcp as '{}'; not injected", name),
+            None => {
+                let config = injected_mcp_config(
+                    existing.unwrap_or_else(|| json!({ "mcpServers": {} })),
+                    &exe,
+                    options.mcp_transform.as_deref(),
+                );
+                println!("# .mcp.json during the session:");
+                for line in serde_json::to_string_pretty(&config)?.lines() {
+                    println!("#   {}", line);
+                }
+            }
+        }
+    }
+
+    if let Ok(cwd) = std::env::current_dir() {
+        println!("cd {}", shell_quote(&cwd.to_string_lossy()));
+    }
+    let mut words: Vec<String> = Vec::new();
+    if clear_env {
+        words.extend(["env".to_string(), "-i".to_string()]);
+        words.extend(env.iter().map(|(name, value)| format!("{}={}", name, shell_quote(value))));
+    } else if !env.is_empty() {
+        words.push("env".to_string());
+        words.extend(env.iter().map(|(name, value)| format!("{}={}", name, shell_quote(value))));
+    }
+    words.push(shell_quote(&command.to_string_lossy()));
+    words.extend(cmd_args.iter().map(|arg| shell_quote(arg)));
+    println!("{}", words.join(" "));
+    Ok(())
+}
+
+/// Arguments to start the agent with
+///
+/// On restarts the agent's continue flag (see `pool::continue_flag_for`) is
+/// added so it resumes its session, unless the user already passed it.
+fn agent_args(cmd_args: &[String], continue_flag: Option<&str>, restarting: bool) -> Vec<String> {
+    let mut args = cmd_args.to_vec();
+    if let Some(flag) = continue_flag.filter(|_| restarting) {
+        if !args.iter().any(|a| a == flag) {
+            args.push(flag.to_string());
+        }
+    }
+    args
+}
+
 /// Run a command with supervision
 pub fn run_command(
     command: PathBuf,
     cmd_args: Vec<String>,
-    inject_mcp: bool,
+    options: WrapperOptions,
 ) -> Result<()> {
     let command_name = command
         .file_name()
@@ -307,23 +1277,83 @@ pub fn run_command(
     info!("Command: {:?}", command);
     info!("Wrapper PID: {}", process::id());
 
-    // Create shared state
-    let mut shared_state = SharedState::new(&command_name);
-    let _ = shared_state.save(); // Initial save
+    // Every way out of the session, including errors, ends up in the report
+    let mut session_report = SessionReport::new(&command_name);
+    let result = supervise(&command, &cmd_args, &options, &command_name, &mut session_report);
+    if let Err(e) = &result {
+        session_report.finish(SessionEnd::WrapperFailed(format!("{:#}", e)));
+    }
+
+    if options.report || options.report_file.is_some() {
+        session_report.emit(options.report, options.report_file.as_deref());
+    }
 
-    // Drop root privileges if running as root
+    // Exit with the agent's exit code if it exited normally
+    if let Some(code) = result? {
+        process::exit(code);
+    }
+
+    Ok(())
+}
+
+/// Supervise the agent until it exits or the wrapper shuts down
+///
+/// Returns the agent's exit code if it exited on its own.
+fn supervise(
+    command: &Path,
+    cmd_args: &[String],
+    options: &WrapperOptions,
+    command_name: &str,
+    session_report: &mut SessionReport,
+) -> Result<Option<i32>> {
+    let mut shared_state = SharedState::new(command_name);
+
+    // Set up notifications and health recording; the MCP server picks up
+    // the settings too (notifications through a private file, see `notify`)
+    let notifications = match &options.notify {
+        Some(spec) => Some(Notifications::from_spec(spec, options.notify_events.as_deref())?),
+        None => None,
+    };
+    let health_history = health_history_path(options);
+    for (name, value) in wrapper_env(health_history.as_deref()) {
+        std::env::set_var(name, value);
+    }
+
+    // Drop root privileges if running as root (or to the --run-as user)
     if privileges::is_root() {
         info!("Running as root, will drop privileges before spawning agent");
-        privileges::drop_privileges()?;
     }
+    privileges::drop_privileges_for(options.run_as.as_deref())?;
+
+    // Create shared state, in the runtime directory of the user the agent runs as
+    let runtime_dir = paths::ensure_runtime_dir().context("Failed to set up the runtime directory")?;
+    debug!("Runtime directory: {}", runtime_dir.display());
+    let _ = shared_state.save(); // Initial save
 
-    // Restore .mcp.json if a previous run crashed
-    restore_mcp_if_dirty();
+    let notify_settings = match &options.notify {
+        Some(spec) => {
+            let settings = notify::Settings { backend: spec.clone(), events: options.notify_events.clone() };
+            match settings.save() {
+                Ok(path) => Some(path),
+                Err(e) => {
+                    warn!("Failed to share notification settings with the MCP server: {}", e);
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+
+    // With --env-allow/--env-clear the agent gets only the allowed variables
+    let agent_env = restricted_agent_env(options);
+    if let Some(env) = &agent_env {
+        debug!("Agent environment restricted to {} variables", env.len());
+    }
 
-    // Inject lazarus-mcp into .mcp.json
-    let mcp_paths = if inject_mcp {
-        match inject_mcp_server() {
-            Ok(paths) => Some(paths),
+    // Inject lazarus-mcp into .mcp.json (restoring it first if a previous run crashed)
+    let mcp_paths = if options.inject_mcp {
+        match acquire_mcp_injection(options.mcp_transform.as_deref()) {
+            Ok(paths) => paths,
             Err(e) => {
                 warn!("Failed to inject MCP server: {}. Continuing without injection.", e);
                 None
@@ -344,14 +1374,15 @@ pub fn run_command(
     // Clean up any stale signal files
     let _ = fs::remove_file(signal_file_path());
 
-    // Set up signal handling for graceful shutdown (SIGINT and SIGTERM)
+    // Set up signal handling for graceful shutdown (SIGINT and SIGTERM).
+    // The handlers only clear `running`: the supervision loop then stops the
+    // agent and restores .mcp.json on the normal shutdown path below, since
+    // taking locks or touching files isn't safe inside a signal handler.
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
 
     // Handle Ctrl+C (SIGINT)
     ctrlc::set_handler(move || {
-        // Trigger emergency cleanup on signal
-        emergency_cleanup();
         r.store(false, Ordering::SeqCst);
     }).context("Failed to set Ctrl+C handler")?;
 
@@ -359,24 +1390,52 @@ pub fn run_command(
     let r2 = running.clone();
     if let Err(e) = unsafe {
         signal_hook::low_level::register(signal_hook::consts::SIGTERM, move || {
-            emergency_cleanup();
             r2.store(false, Ordering::SeqCst);
         })
     } {
         warn!("Failed to register SIGTERM handler: {}", e);
     }
 
+    // Keep temp files under budget (checked at startup, then periodically)
+    if let Some(budget) = options.tmp_budget {
+        spawn_tmp_budget_thread(budget, running.clone());
+    }
+
+    // Keep the injection (and the backup) in sync with edits the user makes
+    // to .mcp.json, and re-apply it on SIGHUP. The handler only sets a flag;
+    // the watcher thread does the work.
+    let mcp_watching = Arc::new(AtomicBool::new(true));
+    let mcp_reload = Arc::new(AtomicBool::new(false));
+    let mcp_watcher = mcp_paths.clone().and_then(|(backup_path, target_path)| {
+        spawn_mcp_watcher(
+            backup_path,
+            target_path,
+            options.mcp_transform.clone(),
+            mcp_watching.clone(),
+            mcp_reload.clone(),
+            options.restart_on_reload,
+        )
+    });
+    let reload_handler = match mcp_watcher {
+        Some(_) => signal_hook::flag::register(signal_hook::consts::SIGHUP, mcp_reload.clone())
+            .map_err(|e| warn!("Failed to register SIGHUP handler: {}", e))
+            .ok(),
+        None => None,
+    };
+
+    let continue_flag = crate::pool::continue_flag_for(command_name);
     let mut pending_prompt: Option<String> = None;
     let mut final_exit_code: Option<i32> = None;
 
     while running.load(Ordering::SeqCst) {
         // Build args for this run
-        let mut args = cmd_args.clone();
+        let mut args = agent_args(cmd_args, continue_flag, shared_state.restart_count > 0);
 
         // Add pending prompt as a command-line argument (for restart with prompt)
-        if let Some(prompt) = pending_prompt.take() {
+        let prompt = pending_prompt.take();
+        if let Some(prompt) = &prompt {
             info!("Adding prompt as command-line argument: {}", prompt);
-            args.push(prompt);
+            args.push(prompt.clone());
         }
 
         info!("Starting {} with args: {:?}", command_name, args);
@@ -386,41 +1445,113 @@ pub fn run_command(
         let _ = shared_state.save();
 
         // Spawn command
-        let exit_reason = run_agent(
-            &command,
+        let mut health = HealthRecorder::new(health_history.as_deref(), session_report);
+        let exit_reason = match run_agent(
+            command,
             &args,
+            options.pty,
             running.clone(),
             &mut shared_state,
-        )?;
+            &mut health,
+            agent_env.as_deref(),
+        ) {
+            // The prompt is too large for the command line: hand it over in a file instead
+            Err(e) if is_arg_too_long(&e) && prompt.is_some() => {
+                let prompt_file = write_prompt_file(prompt.as_deref().unwrap_or_default())?;
+                warn!(
+                    "Prompt too long for the command line; passing it via {}",
+                    prompt_file.display()
+                );
+                args.pop();
+                args.push(prompt_file_reference(&prompt_file));
+                run_agent(
+                    command,
+                    &args,
+                    options.pty,
+                    running.clone(),
+                    &mut shared_state,
+                    &mut health,
+                    agent_env.as_deref(),
+                )?
+            }
+            Err(e) if is_arg_too_long(&e) => {
+                return Err(e.context("Agent arguments exceed the system's argument size limit (ARG_MAX)"));
+            }
+            result => result?,
+        };
 
         match exit_reason {
-            ExitReason::RestartRequested { reason, prompt } => {
-                info!("Restart requested: {}", reason);
+            ExitReason::RestartRequested { explanation, prompt } => {
+                info!("Restart requested ({}): {}", explanation.trigger, explanation.reason);
                 shared_state.restart_count += 1;
                 shared_state.agent_status = AgentState::Restarting;
+                if let Some(notifications) = &notifications {
+                    notifications.notify(
+                        NotificationKind::Restart,
+                        command_name,
+                        &format!("{} restarted", command_name),
+                        &format!("{}: {}", explanation.trigger, explanation.reason),
+                    );
+                }
+                session_report.record_restart(explanation.clone());
+                shared_state.exit_detail = Some(format!("restarted ({}): {}", explanation.trigger, explanation.reason));
+                shared_state.exit_code = None;
+                shared_state.last_restart = Some(explanation);
                 let _ = shared_state.save();
 
                 pending_prompt = prompt;
 
                 // Clear terminal and reset before restart
-                print!("\x1b[2J\x1b[H\x1b[0m");
-                let _ = std::io::stdout().flush();
+                if options.color {
+                    print!("\x1b[2J\x1b[H\x1b[0m");
+                    let _ = std::io::stdout().flush();
+                }
 
                 // Small delay before restart
                 std::thread::sleep(Duration::from_millis(100));
                 continue;
             }
-            ExitReason::NormalExit(code) => {
-                info!("{} exited with code: {}", command_name, code);
+            ExitReason::NormalExit(status) => {
+                let code = status.code().unwrap_or(1);
+                let detail = describe_exit(status);
+                info!("{} {}", command_name, detail);
                 shared_state.agent_status = AgentState::Stopped;
+                shared_state.exit_detail = Some(detail.clone());
+                shared_state.exit_code = status.code();
+                if !status.success() {
+                    shared_state.last_restart = Some(RestartExplanation {
+                        trigger: RestartTrigger::Crash,
+                        reason: format!("{} {}", command_name, detail),
+                        timestamp: unix_now(),
+                        metrics: RestartMetrics {
+                            agent_pid: shared_state.agent_pid,
+                            ..Default::default()
+                        },
+                    });
+                }
                 let _ = shared_state.save();
                 final_exit_code = Some(code);
+                session_report.finish(SessionEnd::Exited(code));
+                if let Some(notifications) = &notifications {
+                    // Wait for delivery, since the wrapper is about to exit
+                    let sent = notifications.notify(
+                        NotificationKind::Exit,
+                        command_name,
+                        &format!("{} exited", command_name),
+                        &format!("{} {}", command_name, detail),
+                    );
+                    if let Some(sent) = sent {
+                        let _ = sent.join();
+                    }
+                }
                 break;
             }
             ExitReason::WrapperShutdown => {
                 info!("Wrapper shutdown requested");
                 shared_state.agent_status = AgentState::Stopped;
+                shared_state.exit_detail = Some(SessionEnd::WrapperShutdown.to_string());
                 let _ = shared_state.save();
+                session_report.finish(SessionEnd::WrapperShutdown);
                 break;
             }
         }
@@ -429,53 +1560,144 @@ pub fn run_command(
     // Clean up signal files
     let _ = fs::remove_file(signal_file_path());
     let _ = fs::remove_file(SharedState::state_file_path());
+    let _ = fs::remove_file(prompt_file_path());
+    if let Some(path) = &notify_settings {
+        let _ = fs::remove_file(path);
+    }
+
+    // Stop watching before restoring, so the restore isn't taken for an edit
+    if let Some(id) = reload_handler {
+        signal_hook::low_level::unregister(id);
+    }
+    mcp_watching.store(false, Ordering::SeqCst);
+    if let Some(watcher) = mcp_watcher {
+        let _ = watcher.join();
+    }
 
-    // Restore .mcp.json from backup
+    // Restore .mcp.json from backup (unless another wrapper here still uses it)
     if let Some((ref backup_path, ref target_path)) = mcp_paths {
-        restore_mcp_config(backup_path, target_path);
+        release_mcp_injection(backup_path, target_path);
     }
 
     info!("Wrapper cleanup complete");
+    Ok(final_exit_code)
+}
 
-    // Exit with the agent's exit code if it exited normally
-    if let Some(code) = final_exit_code {
-        process::exit(code);
-    }
+/// Path of the file used to pass prompts too large for the command line
+fn prompt_file_path() -> PathBuf {
+    paths::runtime_dir().join(format!("lazarus-mcp-prompt-{}.txt", process::id()))
+}
 
-    Ok(())
+/// Write a prompt to the prompt file
+fn write_prompt_file(prompt: &str) -> Result<PathBuf> {
+    let path = prompt_file_path();
+    fs::write(&path, prompt)
+        .with_context(|| format!("Failed to write prompt file {}", path.display()))?;
+    Ok(path)
+}
+
+/// Short prompt asking the agent to read the real prompt from a file
+fn prompt_file_reference(path: &Path) -> String {
+    format!(
+        "The instructions for this session were too long to pass directly. Read {} and follow them.",
+        path.display()
+    )
+}
+
+/// Whether spawning failed because the arguments exceed ARG_MAX (E2BIG)
+fn is_arg_too_long(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|e| e.downcast_ref::<std::io::Error>())
+        .any(|e| e.raw_os_error() == Some(libc::E2BIG))
 }
 
 #[derive(Debug)]
 enum ExitReason {
-    RestartRequested { reason: String, prompt: Option<String> },
-    NormalExit(i32),
+    RestartRequested { explanation: RestartExplanation, prompt: Option<String> },
+    NormalExit(std::process::ExitStatus),
     WrapperShutdown,
 }
 
+/// How an agent process ended, e.g. "exited with code 1" or "killed by SIGSEGV (core dumped)"
+fn describe_exit(status: std::process::ExitStatus) -> String {
+    use std::os::unix::process::ExitStatusExt;
+
+    if let Some(code) = status.code() {
+        return format!("exited with code {}", code);
+    }
+    let Some(sig) = status.signal() else {
+        return "exited".to_string();
+    };
+    let name = Signal::try_from(sig)
+        .map(|s| s.as_str().to_string())
+        .unwrap_or_else(|_| format!("signal {}", sig));
+    if status.core_dumped() {
+        format!("killed by {} (core dumped)", name)
+    } else {
+        format!("killed by {}", name)
+    }
+}
+
+/// Where the agent's health samples go: the history file (if any) and the session report
+struct HealthRecorder<'a> {
+    history: Option<&'a Path>,
+    report: &'a mut SessionReport,
+    error_logged: bool,
+}
+
+impl<'a> HealthRecorder<'a> {
+    fn new(history: Option<&'a Path>, report: &'a mut SessionReport) -> Self {
+        Self {
+            history,
+            report,
+            error_logged: false,
+        }
+    }
+
+    fn record(&mut self, sample: &HealthSample) {
+        self.report.record_sample(sample);
+        if let Some(path) = self.history {
+            if let Err(e) = health::append_sample(path, sample) {
+                if !self.error_logged {
+                    warn!("Failed to write health history to {}: {}", path.display(), e);
+                    self.error_logged = true;
+                }
+            }
+        }
+    }
+}
+
 /// Run an agent as a simple child process
 fn run_agent(
-    agent_path: &PathBuf,
+    agent_path: &Path,
     args: &[String],
+    use_pty: bool,
     running: Arc<AtomicBool>,
     shared_state: &mut SharedState,
+    health: &mut HealthRecorder,
+    env: Option<&[(std::ffi::OsString, std::ffi::OsString)]>,
 ) -> Result<ExitReason> {
     // Build command
     let mut cmd = Command::new(agent_path);
     cmd.args(args);
 
-    // Ensure ~/.local/bin is in PATH (for user-installed tools like claude)
-    if let Ok(home) = std::env::var("HOME") {
-        let local_bin = format!("{}/.local/bin", home);
-        if PathBuf::from(&local_bin).exists() {
-            let current_path = std::env::var("PATH").unwrap_or_default();
-            if !current_path.contains(&local_bin) {
-                cmd.env("PATH", format!("{}:{}", local_bin, current_path));
-            }
-        }
+    if let Some(env) = env {
+        // A restricted environment (already with the adjusted PATH)
+        cmd.env_clear();
+        cmd.envs(env.iter().cloned());
+    } else if let Some(path) = agent_path_env() {
+        // Ensure ~/.local/bin is in PATH (for user-installed tools like claude)
+        cmd.env("PATH", path);
     }
 
-    // Spawn agent directly
-    let mut child = cmd.spawn().context("Failed to spawn agent")?;
+    // Spawn agent directly, or attached to a PTY. The relay (if any) is
+    // dropped on return, which drains output and restores the terminal.
+    let (mut child, _pty_relay) = if use_pty {
+        let (child, relay) = pty::spawn(&mut cmd)?;
+        (child, Some(relay))
+    } else {
+        (cmd.spawn().context("Failed to spawn agent")?, None)
+    };
 
     let child_pid = Pid::from_raw(child.id() as i32);
     let child_pid_u32 = child.id();
@@ -486,7 +1708,22 @@ fn run_agent(
     let _ = shared_state.save();
 
     // Monitor the child process
+    let mut last_heartbeat = std::time::Instant::now();
+    let mut sampler = HealthSampler::new(child_pid_u32);
+    let mut last_sample: Option<std::time::Instant> = None;
+    let waker = LoopWaker::new();
     loop {
+        // Let the dashboard know we're alive
+        if last_heartbeat.elapsed() >= HEARTBEAT_INTERVAL {
+            let _ = shared_state.save();
+            last_heartbeat = std::time::Instant::now();
+        }
+
+        if last_sample.is_none_or(|t| t.elapsed() >= health::SAMPLE_INTERVAL) {
+            health.record(&sampler.sample(shared_state.agent_status));
+            last_sample = Some(std::time::Instant::now());
+        }
+
         // Check if wrapper should stop
         if !running.load(Ordering::SeqCst) {
             let _ = signal::kill(child_pid, Signal::SIGINT);
@@ -497,6 +1734,14 @@ fn run_agent(
         if let Some(signal_content) = check_restart_signal() {
             info!("Restart signal detected: {}", signal_content.reason);
 
+            // Signals from outside the agent (e.g. the dashboard) carry no metrics,
+            // so capture them here before the agent goes away
+            let metrics = if signal_content.metrics.agent_pid.is_some() {
+                signal_content.metrics
+            } else {
+                RestartMetrics::capture(child_pid_u32)
+            };
+
             // Send SIGINT to agent for graceful shutdown
             let _ = signal::kill(child_pid, Signal::SIGINT);
 
@@ -522,7 +1767,12 @@ fn run_agent(
             }
 
             return Ok(ExitReason::RestartRequested {
-                reason: signal_content.reason,
+                explanation: RestartExplanation {
+                    trigger: signal_content.trigger,
+                    reason: signal_content.reason,
+                    timestamp: unix_now(),
+                    metrics,
+                },
                 prompt: signal_content.prompt,
             });
         }
@@ -530,12 +1780,12 @@ fn run_agent(
         // Check if child has exited
         match child.try_wait() {
             Ok(Some(status)) => {
-                let code = status.code().unwrap_or(1);
-                return Ok(ExitReason::NormalExit(code));
+                return Ok(ExitReason::NormalExit(status));
             }
             Ok(None) => {
-                // Still running
-                std::thread::sleep(Duration::from_millis(100));
+                // Still running: sleep until the child changes state, a signal
+                // file appears, or the next heartbeat is due
+                waker.wait(HEARTBEAT_INTERVAL.saturating_sub(last_heartbeat.elapsed()));
             }
             Err(e) => {
                 return Err(anyhow::anyhow!("Error checking child status: {}", e));
@@ -543,3 +1793,361 @@ fn run_agent(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_arg_too_long() {
+        // A single argument over the kernel's per-string limit fails with E2BIG
+        let huge = "x".repeat(256 * 1024);
+        let err = Command::new("/bin/true")
+            .arg(&huge)
+            .spawn()
+            .context("Failed to spawn agent")
+            .unwrap_err();
+        assert!(is_arg_too_long(&err));
+
+        let other = Command::new("/nonexistent/agent")
+            .spawn()
+            .context("Failed to spawn agent")
+            .unwrap_err();
+        assert!(!is_arg_too_long(&other));
+    }
+
+    #[test]
+    fn test_heartbeat_staleness() {
+        let mut state = SharedState::new("claude");
+        // Old state files without a heartbeat are never stale
+        assert!(!state.is_stale(u64::MAX));
+
+        state.heartbeat_ts = 1000;
+        assert!(!state.is_stale(1000 + HEARTBEAT_STALE_SECS));
+        assert!(state.is_stale(1001 + HEARTBEAT_STALE_SECS));
+    }
+
+    #[test]
+    fn test_loop_waker_wakes_on_child_exit() {
+        let waker = LoopWaker::new();
+        assert!(waker.signals.is_some());
+
+        let mut child = Command::new("sleep").arg("0.05").spawn().unwrap();
+        let start = std::time::Instant::now();
+        while child.try_wait().unwrap().is_none() {
+            waker.wait(Duration::from_secs(10));
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_loop_waker_wakes_on_signal_file() {
+        let dir = paths::ensure_runtime_dir().unwrap();
+        let name = format!("test-waker-{}", process::id());
+        let waker = LoopWaker::with_signal_file(&name);
+        if waker.inotify.is_none() {
+            return; // inotify unavailable in this environment
+        }
+
+        let path = dir.join(&name);
+        let writer_path = path.clone();
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            fs::write(&writer_path, "{}").unwrap();
+        });
+
+        let start = std::time::Instant::now();
+        waker.wait(Duration::from_secs(10));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        writer.join().unwrap();
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_inotify_names() {
+        fn event(name: &[u8], len: usize, mask: u32) -> Vec<u8> {
+            let header = libc::inotify_event { wd: 1, mask, cookie: 0, len: len as u32 };
+            let mut bytes = unsafe {
+                std::slice::from_raw_parts(
+                    (&header as *const libc::inotify_event).cast::<u8>(),
+                    std::mem::size_of::<libc::inotify_event>(),
+                )
+            }
+            .to_vec();
+            bytes.extend_from_slice(name);
+            bytes.resize(bytes.len() + len - name.len(), 0);
+            bytes
+        }
+
+        // State and health saves don't count, the signal file does
+        let mut events = event(b"lazarus-mcp-state-42", 32, libc::IN_CLOSE_WRITE);
+        assert!(!inotify_names(&events, b"lazarus-mcp-42"));
+        events.extend(event(b"lazarus-mcp-42", 16, libc::IN_MOVED_TO));
+        assert!(inotify_names(&events, b"lazarus-mcp-42"));
+        assert!(!inotify_names(&events, b"lazarus-mcp-4"));
+        assert!(inotify_names(&event(b"", 0, libc::IN_Q_OVERFLOW), b"lazarus-mcp-42"));
+    }
+
+    #[test]
+    fn test_transform_mcp_config() {
+        let config = json!({ "mcpServers": { "lazarus-mcp": { "command": "lazarus-mcp" }, "other": {} } });
+
+        let renamed = transform_mcp_config(&config, "sed 's/\"other\"/\"renamed\"/'").unwrap();
+        assert!(renamed["mcpServers"].get("renamed").is_some());
+        assert!(renamed["mcpServers"].get("lazarus-mcp").is_some());
+
+        assert!(transform_mcp_config(&config, "echo not json").is_err());
+        assert!(transform_mcp_config(&config, "echo '[1, 2]'").is_err());
+        assert!(transform_mcp_config(&config, "cat; exit 1").is_err());
+
+        // A hung transform is killed
+        let started = std::time::Instant::now();
+        let err = transform_mcp_config_within(&config, "sleep 30", Duration::from_millis(200)).unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_find_running_wrappers() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = SharedState::new("claude");
+        let own = dir.path().join(format!("lazarus-mcp-state-{}", std::process::id()));
+        fs::write(&own, serde_json::to_string(&state).unwrap()).unwrap();
+        state.wrapper_pid = u32::MAX;
+        let stale = dir.path().join(format!("lazarus-mcp-state-{}", u32::MAX));
+        fs::write(&stale, serde_json::to_string(&state).unwrap()).unwrap();
+
+        let wrappers = find_running_wrappers(&[dir.path().to_path_buf()]);
+        assert_eq!(wrappers.len(), 1);
+        assert_eq!(wrappers[0].pid, std::process::id());
+        assert!(describe_wrapper(&wrappers[0]).contains("claude"));
+
+        // The dead wrapper's state file was cleaned up
+        assert!(own.exists());
+        assert!(!stale.exists());
+    }
+
+    #[test]
+    fn test_mcp_lock_lets_one_wrapper_inject() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock_path = dir.path().join(MCP_LOCK_FILE);
+
+        // The transform counts the injections
+        let injections = dir.path().join("injections");
+        let transform = format!("echo >> '{}'; cat", injections.display());
+
+        let wrappers: Vec<_> = (0..2)
+            .map(|_| {
+                let (dir, transform) = (dir.path().to_path_buf(), transform.clone());
+                std::thread::spawn(move || acquire_mcp_injection_in(&dir, Some(&transform)).unwrap())
+            })
+            .collect();
+        let paths: Vec<_> = wrappers.into_iter().map(|w| w.join().unwrap()).collect();
+
+        let expected = (dir.path().join(MCP_BACKUP_FILE), dir.path().join(MCP_TARGET_FILE));
+        assert_eq!(paths, [Some(expected.clone()), Some(expected.clone())]);
+        assert_eq!(fs::read_to_string(&injections).unwrap(), "\n");
+        assert!(fs::read_to_string(&expected.1).unwrap().contains("lazarus-mcp"));
+
+        let mut lock = McpLock::acquire(&lock_path, false).unwrap();
+        assert_eq!(lock.holders(), [process::id(), process::id()]);
+
+        // Held locks can't be taken without waiting
+        let err = McpLock::acquire(&lock_path, false).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+
+        // Dead wrappers are dropped from the list
+        lock.set_holders(&[u32::MAX]).unwrap();
+        drop(lock);
+        assert!(McpLock::acquire(&lock_path, false).unwrap().holders().is_empty());
+    }
+
+    #[test]
+    fn test_last_wrapper_out_restores() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock_path = dir.path().join(MCP_LOCK_FILE);
+        let backup = dir.path().join("backup");
+        let target = dir.path().join(".mcp.json");
+        fs::write(&backup, "{}").unwrap();
+        fs::write(&target, "injected").unwrap();
+
+        // Another live wrapper still uses the injection
+        let other = nix::unistd::getppid().as_raw() as u32;
+        McpLock::acquire(&lock_path, true).unwrap().set_holders(&[other, process::id()]).unwrap();
+        release_mcp_injection_with(&backup, &target, &lock_path, true).unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "injected");
+        assert_eq!(McpLock::acquire(&lock_path, true).unwrap().holders(), vec![other]);
+
+        // Once it's gone, the last one out restores and removes the lockfile
+        McpLock::acquire(&lock_path, true).unwrap().set_holders(&[process::id()]).unwrap();
+        release_mcp_injection_with(&backup, &target, &lock_path, true).unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "{}");
+        assert!(!backup.exists());
+        assert!(!lock_path.exists());
+    }
+
+    #[test]
+    fn test_agent_args() {
+        let args = vec!["-p".to_string()];
+        assert_eq!(agent_args(&args, Some("--continue"), false), ["-p"]);
+        assert_eq!(agent_args(&args, Some("--continue"), true), ["-p", "--continue"]);
+        // Agents without a continue flag are just relaunched
+        assert_eq!(agent_args(&args, None, true), ["-p"]);
+        // Not added twice
+        let args = vec!["--continue".to_string()];
+        assert_eq!(agent_args(&args, Some("--continue"), true), ["--continue"]);
+    }
+
+    #[test]
+    fn test_env_allowed() {
+        let mut options = WrapperOptions {
+            env_allow: vec!["ANTHROPIC_API_KEY".to_string()],
+            ..Default::default()
+        };
+        assert!(options.restricts_env());
+        for name in ["ANTHROPIC_API_KEY", "PATH", "HOME", "LAZARUS_LOG_FORMAT", "XDG_RUNTIME_DIR"] {
+            assert!(env_allowed(name, &options), "{}", name);
+        }
+        assert!(!env_allowed("AWS_SECRET_ACCESS_KEY", &options));
+
+        // Clearing drops the defaults, but not what lazarus-mcp needs
+        options.env_clear = true;
+        assert!(!env_allowed("PATH", &options));
+        assert!(env_allowed("ANTHROPIC_API_KEY", &options));
+        assert!(env_allowed("LAZARUS_HEALTH_HISTORY", &options));
+
+        assert!(!WrapperOptions::default().restricts_env());
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("--continue"), "--continue");
+        assert_eq!(shell_quote("/usr/bin/claude"), "/usr/bin/claude");
+        assert_eq!(shell_quote("two words"), "'two words'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn test_version_json() {
+        let version = version_json();
+        assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+        assert!(version["build_time"].is_string());
+        assert!(version["git_hash"].is_string());
+    }
+
+    #[test]
+    fn test_describe_exit() {
+        use std::os::unix::process::ExitStatusExt;
+        use std::process::ExitStatus;
+
+        assert_eq!(describe_exit(ExitStatus::from_raw(0)), "exited with code 0");
+        assert_eq!(describe_exit(ExitStatus::from_raw(2 << 8)), "exited with code 2");
+        assert_eq!(describe_exit(ExitStatus::from_raw(libc::SIGKILL)), "killed by SIGKILL");
+        assert_eq!(
+            describe_exit(ExitStatus::from_raw(libc::SIGSEGV | 0x80)),
+            "killed by SIGSEGV (core dumped)"
+        );
+    }
+
+    #[test]
+    fn test_remerge_after_user_edit() {
+        let exe = Path::new("/usr/bin/lazarus-mcp");
+
+        // The user added a server and (accidentally) dropped ours
+        let edited = json!({ "mcpServers": { "db": { "command": "db-mcp" } } });
+        let (user, merged) = remerge_mcp_config(&edited, exe);
+        assert_eq!(user, edited);
+        assert_eq!(merged["mcpServers"]["db"]["command"], "db-mcp");
+        assert_eq!(merged["mcpServers"]["lazarus-mcp"], lazarus_server_entry(exe));
+
+        // Our entry is kept out of the user's config and not duplicated
+        let (user, merged) = remerge_mcp_config(&merged, exe);
+        assert!(user["mcpServers"].get("lazarus-mcp").is_none());
+        assert_eq!(merged["mcpServers"].as_object().unwrap().len(), 2);
+
+        // A lazarus-mcp server the user configured themselves isn't doubled up
+        let edited = json!({ "mcpServers": { "mine": { "command": "lazarus-mcp", "args": ["--mcp-server"] } } });
+        let (_, merged) = remerge_mcp_config(&edited, exe);
+        assert_eq!(merged, edited);
+    }
+
+    #[test]
+    fn test_reinject_after_edit_updates_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup = dir.path().join("backup");
+        let target = dir.path().join(".mcp.json");
+        fs::write(&backup, "").unwrap();
+        fs::write(&target, r#"{ "mcpServers": { "db": { "command": "db-mcp" } } }"#).unwrap();
+
+        let written = reinject_after_edit(&backup, &target, None, "").unwrap();
+        let merged: serde_json::Value = serde_json::from_str(&written).unwrap();
+        assert!(merged["mcpServers"].get("lazarus-mcp").is_some());
+        assert_eq!(fs::read_to_string(&target).unwrap(), written);
+
+        // The user's edit is what gets restored on exit
+        let restored: serde_json::Value = serde_json::from_str(&fs::read_to_string(&backup).unwrap()).unwrap();
+        assert_eq!(restored, json!({ "mcpServers": { "db": { "command": "db-mcp" } } }));
+
+        // Seeing our own write again is a no-op
+        assert_eq!(reinject_after_edit(&backup, &target, None, &written).unwrap(), written);
+    }
+
+    #[test]
+    fn test_reload_reapplies_transform_to_user_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup = dir.path().join("backup");
+        let target = dir.path().join(".mcp.json");
+        fs::write(&backup, r#"{ "mcpServers": { "db": { "command": "db-mcp" } } }"#).unwrap();
+        let tag = dir.path().join("tag");
+        let transform = format!(r#"sed 's/"mcpServers"/"tag": "'$(cat {})'", "mcpServers"/'"#, tag.display());
+
+        fs::write(&tag, "one").unwrap();
+        fs::write(&target, "{}").unwrap();
+        let first = reload_mcp_config(&backup, &target, Some(&transform), "{}").unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&first).unwrap()["tag"], "one");
+
+        // Reloading re-runs the transform on the user's config, not on its previous output
+        fs::write(&tag, "two").unwrap();
+        let reloaded = reload_mcp_config(&backup, &target, Some(&transform), &first).unwrap();
+        let reloaded: serde_json::Value = serde_json::from_str(&reloaded).unwrap();
+        assert_eq!(reloaded["tag"], "two");
+        assert!(reloaded["mcpServers"].get("db").is_some());
+        assert!(reloaded["mcpServers"].get("lazarus-mcp").is_some());
+        assert!(fs::read_to_string(&backup).unwrap().contains("db-mcp"));
+    }
+
+    #[test]
+    fn test_detects_lazarus_under_other_name() {
+        let config = json!({
+            "mcpServers": {
+                "other": { "command": "node", "args": ["server.js"] },
+                "hot-reload": { "command": "/usr/local/bin/lazarus-mcp", "args": ["--mcp-server"] }
+            }
+        });
+        assert_eq!(find_existing_lazarus_server(&config), Some("hot-reload".to_string()));
+    }
+
+    #[test]
+    fn test_own_key_is_not_a_duplicate() {
+        let config = json!({
+            "mcpServers": {
+                "lazarus-mcp": { "command": "lazarus-mcp", "args": ["--mcp-server"] }
+            }
+        });
+        assert_eq!(find_existing_lazarus_server(&config), None);
+    }
+
+    #[test]
+    fn test_wrapper_mode_entry_is_not_a_duplicate() {
+        // lazarus-mcp wrapping another server is not our MCP server
+        let config = json!({
+            "mcpServers": {
+                "wrapped": { "command": "lazarus-mcp", "args": ["node", "server.js"] }
+            }
+        });
+        assert_eq!(find_existing_lazarus_server(&config), None);
+        assert_eq!(find_existing_lazarus_server(&json!({})), None);
+    }
+}